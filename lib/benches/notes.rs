@@ -0,0 +1,64 @@
+//! 几千个音符的整帧绘制耗时：排队、合批、上传、提交，并等 GPU 画完这一帧。
+//!
+//! `cargo bench --bench notes`，需要可用的图形适配器（软件适配器也可以）。
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use glam::{uvec2, vec3};
+use std::time::Duration;
+use wgpu_android_lib::*;
+
+const NOTES: usize = 5000;
+
+/// `layers` 个 z_index 乘以两张纹理就是每帧的网格分组数
+fn draw_notes(frame: u32, layers: usize) {
+    let textures = ["1", "1px"].map(texture_id);
+
+    for i in 0..NOTES {
+        let x = (i % 100) as f32 * 1.2 - 60.0;
+        let y = ((i / 100) as f32 * 2.5 + frame as f32) % 128.0 - 64.0;
+
+        draw_sprite_ex(
+            textures[(i / layers) % textures.len()],
+            DrawTextureParams {
+                raw_draw_params: RawDrawParams {
+                    position: vec3(x, y, 0.0),
+                    dest_size: Some(uvec2(8, 2)),
+                    z_index: (i % layers) as i32,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn thousands_of_notes(c: &mut Criterion) {
+    init_headless(uvec2(128, 128), RunTimeContext::default())
+        .expect("No graphics adapter for the benchmark");
+
+    for layers in [4, 200] {
+        // 预热：创建管线、扩容缓冲区
+        draw_notes(0, layers);
+        render_headless_frame();
+
+        let mut frame = 0;
+
+        c.bench_function(&format!("{NOTES} notes in {} groups", layers * 2), |b| {
+            b.iter(|| {
+                frame += 1;
+                draw_notes(frame, layers);
+                render_headless_frame();
+
+                // 回读会等待这一帧的提交全部完成
+                read_render_target(RenderTargetId(0)).unwrap();
+            })
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20).measurement_time(Duration::from_secs(10));
+    targets = thousands_of_notes
+}
+criterion_main!(benches);
@@ -1,6 +1,7 @@
 use crate::*;
 use parking_lot::lock_api::Mutex;
 use pollster::FutureExt;
+#[cfg(target_os = "windows")]
 use winit::platform::windows::{BackdropType, WindowAttributesExtWindows};
 
 pub fn init_game(
@@ -18,7 +19,7 @@ pub fn init_game(
 
     let mut event_loop_builder = EventLoop::<WinitMessage>::with_user_event();
 
-    #[cfg(target_os = "macos")]
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
     {
         env_logger::builder()
             .filter_level(LevelFilter::Info) // 默认日志级别
@@ -49,9 +50,18 @@ pub fn init_game(
         event_loop_builder.with_android_app(ANDROID_APP.get().expect(msg).clone());
     }
 
-    let event_loop = event_loop_builder
-        .build()
-        .expect("Failed to build event loop");
+    let event_loop = event_loop_builder.build().unwrap_or_else(|e| {
+        // Wayland 通过 XDG_RUNTIME_DIR 下的 socket 连接合成器，缺少时给出明确的提示
+        #[cfg(target_os = "linux")]
+        if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+            panic!("Failed to build event loop: {}, XDG_RUNTIME_DIR is not set", e);
+        }
+
+        panic!("Failed to build event loop: {}", e)
+    });
+
+    init_audio(&init_game_config.audio_config);
+    set_autoplay(init_game_config.autoplay);
 
     event_loop.set_control_flow(ControlFlow::Poll);
 
@@ -110,6 +120,10 @@ pub fn init_game(
             if let Ok(frame_state) = check_frame_rx.await {
                 get_global_wgpu().write().resize(frame_state.resize, false);
 
+                // 固定本帧的输入状态
+                begin_input_frame();
+                record_replay_frame();
+
                 // 执行游戏逻辑（物理、AI、状态更新等）
                 game.update().await;
 
@@ -149,8 +163,9 @@ pub fn init_game(
 
             // 更新timer
             time::update();
+            update_song_clock();
 
-            #[cfg(any(target_os = "windows", target_os = "macos"))]
+            #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
             framerate_limiter();
 
             // info!("-------------新的一帧-------------");
@@ -170,6 +185,31 @@ pub fn init_game(
     let _ = event_loop.run_app(&mut app);
 }
 
+/// 不创建窗口和事件循环，直接初始化一个离屏渲染器，用于 CI 或渲染服务器。
+///
+/// 之后每帧照常调用绘制函数，再用 [`render_headless_frame`] 把队列渲染到默认 RT。
+pub fn init_headless(size: UVec2, run_time_context: RunTimeContext) -> anyhow::Result<()> {
+    let _ = RUN_TIME_CONTEXT.set(Arc::new(RwLock::new(run_time_context)));
+
+    if check_wgpu_init() {
+        return Ok(());
+    }
+
+    WgpuRenderer::new_headless(size, &GraphicsConfig::default()).block_on()
+}
+
+/// headless 模式下的一帧：渲染所有排队的网格但不 present。
+pub fn render_headless_frame() {
+    process_texture_uploads();
+
+    let mut wr = get_global_wgpu().write();
+    wr.update_camera_buffer();
+    wr.draw_offscreen();
+    wr.end_frame();
+
+    clear_shader_uniform_table();
+}
+
 #[derive(Default)]
 struct App {
     pub runtime: Option<Box<Runtime>>,
@@ -221,7 +261,14 @@ impl ApplicationHandler<WinitMessage> for App {
         } else {
             // 从桌面回来不会执行
             self.init_window(event_loop);
-            WgpuRenderer::new(self.window.clone().unwrap()).block_on();
+
+            let window = self.window.clone().unwrap();
+            if let Err(e) =
+                WgpuRenderer::new(window, &self.init_game_config.graphics_config).block_on()
+            {
+                error!("Failed to initialize renderer: {}", e);
+                event_loop.exit();
+            }
         }
     }
 
@@ -232,6 +279,11 @@ impl ApplicationHandler<WinitMessage> for App {
         _: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
+        // 键盘、鼠标和触控（包括 Android 的触摸）交给输入模块，回放和自动游玩时忽略真实输入
+        if !is_replay_playing() && !is_autoplay_enabled() {
+            get_input().write().handle_window_event(&event);
+        }
+
         match event {
             WindowEvent::Resized(new_size) => {
                 let width = new_size.width.max(1);
@@ -246,6 +298,17 @@ impl ApplicationHandler<WinitMessage> for App {
         }
     }
 
+    // 每次事件循环处理完窗口事件后调用，回放和自动游玩的事件在这里注入
+    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+        pump_replay();
+        pump_autoplay();
+
+        // 后台解码好的纹理在事件循环线程上传，游戏逻辑等待加载时也不会卡住
+        if check_wgpu_init() {
+            process_texture_uploads();
+        }
+    }
+
     // region: 看起来没什么用的内容
 
     // 当应用程序被挂起时调用
@@ -284,6 +347,17 @@ impl App {
                 None
             });
 
+        // 两个扩展分别写 Wayland 和 X11 的字段，运行时用哪个后端就生效哪个
+        #[cfg(target_os = "linux")]
+        let wa = {
+            use winit::platform::wayland::WindowAttributesExtWayland;
+            use winit::platform::x11::WindowAttributesExtX11;
+
+            let app_id = wc.app_id.as_ref().unwrap_or(&wc.title_name);
+            let wa = WindowAttributesExtWayland::with_name(wa, app_id, "");
+            WindowAttributesExtX11::with_name(wa, app_id, app_id)
+        };
+
         match event_loop.create_window(wa) {
             Ok(window) => {
                 self.window = Some(Arc::new(window));
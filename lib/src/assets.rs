@@ -1,5 +1,9 @@
 use crate::*;
 
+use anyhow::{Context, bail};
+use image::DynamicImage;
+use wgpu::AddressMode;
+
 pub static ASSETS: Lazy<RwLock<Assets>> =
     Lazy::new(|| RwLock::new(Assets::new()));
 
@@ -31,6 +35,8 @@ impl Assets {
     
         if let Some(image) = image_map.get(&handle) {
             ImageSizeResult::Loaded(uvec2(image.width(), image.height()))
+        } else if is_texture_loading(handle) {
+            ImageSizeResult::LoadingInProgress
         } else {
             ImageSizeResult::ImageNotFound
         }
@@ -59,6 +65,93 @@ pub fn texture_id(id: &str) -> TextureHandle {
     }
 }
 
+/// 从图片文件加载纹理并以 `name` 注册，同名纹理会被替换。
+///
+/// 需要在渲染器初始化之后调用。
+pub fn load_texture(name: &str, path: impl AsRef<std::path::Path>) -> anyhow::Result<TextureHandle> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read texture '{}' from {:?}", name, path))?;
+
+    load_texture_from_bytes(name, &bytes)
+        .with_context(|| format!("Failed to load texture from {:?}", path))
+}
+
+/// 从内存中的 PNG / JPEG 等图片数据加载纹理
+pub fn load_texture_from_bytes(name: &str, bytes: &[u8]) -> anyhow::Result<TextureHandle> {
+    let img = image::load_from_memory(bytes)
+        .with_context(|| format!("Failed to decode texture '{}'", name))?;
+
+    load_texture_from_image(name, img)
+}
+
+pub fn load_texture_from_image(name: &str, img: DynamicImage) -> anyhow::Result<TextureHandle> {
+    if !check_wgpu_init() {
+        bail!("Cannot load texture '{}' before the renderer is initialized", name);
+    }
+
+    let wr = get_global_wgpu().read();
+    let context = &wr.context;
+
+    context
+        .capabilities
+        .check_texture_size(uvec2(img.width(), img.height()))
+        .with_context(|| format!("Cannot load texture '{}'", name))?;
+
+    let texture = Texture::from_image_ex(
+        &context.device,
+        &context.queue,
+        &img,
+        Some(name),
+        false,
+        AddressMode::Repeat,
+    )
+    .with_context(|| format!("Failed to create texture '{}'", name))?;
+
+    let mut textures = context.textures.lock();
+
+    // 替换时先释放旧纹理的显存
+    if let Some(old) = textures.remove(&texture_path(name)) {
+        old.texture.texture.destroy();
+    }
+
+    load_texture_with_image(context, name, img, texture, &mut textures);
+
+    drop(textures);
+    drop(wr);
+
+    // 图集里的旧区域还是旧图片的像素，移除后按新图片重新打包
+    let handle = texture_path(name);
+    if atlas_remove(handle)
+        && let Err(e) = atlas_add_texture(name)
+    {
+        warn!("Texture '{}' no longer fits the atlas, drawing it directly: {}", name, e);
+    }
+
+    Ok(handle)
+}
+
+/// 卸载纹理并释放显存，返回是否存在该纹理。
+///
+/// 本帧已经排队、仍引用该纹理的绘制会退回到 "error" 纹理（未加载时为 "1px"）。
+pub fn unload_texture(name: &str) -> bool {
+    let Some(handle) = ASSETS.write().textures.remove(name) else {
+        return false;
+    };
+
+    ASSETS.read().texture_image_map.lock().remove(&handle);
+    atlas_remove(handle);
+
+    if check_wgpu_init() {
+        let wr = get_global_wgpu().read();
+        if let Some(texture) = wr.context.textures.lock().remove(&handle) {
+            texture.texture.texture.destroy();
+        }
+    }
+
+    true
+}
+
 // TODO: rename to something like "unchecked_id"
 pub fn texture_path(path: &str) -> TextureHandle {
     TextureHandle::from_path(path)
@@ -71,3 +164,37 @@ pub enum ImageSizeResult {
     Loaded(UVec2),
 }
 
+#[test]
+fn runtime_texture_loading() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(16, 16, image::Rgba([0, 255, 0, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        let handle = load_texture_from_bytes("runtime_green", &png).unwrap();
+        assert_eq!(texture_id("runtime_green"), handle);
+
+        draw_sprite_ex(handle, DrawTextureParams::default());
+    }) else {
+        return;
+    };
+
+    // 未指定尺寸时按图片尺寸绘制在屏幕中心
+    assert_eq!(image.get_pixel(64, 64), &image::Rgba([0, 255, 0, 255]));
+    assert_eq!(image.get_pixel(64 + 12, 64), &image::Rgba([0, 0, 0, 255]));
+    assert!(matches!(
+        Assets::image_size(texture_path("runtime_green")),
+        ImageSizeResult::Loaded(size) if size == uvec2(16, 16)
+    ));
+
+    assert!(unload_texture("runtime_green"));
+    assert!(!unload_texture("runtime_green"));
+    assert_eq!(texture_id_safe("runtime_green"), None);
+
+    assert!(load_texture_from_bytes("broken", b"not an image").is_err());
+    assert!(load_texture("missing", "tests/golden/does_not_exist.png").is_err());
+    assert_eq!(texture_id_safe("broken"), None);
+}
@@ -0,0 +1,506 @@
+//! 运行时纹理图集。
+//!
+//! 小纹理（音符皮肤、判定文字、粒子等）打包进共享的图集页，`draw_sprite_ex`
+//! 绘制时把纹理换成所在的页并改写 UV，这样不同的小纹理可以落在同一个
+//! `MeshGroupKey` 里合批。图集页是普通的纹理，以 `__atlas_page_{i}` 注册。
+
+use crate::*;
+
+use anyhow::{Result, anyhow, bail};
+use image::RgbaImage;
+use wgpu::{Extent3d, FilterMode, Origin3d, TextureAspect};
+
+/// 每个区域四周复制一圈边缘像素，避免采样到相邻的纹理
+const ATLAS_PADDING: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    /// 在图集页中的像素位置（不含 padding）
+    pub position: UVec2,
+    pub size: UVec2,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+impl AtlasRegion {
+    /// 把纹理自身的 UV 映射到图集页上
+    pub fn map_uv(&self, uv: Vec2) -> Vec2 {
+        self.uv_min + uv * (self.uv_max - self.uv_min)
+    }
+}
+
+/// 按行（shelf）放置矩形的简单装箱器，只分配不回收
+#[derive(Clone, Debug)]
+pub struct ShelfPacker {
+    size: UVec2,
+    // (y, 高度, 已用宽度)
+    shelves: Vec<(u32, u32, u32)>,
+    used_height: u32,
+    used_pixels: u64,
+}
+
+impl ShelfPacker {
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            size,
+            shelves: Vec::new(),
+            used_height: 0,
+            used_pixels: 0,
+        }
+    }
+
+    /// 返回矩形左下角的位置，放不下时返回 `None`
+    pub fn allocate(&mut self, size: UVec2) -> Option<UVec2> {
+        if size.x > self.size.x || size.y > self.size.y {
+            return None;
+        }
+
+        // 选择能放下且高度最接近的行，减少浪费
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|(_, height, used)| *height >= size.y && self.size.x - *used >= size.x)
+            .min_by_key(|(_, height, _)| *height);
+
+        let position = match best {
+            Some((y, _, used)) => {
+                let position = uvec2(*used, *y);
+                *used += size.x;
+                position
+            }
+            None => {
+                if self.size.y - self.used_height < size.y {
+                    return None;
+                }
+
+                let position = uvec2(0, self.used_height);
+                self.shelves.push((self.used_height, size.y, size.x));
+                self.used_height += size.y;
+                position
+            }
+        };
+
+        self.used_pixels += size.x as u64 * size.y as u64;
+
+        Some(position)
+    }
+
+    pub fn used_pixels(&self) -> u64 {
+        self.used_pixels
+    }
+
+    pub fn occupancy(&self) -> f32 {
+        self.used_pixels as f32 / (self.size.x as u64 * self.size.y as u64) as f32
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AtlasPageStats {
+    pub entries: usize,
+    pub used_pixels: u64,
+    pub total_pixels: u64,
+    /// 已分配像素（含 padding）占整页的比例
+    pub occupancy: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AtlasStats {
+    pub entries: usize,
+    pub pages: Vec<AtlasPageStats>,
+}
+
+impl AtlasStats {
+    pub fn occupancy(&self) -> f32 {
+        let total: u64 = self.pages.iter().map(|p| p.total_pixels).sum();
+        let used: u64 = self.pages.iter().map(|p| p.used_pixels).sum();
+
+        if total == 0 { 0.0 } else { used as f32 / total as f32 }
+    }
+}
+
+struct AtlasPage {
+    handle: TextureHandle,
+    packer: ShelfPacker,
+    entries: usize,
+}
+
+pub struct TextureAtlas {
+    pub page_size: UVec2,
+    /// 超过这个尺寸的纹理不进图集
+    pub max_entry_size: UVec2,
+    pages: Vec<AtlasPage>,
+    regions: HashMap<TextureHandle, AtlasRegion>,
+}
+
+impl Default for TextureAtlas {
+    fn default() -> Self {
+        Self {
+            page_size: uvec2(2048, 2048),
+            max_entry_size: uvec2(512, 512),
+            pages: Vec::new(),
+            regions: HashMap::new(),
+        }
+    }
+}
+
+impl TextureAtlas {
+    pub fn region(&self, handle: TextureHandle) -> Option<AtlasRegion> {
+        self.regions.get(&handle).copied()
+    }
+
+    pub fn page_handle(&self, page: usize) -> Option<TextureHandle> {
+        self.pages.get(page).map(|p| p.handle)
+    }
+
+    pub fn stats(&self) -> AtlasStats {
+        AtlasStats {
+            entries: self.regions.len(),
+            pages: self
+                .pages
+                .iter()
+                .map(|page| {
+                    let total_pixels = page.packer.size.x as u64 * page.packer.size.y as u64;
+
+                    AtlasPageStats {
+                        entries: page.entries,
+                        used_pixels: page.packer.used_pixels(),
+                        total_pixels,
+                        occupancy: page.packer.occupancy(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn insert(&mut self, handle: TextureHandle, image: &RgbaImage) -> Result<AtlasRegion> {
+        let size = uvec2(image.width(), image.height());
+
+        if size.x == 0 || size.y == 0 {
+            bail!("Cannot add an empty image to the atlas");
+        }
+
+        if size.x > self.max_entry_size.x || size.y > self.max_entry_size.y {
+            bail!(
+                "Image {}x{} is larger than the atlas entry limit {}x{}",
+                size.x,
+                size.y,
+                self.max_entry_size.x,
+                self.max_entry_size.y
+            );
+        }
+
+        let padded = size + UVec2::splat(ATLAS_PADDING * 2);
+
+        let allocation = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, page)| page.packer.allocate(padded).map(|p| (i, p)));
+
+        let (page, position) = match allocation {
+            Some(allocation) => allocation,
+            None => {
+                let page = self.create_page()?;
+                let position = self.pages[page]
+                    .packer
+                    .allocate(padded)
+                    .ok_or_else(|| anyhow!("Image does not fit into an empty atlas page"))?;
+
+                (page, position)
+            }
+        };
+
+        write_padded_image(self.pages[page].handle, position, image)?;
+
+        self.pages[page].entries += 1;
+
+        let page_size = self.pages[page].packer.size.as_vec2();
+        let position = position + UVec2::splat(ATLAS_PADDING);
+
+        let region = AtlasRegion {
+            page,
+            position,
+            size,
+            uv_min: position.as_vec2() / page_size,
+            uv_max: (position + size).as_vec2() / page_size,
+        };
+
+        self.regions.insert(handle, region);
+
+        Ok(region)
+    }
+
+    fn create_page(&mut self) -> Result<usize> {
+        let index = self.pages.len();
+        let (handle, size) = create_page_texture(
+            &format!("__atlas_page_{index}"),
+            self.page_size,
+            Texture::IMAGE_FILTER_MODE,
+        )?;
+
+        info!("Created atlas page {} ({}x{})", index, size.x, size.y);
+
+        self.pages.push(AtlasPage {
+            handle,
+            packer: ShelfPacker::new(size),
+            entries: 0,
+        });
+
+        Ok(index)
+    }
+}
+
+/// 创建一张空白的透明纹理页并以 `name` 注册，尺寸会被钳制到设备上限
+pub(crate) fn create_page_texture(
+    name: &str,
+    size: UVec2,
+    filter_mode: FilterMode,
+) -> Result<(TextureHandle, UVec2)> {
+    if !check_wgpu_init() {
+        bail!("Cannot create texture page '{}' before the renderer is initialized", name);
+    }
+
+    let wr = get_global_wgpu().read();
+    let context = &wr.context;
+
+    let size = context.capabilities.clamp_texture_size(size);
+
+    let texture = BindableTexture::new(
+        &context.device,
+        &context.texture_layout,
+        &TextureCreationParams {
+            label: Some(name),
+            width: size.x,
+            height: size.y,
+            format: TextureFormat::Rgba8UnormSrgb,
+            filter_mode,
+            ..Default::default()
+        },
+    );
+
+    let handle = texture_path(name);
+    context.textures.lock().insert(handle, texture);
+
+    Ok((handle, size))
+}
+
+/// 把图片原样写到纹理页的 `position` 处
+pub(crate) fn write_texture_region(
+    page: TextureHandle,
+    position: UVec2,
+    image: &RgbaImage,
+) -> Result<()> {
+    let wr = get_global_wgpu().read();
+    let textures = wr.context.textures.lock();
+    let texture = textures
+        .get(&page)
+        .ok_or_else(|| anyhow!("Texture page is missing"))?;
+
+    wr.context.queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture.texture.texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: position.x,
+                y: position.y,
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        image,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width()),
+            rows_per_image: Some(image.height()),
+        },
+        Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+
+    Ok(())
+}
+
+/// 上下翻转后写入图集页（与 `Texture::from_image` 的方向一致），并复制一圈边缘像素
+fn write_padded_image(page: TextureHandle, position: UVec2, image: &RgbaImage) -> Result<()> {
+    let (w, h) = image.dimensions();
+    let pad = ATLAS_PADDING;
+
+    let padded = RgbaImage::from_fn(w + pad * 2, h + pad * 2, |x, y| {
+        let sx = x.saturating_sub(pad).min(w - 1);
+        let sy = y.saturating_sub(pad).min(h - 1);
+        *image.get_pixel(sx, h - 1 - sy)
+    });
+
+    write_texture_region(page, position, &padded)
+}
+
+static ATLAS: Lazy<RwLock<TextureAtlas>> = Lazy::new(|| RwLock::new(TextureAtlas::default()));
+
+pub fn get_atlas() -> &'static RwLock<TextureAtlas> {
+    &ATLAS
+}
+
+/// 把已经加载的纹理加入图集，之后用同一个名字绘制时会自动使用图集页
+pub fn atlas_add_texture(name: &str) -> Result<AtlasRegion> {
+    let handle = texture_id_safe(name).ok_or_else(|| anyhow!("Texture '{}' is not loaded", name))?;
+
+    if let Some(region) = ATLAS.read().region(handle) {
+        return Ok(region);
+    }
+
+    let image = ASSETS
+        .read()
+        .texture_image_map
+        .lock()
+        .get(&handle)
+        .cloned()
+        .ok_or_else(|| anyhow!("Texture '{}' has no CPU image", name))?;
+
+    ATLAS.write().insert(handle, &image)
+}
+
+/// 直接把图片放进图集并以 `name` 注册，不会为它单独创建 GPU 纹理
+pub fn atlas_add_image(name: &str, image: RgbaImage) -> Result<AtlasRegion> {
+    let handle = texture_path(name);
+    let region = ATLAS.write().insert(handle, &image)?;
+
+    let mut assets = ASSETS.write();
+    assets.insert_handle(name, handle);
+    assets.texture_image_map.lock().insert(handle, Arc::new(image));
+
+    Ok(region)
+}
+
+/// 从图集中移除纹理的映射，占用的空间不会回收
+pub fn atlas_remove(handle: TextureHandle) -> bool {
+    ATLAS.write().regions.remove(&handle).is_some()
+}
+
+pub fn atlas_region(handle: TextureHandle) -> Option<AtlasRegion> {
+    ATLAS.read().region(handle)
+}
+
+pub fn atlas_stats() -> AtlasStats {
+    ATLAS.read().stats()
+}
+
+#[test]
+fn shelf_packer_fills_rows() {
+    let mut packer = ShelfPacker::new(uvec2(64, 64));
+
+    assert_eq!(packer.allocate(uvec2(32, 16)), Some(uvec2(0, 0)));
+    assert_eq!(packer.allocate(uvec2(16, 8)), Some(uvec2(32, 0)));
+    // 第一行剩下 16 像素宽，放不下 20，开新的一行
+    assert_eq!(packer.allocate(uvec2(20, 20)), Some(uvec2(0, 16)));
+    // 矮的矩形优先放进最矮且放得下的行
+    assert_eq!(packer.allocate(uvec2(16, 10)), Some(uvec2(48, 0)));
+    assert_eq!(packer.allocate(uvec2(40, 8)), Some(uvec2(20, 16)));
+
+    assert_eq!(packer.allocate(uvec2(65, 1)), None);
+    assert_eq!(packer.allocate(uvec2(64, 30)), None);
+    assert_eq!(packer.allocate(uvec2(64, 28)), Some(uvec2(0, 36)));
+
+    let used = 32 * 16 + 16 * 8 + 20 * 20 + 16 * 10 + 40 * 8 + 64 * 28;
+    assert_eq!(packer.used_pixels(), used);
+    assert!((packer.occupancy() - used as f32 / 4096.0).abs() < 1e-6);
+}
+
+#[test]
+fn atlas_batches_and_matches_plain_textures() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    // 四个象限颜色不同，用来检查图集里的方向和 UV
+    let pattern = RgbaImage::from_fn(8, 8, |x, y| match (x < 4, y < 4) {
+        (true, true) => image::Rgba([255, 0, 0, 255]),
+        (false, true) => image::Rgba([0, 255, 0, 255]),
+        (true, false) => image::Rgba([0, 0, 255, 255]),
+        (false, false) => image::Rgba([255, 255, 255, 255]),
+    });
+
+    let mut png = Vec::new();
+    pattern
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+    let params = |x: f32| DrawTextureParams {
+        raw_draw_params: RawDrawParams {
+            position: vec3(x, 0.0, 0.0),
+            dest_size: Some(uvec2(16, 16)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        load_texture_from_bytes("atlas_plain", &png).unwrap();
+        let region = atlas_add_image("atlas_a", pattern.clone()).unwrap();
+        atlas_add_image("atlas_b", RgbaImage::from_pixel(4, 4, image::Rgba([255, 255, 0, 255]))).unwrap();
+
+        // 不同的图集纹理落在同一组里
+        draw_sprite_ex(texture_id("atlas_a"), params(20.0));
+        draw_sprite_ex(texture_id("atlas_b"), params(40.0));
+
+        let queues = consume_render_queues();
+        assert_eq!(queues.len(), 1);
+        assert_eq!(
+            Some(queues.keys().next().unwrap().texture_id),
+            get_atlas().read().page_handle(region.page)
+        );
+
+        draw_sprite_ex(texture_id("atlas_plain"), params(-20.0));
+        draw_sprite_ex(texture_id("atlas_a"), params(20.0));
+    }) else {
+        return;
+    };
+
+    // 图集绘制的结果与单独纹理逐像素一致
+    for y in 56..72 {
+        for x in 36..52 {
+            assert_eq!(image.get_pixel(x, y), image.get_pixel(x + 40, y), "({x}, {y})");
+        }
+    }
+    assert_ne!(image.get_pixel(40, 60), image.get_pixel(48, 60));
+    assert_ne!(image.get_pixel(40, 60), image.get_pixel(40, 68));
+
+    let stats = atlas_stats();
+    assert!(stats.entries >= 2);
+    assert!(stats.occupancy() > 0.0);
+    assert_eq!(stats.pages.iter().map(|p| p.entries).sum::<usize>(), stats.entries);
+
+    unload_texture("atlas_plain");
+}
+
+#[test]
+fn reloading_atlas_texture_draws_new_pixels() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let png = |color: image::Rgba<u8>| {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(8, 8, color)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    };
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        load_texture_from_bytes("atlas_reload", &png(image::Rgba([255, 0, 0, 255]))).unwrap();
+        atlas_add_texture("atlas_reload").unwrap();
+        draw_sprite_ex(texture_id("atlas_reload"), DrawTextureParams::default());
+        render_headless_frame();
+
+        load_texture_from_bytes("atlas_reload", &png(image::Rgba([0, 0, 255, 255]))).unwrap();
+        assert!(atlas_region(texture_id("atlas_reload")).is_some());
+
+        draw_sprite_ex(texture_id("atlas_reload"), DrawTextureParams::default());
+    }) else {
+        return;
+    };
+
+    assert_eq!(image.get_pixel(64, 64), &image::Rgba([0, 0, 255, 255]));
+
+    unload_texture("atlas_reload");
+}
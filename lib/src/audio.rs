@@ -0,0 +1,954 @@
+use crate::*;
+
+use anyhow::{Result, anyhow, bail};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// 混音器输出固定为交错的双声道 f32
+pub const AUDIO_CHANNELS: usize = 2;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AudioBackend {
+    /// 输出到系统默认声卡（需要 `cpal` feature），不可用时退回 `Null`
+    #[default]
+    Device,
+    /// 不发声，但按真实时间消耗混音数据，播放位置照常推进
+    Null,
+    /// 不启动任何线程，由调用者通过 [`render_audio_offline`] 手动驱动混音
+    Offline,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AudioConfig {
+    pub backend: AudioBackend,
+    /// `Null` / `Offline` 输出的采样率，`Device` 使用设备自己的采样率
+    pub sample_rate: u32,
+    /// `Null` 输出每次混音的帧数
+    pub buffer_frames: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            backend: AudioBackend::default(),
+            sample_rate: 48000,
+            buffer_frames: 512,
+        }
+    }
+}
+
+/// 解码后的音频，统一存为双声道 f32 帧
+#[derive(Debug)]
+pub struct SoundData {
+    pub sample_rate: u32,
+    pub frames: Vec<[f32; 2]>,
+}
+
+impl SoundData {
+    pub fn duration(&self) -> f64 {
+        self.frames.len() as f64 / self.sample_rate as f64
+    }
+}
+
+/// 解码 OGG / WAV / MP3 / FLAC，`extension` 用来辅助格式探测，可以为空。
+pub fn decode_sound(bytes: Vec<u8>, extension: Option<&str>) -> Result<SoundData> {
+    let mss = MediaSourceStream::new(Box::new(std::io::Cursor::new(bytes)), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("No audio track found"))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| anyhow!("Unknown sample rate"))?;
+
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut frames = Vec::new();
+    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 损坏的包直接跳过
+            Err(SymphoniaError::DecodeError(e)) => {
+                warn!("Skipping undecodable audio packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+
+        let buffer = sample_buffer.get_or_insert_with(|| {
+            SampleBuffer::<f32>::new(decoded.capacity() as u64, spec)
+        });
+
+        if buffer.capacity() < decoded.capacity() * channels {
+            *buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        }
+
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks_exact(channels) {
+            frames.push(match channels {
+                1 => [frame[0], frame[0]],
+                _ => [frame[0], frame[1]],
+            });
+        }
+    }
+
+    if frames.is_empty() {
+        bail!("Audio stream contains no samples");
+    }
+
+    Ok(SoundData {
+        sample_rate,
+        frames,
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VoiceId(pub u64);
+
+#[derive(Copy, Clone, Debug)]
+pub struct PlaySoundParams {
+    pub volume: f32,
+    /// -1.0 为最左，1.0 为最右
+    pub pan: f32,
+    pub looped: bool,
+}
+
+impl Default for PlaySoundParams {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pan: 0.0,
+            looped: false,
+        }
+    }
+}
+
+struct Voice {
+    id: VoiceId,
+    sound: Arc<SoundData>,
+    // 以源音频的帧为单位，带小数用于重采样
+    position: f64,
+    params: PlaySoundParams,
+    paused: bool,
+    // 播放速率，1.0 为原速
+    rate: f64,
+    // 从混音器的第几帧开始发声，用于按时间预约的音效
+    start_frame: u64,
+    // 已经开始发声，只有开始发声的声部计入复音数
+    started: bool,
+    // 复音数限制的分组
+    group: Option<VoiceGroup>,
+}
+
+/// 同一分组的声部最多同时存在 `max_voices` 个，超出时抢占最早开始的
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceGroup {
+    pub id: u64,
+    pub max_voices: usize,
+}
+
+impl Voice {
+    fn is_finished(&self) -> bool {
+        let len = self.sound.frames.len();
+        len == 0 || (!self.params.looped && self.position >= len as f64)
+    }
+
+    /// 把当前声部混入 `out`，返回是否还有数据
+    fn mix_into(&mut self, out: &mut [f32], output_sample_rate: u32) -> bool {
+        if self.paused {
+            return true;
+        }
+
+        let frames = &self.sound.frames;
+        let len = frames.len();
+
+        // 空的音频没有可以循环的数据
+        if len == 0 {
+            return false;
+        }
+
+        let step = self.sound.sample_rate as f64 / output_sample_rate as f64 * self.rate;
+
+        // 简单的平衡声像，居中时两边都保持原音量
+        let left_gain = self.params.volume * (1.0 - self.params.pan).min(1.0);
+        let right_gain = self.params.volume * (1.0 + self.params.pan).min(1.0);
+
+        for out_frame in out.chunks_exact_mut(AUDIO_CHANNELS) {
+            if self.position >= len as f64 {
+                if self.params.looped {
+                    self.position %= len as f64;
+                } else {
+                    return false;
+                }
+            }
+
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            let a = frames[index];
+            let b = if index + 1 < len {
+                frames[index + 1]
+            } else if self.params.looped {
+                frames[0]
+            } else {
+                a
+            };
+
+            out_frame[0] += (a[0] + (b[0] - a[0]) * t) * left_gain;
+            out_frame[1] += (a[1] + (b[1] - a[1]) * t) * right_gain;
+
+            self.position += step;
+        }
+
+        true
+    }
+}
+
+/// 软件混音器，输出后端每次从这里拉取一段交错的双声道数据。
+///
+/// 播放位置只由混音器实际消耗的帧数推进，不依赖墙上时间。
+pub struct Mixer {
+    sample_rate: u32,
+    frames_mixed: u64,
+    next_voice_id: u64,
+
+    music: Option<Voice>,
+    voices: Vec<Voice>,
+
+    /// 音效声部总数上限，超出时抢占最早开始的声部
+    pub max_voices: usize,
+
+    // 某一帧对应的帧计时器时间，用来把游戏时间换算成混音帧
+    clock_anchor: Option<(u64, f64)>,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            frames_mixed: 0,
+            next_voice_id: 1,
+            music: None,
+            voices: Vec::new(),
+            max_voices: 64,
+            clock_anchor: None,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// 混音器启动以来输出的总帧数
+    pub fn frames_mixed(&self) -> u64 {
+        self.frames_mixed
+    }
+
+    fn gen_voice_id(&mut self) -> VoiceId {
+        let id = VoiceId(self.next_voice_id);
+        self.next_voice_id += 1;
+        id
+    }
+
+    pub fn play_sound(&mut self, sound: Arc<SoundData>, params: PlaySoundParams) -> VoiceId {
+        self.schedule_sound(sound, params, self.frames_mixed, None)
+    }
+
+    /// 从第 `start_frame` 帧开始播放，已经过去的帧会立即播放
+    pub fn schedule_sound(
+        &mut self,
+        sound: Arc<SoundData>,
+        params: PlaySoundParams,
+        start_frame: u64,
+        group: Option<VoiceGroup>,
+    ) -> VoiceId {
+        // 预约到之后的声部等开始发声时再抢占，不影响正在播放的声部
+        let started = start_frame <= self.frames_mixed;
+        if started {
+            self.make_room(group);
+        }
+
+        let id = self.gen_voice_id();
+
+        self.voices.push(Voice {
+            id,
+            sound,
+            position: 0.0,
+            params,
+            paused: false,
+            rate: 1.0,
+            start_frame,
+            started,
+            group,
+        });
+
+        id
+    }
+
+    // 为即将发声的声部腾出分组和总数上的位置
+    fn make_room(&mut self, group: Option<VoiceGroup>) {
+        if let Some(group) = group {
+            self.steal_voices(|voice| voice.group.is_some_and(|g| g.id == group.id), group.max_voices);
+        }
+        self.steal_voices(|_| true, self.max_voices);
+    }
+
+    // 符合条件且已经发声的声部达到上限时去掉最早开始的
+    fn steal_voices(&mut self, matches: impl Fn(&Voice) -> bool, max_voices: usize) {
+        let max_voices = max_voices.max(1);
+
+        while self.voices.iter().filter(|v| v.started && matches(v)).count() >= max_voices {
+            let oldest = self
+                .voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.started && matches(v))
+                .min_by_key(|(_, v)| (v.start_frame, v.id))
+                .map(|(i, _)| i);
+
+            match oldest {
+                Some(i) => {
+                    self.voices.remove(i);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// 记录 `frame` 帧在帧计时器上的时间，由输出后端在每次混音前调用
+    pub fn set_clock_anchor(&mut self, frame: u64, game_time: f64) {
+        // 回调时间有抖动，小误差只做缓慢修正
+        if let Some(predicted) = self.frame_to_game_time(frame) {
+            let error = game_time - predicted;
+            if error.abs() < 0.02 {
+                self.clock_anchor = Some((frame, predicted + error * 0.05));
+                return;
+            }
+        }
+
+        self.clock_anchor = Some((frame, game_time));
+    }
+
+    pub fn frame_to_game_time(&self, frame: u64) -> Option<f64> {
+        let (anchor_frame, anchor_time) = self.clock_anchor?;
+        Some(anchor_time + (frame as f64 - anchor_frame as f64) / self.sample_rate as f64)
+    }
+
+    /// 帧计时器上的时间对应的混音帧，没有时间锚点时返回 `None`
+    pub fn game_time_to_frame(&self, game_time: f64) -> Option<u64> {
+        let (anchor_frame, anchor_time) = self.clock_anchor?;
+        let frame = anchor_frame as f64 + (game_time - anchor_time) * self.sample_rate as f64;
+
+        Some(frame.round().max(0.0) as u64)
+    }
+
+    pub fn stop_sound(&mut self, id: VoiceId) {
+        self.voices.retain(|voice| voice.id != id);
+    }
+
+    pub fn play_music(&mut self, sound: Arc<SoundData>, params: PlaySoundParams) -> VoiceId {
+        let id = self.gen_voice_id();
+
+        self.music = Some(Voice {
+            id,
+            sound,
+            position: 0.0,
+            params,
+            paused: false,
+            rate: 1.0,
+            start_frame: 0,
+            started: true,
+            group: None,
+        });
+
+        id
+    }
+
+    pub fn stop_music(&mut self) {
+        self.music = None;
+    }
+
+    pub fn set_music_paused(&mut self, paused: bool) {
+        if let Some(music) = &mut self.music {
+            music.paused = paused;
+        }
+    }
+
+    pub fn set_music_volume(&mut self, volume: f32) {
+        if let Some(music) = &mut self.music {
+            music.params.volume = volume;
+        }
+    }
+
+    /// 修改音乐播放速率（不保持音高）
+    pub fn set_music_rate(&mut self, rate: f64) {
+        if let Some(music) = &mut self.music {
+            music.rate = rate.max(0.0);
+        }
+    }
+
+    pub fn seek_music(&mut self, seconds: f64) {
+        if let Some(music) = &mut self.music {
+            music.position = (seconds.max(0.0) * music.sound.sample_rate as f64)
+                .min(music.sound.frames.len() as f64);
+        }
+    }
+
+    /// 当前音乐已经被混音消耗到的位置（秒）
+    pub fn music_position(&self) -> Option<f64> {
+        self.music
+            .as_ref()
+            .map(|music| music.position / music.sound.sample_rate as f64)
+    }
+
+    pub fn is_music_playing(&self) -> bool {
+        self.music
+            .as_ref()
+            .is_some_and(|music| !music.paused && !music.is_finished())
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// 混音到 `out`（交错双声道），长度必须是声道数的整数倍
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+
+        let sample_rate = self.sample_rate;
+
+        if let Some(music) = &mut self.music {
+            music.mix_into(out, sample_rate);
+        }
+
+        let frames_mixed = self.frames_mixed;
+        let block_frames = (out.len() / AUDIO_CHANNELS) as u64;
+
+        // 本块内开始发声的预约声部按开始顺序计入复音数
+        while let Some((id, group)) = self
+            .voices
+            .iter()
+            .filter(|v| !v.started && v.start_frame < frames_mixed + block_frames)
+            .min_by_key(|v| (v.start_frame, v.id))
+            .map(|v| (v.id, v.group))
+        {
+            self.make_room(group);
+
+            if let Some(voice) = self.voices.iter_mut().find(|v| v.id == id) {
+                voice.started = true;
+            }
+        }
+
+        self.voices.retain_mut(|voice| {
+            // 预约的声部从块内对应的帧开始混入
+            let start = voice.start_frame.saturating_sub(frames_mixed);
+            if start >= block_frames {
+                return true;
+            }
+
+            voice.mix_into(&mut out[start as usize * AUDIO_CHANNELS..], sample_rate)
+        });
+
+        self.frames_mixed += block_frames;
+    }
+
+    /// 混音并记录这一块开始时的帧计时器时间
+    pub fn mix_at(&mut self, out: &mut [f32], game_time: f64) {
+        self.set_clock_anchor(self.frames_mixed, game_time);
+        self.mix(out);
+    }
+}
+
+/// 输出线程 / 设备流的句柄，drop 时停止输出
+enum AudioOutput {
+    Null {
+        running: Arc<AtomicBool>,
+        thread: Option<std::thread::JoinHandle<()>>,
+    },
+    Offline,
+    #[cfg(feature = "cpal")]
+    Device {
+        running: Arc<AtomicBool>,
+        thread: Option<std::thread::JoinHandle<()>>,
+    },
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        match self {
+            AudioOutput::Null { running, thread } => {
+                running.store(false, Ordering::SeqCst);
+                if let Some(thread) = thread.take() {
+                    let _ = thread.join();
+                }
+            }
+            #[cfg(feature = "cpal")]
+            AudioOutput::Device { running, thread } => {
+                running.store(false, Ordering::SeqCst);
+                if let Some(thread) = thread.take() {
+                    thread.thread().unpark();
+                    let _ = thread.join();
+                }
+            }
+            AudioOutput::Offline => (),
+        }
+    }
+}
+
+fn start_null_output(mixer: Arc<Mutex<Mixer>>, buffer_frames: u32) -> AudioOutput {
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+
+    let thread = std::thread::Builder::new()
+        .name("kkrd-null-audio".to_owned())
+        .spawn(move || {
+            let sample_rate = mixer.lock().sample_rate();
+            let period = Duration::from_secs_f64(buffer_frames as f64 / sample_rate as f64);
+            let mut buffer = vec![0.0; buffer_frames as usize * AUDIO_CHANNELS];
+            let mut deadline = Instant::now();
+
+            while thread_running.load(Ordering::SeqCst) {
+                mixer.lock().mix_at(&mut buffer, get_precise_time());
+
+                deadline += period;
+                spin_sleep::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        })
+        .expect("Failed to spawn null audio thread");
+
+    AudioOutput::Null {
+        running,
+        thread: Some(thread),
+    }
+}
+
+/// 在独立线程里创建 cpal 输出流（部分平台的 Stream 不能跨线程），返回设备采样率
+#[cfg(feature = "cpal")]
+fn start_device_output(
+    mixer_slot: Arc<OnceLock<Arc<Mutex<Mixer>>>>,
+) -> Result<(AudioOutput, u32)> {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let (init_tx, init_rx) = std::sync::mpsc::channel::<Result<u32>>();
+
+    let thread = std::thread::Builder::new()
+        .name("kkrd-audio-device".to_owned())
+        .spawn(move || {
+            let build = || -> Result<(cpal::Stream, u32)> {
+                let host = cpal::default_host();
+                let device = host
+                    .default_output_device()
+                    .ok_or_else(|| anyhow!("No audio output device"))?;
+
+                let supported = device.default_output_config()?;
+                let sample_rate = supported.sample_rate().0;
+                let channels = supported.channels() as usize;
+
+                info!(
+                    "Audio device: {:?} ({} Hz, {} channels, {:?})",
+                    device.name().unwrap_or_default(),
+                    sample_rate,
+                    channels,
+                    supported.sample_format()
+                );
+
+                let slot = mixer_slot.clone();
+                let mut scratch = Vec::<f32>::new();
+
+                let mut fill = move |data_len: usize, write: &mut dyn FnMut(usize, f32)| {
+                    let frames = data_len / channels;
+                    scratch.resize(frames * AUDIO_CHANNELS, 0.0);
+
+                    match slot.get() {
+                        Some(mixer) => mixer.lock().mix_at(&mut scratch, get_precise_time()),
+                        None => scratch.fill(0.0),
+                    }
+
+                    for (i, frame) in scratch.chunks_exact(AUDIO_CHANNELS).enumerate() {
+                        for c in 0..channels {
+                            let sample = match (channels, c) {
+                                (1, _) => (frame[0] + frame[1]) * 0.5,
+                                (_, 0) => frame[0],
+                                (_, 1) => frame[1],
+                                _ => 0.0,
+                            };
+                            write(i * channels + c, sample);
+                        }
+                    }
+                };
+
+                let config = supported.config();
+                let on_error = |e| error!("Audio stream error: {}", e);
+
+                let stream = match supported.sample_format() {
+                    cpal::SampleFormat::F32 => device.build_output_stream(
+                        &config,
+                        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                            fill(data.len(), &mut |i, s| data[i] = s);
+                        },
+                        on_error,
+                        None,
+                    )?,
+                    cpal::SampleFormat::I16 => device.build_output_stream(
+                        &config,
+                        move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
+                            fill(data.len(), &mut |i, s| {
+                                data[i] = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+                            });
+                        },
+                        on_error,
+                        None,
+                    )?,
+                    format => bail!("Unsupported audio sample format {:?}", format),
+                };
+
+                stream.play()?;
+
+                Ok((stream, sample_rate))
+            };
+
+            match build() {
+                Ok((stream, sample_rate)) => {
+                    let _ = init_tx.send(Ok(sample_rate));
+
+                    while thread_running.load(Ordering::SeqCst) {
+                        std::thread::park();
+                    }
+
+                    drop(stream);
+                }
+                Err(e) => {
+                    let _ = init_tx.send(Err(e));
+                }
+            }
+        })?;
+
+    let sample_rate = init_rx.recv()??;
+
+    Ok((
+        AudioOutput::Device {
+            running,
+            thread: Some(thread),
+        },
+        sample_rate,
+    ))
+}
+
+pub struct AudioEngine {
+    pub mixer: Arc<Mutex<Mixer>>,
+    pub sounds: HashMap<String, Arc<SoundData>>,
+
+    backend: AudioBackend,
+    _output: AudioOutput,
+}
+
+impl AudioEngine {
+    pub fn new(config: &AudioConfig) -> Self {
+        #[cfg(feature = "cpal")]
+        if config.backend == AudioBackend::Device {
+            let mixer_slot = Arc::new(OnceLock::new());
+
+            match start_device_output(mixer_slot.clone()) {
+                Ok((output, sample_rate)) => {
+                    let mixer = Arc::new(Mutex::new(Mixer::new(sample_rate)));
+                    let _ = mixer_slot.set(mixer.clone());
+
+                    return Self {
+                        mixer,
+                        sounds: HashMap::new(),
+                        backend: AudioBackend::Device,
+                        _output: output,
+                    };
+                }
+                Err(e) => warn!("Failed to open audio device, using null output: {}", e),
+            }
+        }
+
+        let mixer = Arc::new(Mutex::new(Mixer::new(config.sample_rate)));
+
+        let (backend, output) = match config.backend {
+            AudioBackend::Offline => (AudioBackend::Offline, AudioOutput::Offline),
+            AudioBackend::Device | AudioBackend::Null => {
+                #[cfg(not(feature = "cpal"))]
+                if config.backend == AudioBackend::Device {
+                    warn!("AudioBackend::Device needs the `cpal` feature, using null audio output");
+                }
+
+                (
+                    AudioBackend::Null,
+                    start_null_output(mixer.clone(), config.buffer_frames),
+                )
+            }
+        };
+
+        Self {
+            mixer,
+            sounds: HashMap::new(),
+            backend,
+            _output: output,
+        }
+    }
+
+    /// 实际使用的输出后端（`Device` 打开失败时会是 `Null`）
+    pub fn backend(&self) -> AudioBackend {
+        self.backend
+    }
+}
+
+static AUDIO: OnceLock<Arc<RwLock<AudioEngine>>> = OnceLock::new();
+
+pub fn init_audio(config: &AudioConfig) {
+    let _ = AUDIO.get_or_init(|| Arc::new(RwLock::new(AudioEngine::new(config))));
+}
+
+pub fn check_audio_init() -> bool {
+    AUDIO.get().is_some()
+}
+
+pub fn get_global_audio() -> &'static Arc<RwLock<AudioEngine>> {
+    AUDIO.get().unwrap_or_else(|| panic!("Audio Not Init"))
+}
+
+pub(crate) fn get_global_mixer() -> Arc<Mutex<Mixer>> {
+    get_global_audio().read().mixer.clone()
+}
+
+pub fn load_sound(name: &str, path: impl AsRef<std::path::Path>) -> Result<()> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    let sound = decode_sound(bytes, extension)?;
+
+    get_global_audio()
+        .write()
+        .sounds
+        .insert(name.to_owned(), Arc::new(sound));
+
+    Ok(())
+}
+
+pub fn load_sound_from_bytes(name: &str, bytes: &[u8]) -> Result<()> {
+    let sound = decode_sound(bytes.to_vec(), None)?;
+
+    get_global_audio()
+        .write()
+        .sounds
+        .insert(name.to_owned(), Arc::new(sound));
+
+    Ok(())
+}
+
+pub fn unload_sound(name: &str) {
+    get_global_audio().write().sounds.remove(name);
+}
+
+fn get_sound(name: &str) -> Option<Arc<SoundData>> {
+    let sound = get_global_audio().read().sounds.get(name).cloned();
+
+    if sound.is_none() {
+        error!("Sound '{}' not loaded", name);
+    }
+
+    sound
+}
+
+/// 播放一次性音效
+pub fn play_sound(name: &str, params: PlaySoundParams) -> Option<VoiceId> {
+    let sound = get_sound(name)?;
+    Some(get_global_mixer().lock().play_sound(sound, params))
+}
+
+pub fn stop_sound(id: VoiceId) {
+    get_global_mixer().lock().stop_sound(id);
+}
+
+/// 播放音乐，同一时间只有一首音乐，会替换之前的
+pub fn play_music(name: &str, params: PlaySoundParams) -> Option<VoiceId> {
+    let sound = get_sound(name)?;
+    Some(get_global_mixer().lock().play_music(sound, params))
+}
+
+pub fn stop_music() {
+    get_global_mixer().lock().stop_music();
+}
+
+pub fn pause_music() {
+    get_global_mixer().lock().set_music_paused(true);
+}
+
+pub fn resume_music() {
+    get_global_mixer().lock().set_music_paused(false);
+}
+
+pub fn set_music_volume(volume: f32) {
+    get_global_mixer().lock().set_music_volume(volume);
+}
+
+pub fn seek_music(seconds: f64) {
+    get_global_mixer().lock().seek_music(seconds);
+}
+
+pub fn set_music_rate(rate: f64) {
+    get_global_mixer().lock().set_music_rate(rate);
+}
+
+/// 音乐已被混音器消耗到的位置（秒），没有音乐时为 `None`
+pub fn get_music_position() -> Option<f64> {
+    get_global_mixer().lock().music_position()
+}
+
+pub fn is_music_playing() -> bool {
+    get_global_mixer().lock().is_music_playing()
+}
+
+/// `Offline` 后端下手动混音 `frames` 帧，返回交错的双声道数据。
+///
+/// `game_time` 是这一块第一帧对应的帧计时器时间，按游戏时间预约的音效依赖它换算成混音帧。
+pub fn render_audio_offline(frames: usize, game_time: f64) -> Vec<f32> {
+    let mut out = vec![0.0; frames * AUDIO_CHANNELS];
+    get_global_mixer().lock().mix_at(&mut out, game_time);
+    out
+}
+
+#[cfg(test)]
+pub(crate) fn test_wav(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    bytes
+}
+
+#[test]
+fn decode_wav() {
+    let sound = decode_sound(test_wav(8000, &[0, i16::MAX, i16::MIN, 0]), Some("wav")).unwrap();
+
+    assert_eq!(sound.sample_rate, 8000);
+    assert_eq!(sound.frames.len(), 4);
+    assert!((sound.frames[1][0] - 1.0).abs() < 1e-3);
+    assert_eq!(sound.frames[1][0], sound.frames[1][1]);
+}
+
+#[test]
+fn mixer_position_follows_consumed_frames() {
+    let sound = Arc::new(SoundData {
+        sample_rate: 24000,
+        frames: vec![[0.5, 0.5]; 24000],
+    });
+
+    let mut mixer = Mixer::new(48000);
+    mixer.play_music(sound.clone(), PlaySoundParams::default());
+
+    let mut out = vec![0.0; 4800 * AUDIO_CHANNELS];
+    mixer.mix(&mut out);
+
+    // 输出 0.1 秒，源音频也应前进 0.1 秒
+    assert!((mixer.music_position().unwrap() - 0.1).abs() < 1e-9);
+    assert_eq!(mixer.frames_mixed(), 4800);
+
+    mixer.set_music_paused(true);
+    mixer.mix(&mut out);
+    assert!((mixer.music_position().unwrap() - 0.1).abs() < 1e-9);
+
+    // 一次性音效：右声像 + 半音量
+    mixer.set_music_paused(false);
+    mixer.stop_music();
+    mixer.play_sound(
+        sound,
+        PlaySoundParams {
+            volume: 0.5,
+            pan: 1.0,
+            ..Default::default()
+        },
+    );
+    mixer.mix(&mut out);
+
+    assert_eq!(out[0], 0.0);
+    assert_eq!(out[1], 0.25);
+    assert_eq!(mixer.active_voices(), 1);
+
+    // 播放完后自动移除
+    let mut long = vec![0.0; 48000 * AUDIO_CHANNELS];
+    mixer.mix(&mut long);
+    mixer.mix(&mut out);
+    assert_eq!(mixer.active_voices(), 0);
+}
+
+#[test]
+fn mixer_ignores_empty_sounds() {
+    let empty = Arc::new(SoundData {
+        sample_rate: 48000,
+        frames: Vec::new(),
+    });
+    let looped = PlaySoundParams {
+        looped: true,
+        ..Default::default()
+    };
+
+    let mut mixer = Mixer::new(48000);
+    mixer.play_music(empty.clone(), looped);
+    mixer.play_sound(empty, looped);
+
+    let mut out = vec![0.0; 64 * AUDIO_CHANNELS];
+    mixer.mix(&mut out);
+
+    assert!(out.iter().all(|&sample| sample == 0.0));
+    assert_eq!(mixer.active_voices(), 0);
+    assert!(!mixer.is_music_playing());
+    assert_eq!(mixer.music_position(), Some(0.0));
+}
@@ -0,0 +1,502 @@
+//! 自动游玩。
+//!
+//! 根据目标列表预先生成一串按下、移动、松开事件，运行时和回放一样在
+//! `App::about_to_wait` 中注入输入模块，游戏代码看到的和真人游玩没有区别。
+
+use crate::*;
+
+use std::fmt;
+
+/// 目标的位置
+#[derive(Clone)]
+pub enum AutoplayPosition {
+    Key(KeyCode),
+    /// 窗口像素坐标，原点在左上角
+    Screen(Vec2),
+    /// 随歌曲时间移动的位置（窗口像素坐标），例如挂在移动判定线上的音符
+    Tracked(Arc<dyn Fn(f64) -> Vec2 + Send + Sync>),
+}
+
+impl fmt::Debug for AutoplayPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoplayPosition::Key(key) => f.debug_tuple("Key").field(key).finish(),
+            AutoplayPosition::Screen(p) => f.debug_tuple("Screen").field(p).finish(),
+            AutoplayPosition::Tracked(_) => f.write_str("Tracked(..)"),
+        }
+    }
+}
+
+impl AutoplayPosition {
+    fn at(&self, song_time: f64) -> Vec2 {
+        match self {
+            AutoplayPosition::Key(_) => Vec2::ZERO,
+            AutoplayPosition::Screen(p) => *p,
+            AutoplayPosition::Tracked(f) => f(song_time),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AutoplayTarget {
+    /// 歌曲时间（秒）
+    pub time: f64,
+    pub position: AutoplayPosition,
+    /// 按住的时长（秒），0 表示普通点击
+    pub hold: f64,
+    /// 按下后划动
+    pub flick: bool,
+}
+
+impl AutoplayTarget {
+    pub fn new(time: f64, position: AutoplayPosition) -> Self {
+        Self {
+            time,
+            position,
+            hold: 0.0,
+            flick: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoplayConfig {
+    /// 按下时间的标准差（秒），0 为完美
+    pub timing_noise: f64,
+    /// 触点位置的标准差（像素）
+    pub position_noise: f32,
+    /// 普通点击按住多久
+    pub tap_duration: f64,
+    /// 长条按住时移动事件的间隔
+    pub move_interval: f64,
+    /// flick 划动的距离（像素）
+    pub flick_distance: f32,
+    /// 同样的种子生成同样的事件
+    pub seed: u64,
+}
+
+impl Default for AutoplayConfig {
+    fn default() -> Self {
+        Self {
+            timing_noise: 0.0,
+            position_noise: 0.0,
+            tap_duration: 0.04,
+            move_interval: 1.0 / 60.0,
+            flick_distance: 80.0,
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl AutoplayConfig {
+    /// 带有人类误差的设置
+    pub fn humanized() -> Self {
+        Self {
+            timing_noise: 0.012,
+            position_noise: 6.0,
+            ..Default::default()
+        }
+    }
+}
+
+// xorshift64*，不依赖外部随机数库并且结果可复现
+struct AutoplayRng(u64);
+
+impl AutoplayRng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // 截断在 3 倍标准差内的正态分布
+    fn normal(&mut self, std_dev: f64) -> f64 {
+        if std_dev <= 0.0 {
+            return 0.0;
+        }
+
+        let u1 = self.next_f64().max(f64::MIN_POSITIVE);
+        let u2 = self.next_f64();
+        let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+
+        z.clamp(-3.0, 3.0) * std_dev
+    }
+}
+
+/// 同一个按键松开和下一次按下之间的最小间隔
+const KEY_RELEASE_GAP: f64 = 0.001;
+
+/// 把目标展开成按时间排序的输入事件
+pub fn generate_autoplay_events(targets: &[AutoplayTarget], config: &AutoplayConfig) -> Vec<ReplayEvent> {
+    let mut rng = AutoplayRng(config.seed.max(1));
+    let mut events = Vec::new();
+
+    // 按键目标先按键收集，加完误差后再排序，避免松开被挪到自己的按下之前
+    let mut key_presses: Vec<(KeyCode, Vec<(f64, f64)>)> = Vec::new();
+
+    for (i, target) in targets.iter().enumerate() {
+        let press = target.time + rng.normal(config.timing_noise);
+        let release = press + target.hold.max(config.tap_duration);
+
+        match &target.position {
+            AutoplayPosition::Key(key) => {
+                match key_presses.iter_mut().find(|(k, _)| k == key) {
+                    Some((_, presses)) => presses.push((press, release)),
+                    None => key_presses.push((*key, vec![(press, release)])),
+                }
+            }
+            position => {
+                let id = i as u64;
+                // 手指落点的误差在一次按压内保持不变
+                let jitter = vec2(
+                    rng.normal(config.position_noise as f64) as f32,
+                    rng.normal(config.position_noise as f64) as f32,
+                );
+                let at = |time: f64| position.at(time) + jitter;
+
+                events.push(ReplayEvent {
+                    song_time: press,
+                    kind: InputEventKind::TouchBegan { id, position: at(press) },
+                });
+
+                // 长条期间跟随目标移动
+                let mut time = press + config.move_interval;
+                while target.hold > 0.0 && time < release {
+                    events.push(ReplayEvent {
+                        song_time: time,
+                        kind: InputEventKind::TouchMoved { id, position: at(time) },
+                    });
+                    time += config.move_interval;
+                }
+
+                let mut end = at(release);
+
+                if target.flick {
+                    // 按下后很快向上划出
+                    let flick_time = press + config.tap_duration / 2.0;
+                    end = at(flick_time) - vec2(0.0, config.flick_distance);
+
+                    events.push(ReplayEvent {
+                        song_time: flick_time,
+                        kind: InputEventKind::TouchMoved { id, position: end },
+                    });
+                }
+
+                events.push(ReplayEvent {
+                    song_time: release,
+                    kind: InputEventKind::TouchEnded { id, position: end },
+                });
+            }
+        }
+    }
+
+    for (key, mut presses) in key_presses {
+        presses.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(presses.len());
+        for (press, release) in presses {
+            let Some(previous) = merged.last_mut() else {
+                merged.push((press, release));
+                continue;
+            };
+
+            if press < previous.0 + 2.0 * KEY_RELEASE_GAP {
+                // 两次按下之间放不下一次松开，合并成一次按压
+                previous.1 = previous.1.max(release);
+                continue;
+            }
+
+            // 上一次松开要在这次按下之前，但不能早于上一次按下
+            previous.1 = previous.1.min(press - KEY_RELEASE_GAP).max(previous.0 + KEY_RELEASE_GAP);
+            merged.push((press, release));
+        }
+
+        for (press, release) in merged {
+            events.push(ReplayEvent {
+                song_time: press,
+                kind: InputEventKind::KeyDown(key),
+            });
+            events.push(ReplayEvent {
+                song_time: release,
+                kind: InputEventKind::KeyUp(key),
+            });
+        }
+    }
+
+    events.sort_by(|a, b| a.song_time.total_cmp(&b.song_time));
+    events
+}
+
+/// 按轨道下标对应按键生成目标，drag 和点击一样处理，flick 使用按键时无法划动
+pub fn autoplay_targets_for_keys(chart: &Chart, keys: &[KeyCode]) -> Vec<AutoplayTarget> {
+    chart
+        .notes
+        .iter()
+        .filter_map(|note| {
+            let key = *keys.get(note.lane as usize)?;
+
+            Some(AutoplayTarget {
+                hold: note.end_time() - note.time,
+                ..AutoplayTarget::new(note.time, AutoplayPosition::Key(key))
+            })
+        })
+        .collect()
+}
+
+impl PhigrosChart {
+    /// 生成跟随判定线移动的触摸目标，位置按 `layout` 换算成窗口坐标
+    pub fn autoplay_targets(self: &Arc<Self>, layout: PhigrosLayout) -> Vec<AutoplayTarget> {
+        let mut targets = Vec::new();
+
+        for (line_index, line) in self.judge_line_list.iter().enumerate() {
+            for (_, note) in line.notes() {
+                let chart = self.clone();
+                let position_x = note.position_x;
+
+                let tracked = move |song_time: f64| {
+                    let line = &chart.judge_line_list[line_index];
+                    let state = line.state_at(song_time - chart.offset);
+                    let world = layout.note_position(&state, NoteSide::Above, position_x, 0.0);
+
+                    // 世界坐标原点在屏幕中心且 y 向上，窗口坐标原点在左上角
+                    vec2(
+                        world.x + layout.screen_size.x / 2.0,
+                        layout.screen_size.y / 2.0 - world.y,
+                    )
+                };
+
+                let time = line.time_to_seconds(note.time) + self.offset;
+
+                targets.push(AutoplayTarget {
+                    time,
+                    position: AutoplayPosition::Tracked(Arc::new(tracked)),
+                    hold: if note.note_type == 3 {
+                        line.time_to_seconds(note.hold_time)
+                    } else {
+                        0.0
+                    },
+                    flick: note.note_type == 4,
+                });
+            }
+        }
+
+        targets.sort_by(|a, b| a.time.total_cmp(&b.time));
+        targets
+    }
+}
+
+struct AutoplayDriver {
+    enabled: bool,
+    config: AutoplayConfig,
+    player: Option<ReplayPlayer>,
+}
+
+static AUTOPLAY: Lazy<Mutex<AutoplayDriver>> = Lazy::new(|| {
+    Mutex::new(AutoplayDriver {
+        enabled: false,
+        config: AutoplayConfig::default(),
+        player: None,
+    })
+});
+
+/// 由 `init_game` 根据 `InitGameConfig::autoplay` 调用，也可以在运行时切换
+pub fn set_autoplay(config: Option<AutoplayConfig>) {
+    let mut autoplay = AUTOPLAY.lock();
+
+    autoplay.enabled = config.is_some();
+    if let Some(config) = config {
+        autoplay.config = config;
+    }
+}
+
+pub fn is_autoplay_enabled() -> bool {
+    AUTOPLAY.lock().enabled
+}
+
+/// 设置当前谱面的目标，通常在开始游玩时调用，从当前歌曲时间开始生效
+pub fn set_autoplay_targets(targets: &[AutoplayTarget]) {
+    let mut autoplay = AUTOPLAY.lock();
+
+    let events = generate_autoplay_events(targets, &autoplay.config);
+    let mut player = ReplayPlayer::new(Replay {
+        chart_hash: 0,
+        settings: ReplaySettings::default(),
+        events,
+    });
+    player.seek(get_song_time());
+
+    autoplay.player = Some(player);
+}
+
+pub fn clear_autoplay_targets() {
+    AUTOPLAY.lock().player = None;
+}
+
+/// 由 winit 线程每次循环调用，注入到时的自动游玩事件
+pub fn pump_autoplay() {
+    let mut autoplay = AUTOPLAY.lock();
+
+    if !autoplay.enabled {
+        return;
+    }
+
+    let Some(player) = autoplay.player.as_mut() else {
+        return;
+    };
+
+    let song_time = game_time_to_song_time(get_precise_time());
+    let mut input = get_input().write();
+
+    for event in player.events_until(song_time) {
+        input.inject_event(InputEvent {
+            time: song_time_to_game_time(event.song_time),
+            kind: event.kind,
+        });
+    }
+}
+
+#[cfg(test)]
+fn autoplay_test_chart() -> Chart {
+    Chart::new(
+        ChartMetadata::default(),
+        TimingMap::constant(0.0, 120.0).unwrap(),
+        2,
+        vec![
+            Note::new(1.0, NoteKind::Tap, 0),
+            Note::new(1.02, NoteKind::Tap, 0),
+            Note::new(2.0, NoteKind::Hold { end_time: 3.0 }, 1),
+        ],
+    )
+}
+
+#[test]
+fn autoplay_perfect_keys() {
+    let chart = autoplay_test_chart();
+    let keys = [KeyCode::KeyF, KeyCode::KeyJ];
+
+    let events = generate_autoplay_events(
+        &autoplay_targets_for_keys(&chart, &keys),
+        &AutoplayConfig::default(),
+    );
+
+    // 连续的同键点击：第一次松开被提前到第二次按下之前
+    assert_eq!(events[0].kind, InputEventKind::KeyDown(KeyCode::KeyF));
+    assert_eq!(events[1].kind, InputEventKind::KeyUp(KeyCode::KeyF));
+    assert!(events[1].song_time < 1.02);
+
+    let mut input = InputState::default();
+    for event in &events {
+        input.inject_event(InputEvent {
+            time: event.song_time,
+            kind: event.kind,
+        });
+    }
+    input.begin_frame();
+
+    let mut engine = JudgementEngine::new(&chart, JudgementConfig::default());
+    for event in &input.frame().events {
+        if let Some(input) = JudgeInput::from_event(event, event.time, &keys, |_| None) {
+            engine.handle_input(&input);
+        }
+    }
+    engine.update(10.0);
+
+    assert_eq!(engine.stats().perfect, 3);
+    assert_eq!(engine.score(), 1_000_000);
+}
+
+#[test]
+fn autoplay_humanized_touches_follow_target() {
+    let targets = vec![
+        AutoplayTarget {
+            hold: 0.5,
+            ..AutoplayTarget::new(
+                1.0,
+                AutoplayPosition::Tracked(Arc::new(|t| vec2(100.0 * t as f32, 50.0))),
+            )
+        },
+        AutoplayTarget {
+            flick: true,
+            ..AutoplayTarget::new(2.0, AutoplayPosition::Screen(vec2(10.0, 10.0)))
+        },
+    ];
+
+    let config = AutoplayConfig::humanized();
+    let events = generate_autoplay_events(&targets, &config);
+
+    // 同样的种子得到同样的事件
+    assert_eq!(events, generate_autoplay_events(&targets, &config));
+
+    let InputEventKind::TouchBegan { position: start, .. } = events[0].kind else {
+        panic!("expected touch began, got {:?}", events[0].kind);
+    };
+    assert!((events[0].song_time - 1.0).abs() <= 3.0 * config.timing_noise);
+    assert!((start - vec2(100.0 * events[0].song_time as f32, 50.0)).length() < 40.0);
+
+    // 按住期间持续移动，最后在目标新的位置松开
+    let moves = events
+        .iter()
+        .filter(|e| matches!(e.kind, InputEventKind::TouchMoved { id: 0, .. }))
+        .count();
+    assert!(moves >= 25);
+
+    let flick = events
+        .iter()
+        .find(|e| matches!(e.kind, InputEventKind::TouchMoved { id: 1, .. }))
+        .unwrap();
+    let InputEventKind::TouchMoved { position, .. } = flick.kind else {
+        unreachable!()
+    };
+    assert!(position.y < 10.0 - config.flick_distance + 40.0);
+}
+
+#[test]
+fn autoplay_same_key_notes_closer_than_release_gap() {
+    let targets = vec![
+        AutoplayTarget::new(1.0, AutoplayPosition::Key(KeyCode::KeyF)),
+        AutoplayTarget::new(1.0005, AutoplayPosition::Key(KeyCode::KeyF)),
+        AutoplayTarget::new(1.5, AutoplayPosition::Key(KeyCode::KeyF)),
+    ];
+
+    let events = generate_autoplay_events(&targets, &AutoplayConfig::default());
+
+    // 按下和松开交替出现，按键不会一直按住
+    let kinds: Vec<_> = events.iter().map(|e| e.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            InputEventKind::KeyDown(KeyCode::KeyF),
+            InputEventKind::KeyUp(KeyCode::KeyF),
+            InputEventKind::KeyDown(KeyCode::KeyF),
+            InputEventKind::KeyUp(KeyCode::KeyF),
+        ]
+    );
+    assert!(events[1].song_time < 1.5);
+    assert_eq!(events[2].song_time, 1.5);
+
+    // 加了误差后同键的顺序被打乱，每次按下之后仍然先松开
+    let mut config = AutoplayConfig::humanized();
+    config.timing_noise = 0.05;
+    let targets: Vec<_> = (0..64)
+        .map(|i| AutoplayTarget::new(1.0 + i as f64 * 0.01, AutoplayPosition::Key(KeyCode::KeyJ)))
+        .collect();
+
+    let mut held = false;
+    let mut last = f64::NEG_INFINITY;
+    for event in generate_autoplay_events(&targets, &config) {
+        match event.kind {
+            InputEventKind::KeyDown(_) => {
+                assert!(!held && event.song_time > last);
+                held = true;
+            }
+            InputEventKind::KeyUp(_) => {
+                assert!(held && event.song_time > last);
+                held = false;
+            }
+            _ => unreachable!(),
+        }
+        last = event.song_time;
+    }
+    assert!(!held);
+}
@@ -1,93 +1,212 @@
-use std::{collections::HashSet, hash::Hash};
+use std::ops::Range;
 
 use wgpu::{
-    AddressMode, BindingResource, BufferBinding, CommandEncoderDescriptor, IndexFormat, LoadOp,
-    Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    SamplerDescriptor, StoreOp, TextureView, TextureViewDescriptor,
+    CommandEncoderDescriptor, IndexFormat, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
 };
 
 use crate::*;
 
-pub fn run_batched_render_passes(
-    renderer: &mut WgpuRenderer,
-    sample_count: Msaa,
-    sprite_shader_id: ShaderId,
-    error_shader_id: ShaderId,
-) {
-    let queues = consume_render_queues();
+/// 一个 `MeshGroupKey` 对应的一次绘制，顶点和索引都是整帧合并后缓冲区里的范围
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawBatch {
+    pub key: MeshGroupKey,
+    pub pipeline_name: String,
+    pub vertices: Range<u32>,
+    /// 为空时按顶点直接绘制
+    pub indices: Range<u32>,
+}
 
-    for (key, mut meshes) in queues.into_iter()/*.sorted_by_key(|(k, _)| k.z_index) */ {
-        // if get_y_sort(key.z_index) {
-        //     meshes.sort_by_key(|mesh| OrderedFloat::<f32>(-(mesh.origin.y + mesh.y_sort_offset)));
-        // }
-
-        render_meshes(
-            renderer,
-            sample_count,
-            MeshDrawData {
-                blend_mode: key.blend_mode,
-                texture: key.texture_id,
-                shader: key.shader,
-                render_target: key.render_target,
-                data: meshes,
-            },
-            sprite_shader_id,
-            error_shader_id,
-        );
+/// 整帧的顶点和索引，索引已经加上了各自在顶点缓冲区中的偏移
+#[derive(Default)]
+pub struct FrameGeometry {
+    pub vertices: Vec<SpriteVertex>,
+    pub indices: Vec<u32>,
+    pub batches: Vec<DrawBatch>,
+}
+
+impl FrameGeometry {
+    pub fn push(&mut self, key: MeshGroupKey, pipeline_name: String, meshes: Vec<Mesh>) {
+        let vertex_start = self.vertices.len() as u32;
+        let index_start = self.indices.len() as u32;
+
+        for mesh in meshes {
+            let offset = self.vertices.len() as u32;
+            self.vertices.extend(mesh.vertices);
+            self.indices.extend(mesh.indices.iter().map(|idx| idx + offset));
+        }
+
+        let vertices = vertex_start..self.vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.batches.push(DrawBatch {
+            key,
+            pipeline_name,
+            vertices,
+            indices: index_start..self.indices.len() as u32,
+        });
+    }
+}
+
+/// 决定各个渲染目标的绘制顺序。
+///
+/// 被其他目标当作纹理采样的渲染目标排在前面，其余按第一次出现的顺序。
+/// 出现循环依赖时按出现顺序打断。
+pub fn render_target_order(batches: &[DrawBatch]) -> Vec<RenderTargetId> {
+    let targets: Vec<RenderTargetId> = batches
+        .iter()
+        .map(|batch| batch.key.render_target)
+        .unique()
+        .collect();
+
+    fn visit(
+        target: RenderTargetId,
+        batches: &[DrawBatch],
+        targets: &[RenderTargetId],
+        visiting: &mut Vec<RenderTargetId>,
+        order: &mut Vec<RenderTargetId>,
+    ) {
+        if order.contains(&target) || visiting.contains(&target) || !targets.contains(&target) {
+            return;
+        }
+
+        visiting.push(target);
+        for batch in batches.iter().filter(|batch| batch.key.render_target == target) {
+            if let TextureHandle::RenderTarget(dependency) = batch.key.texture_id {
+                visit(dependency, batches, targets, visiting, order);
+            }
+        }
+        visiting.pop();
+
+        order.push(target);
+    }
+
+    let mut order = Vec::with_capacity(targets.len());
+    let mut visiting = Vec::new();
+
+    for &target in &targets {
+        visit(target, batches, &targets, &mut visiting, &mut order);
     }
+
+    order
 }
 
-pub fn render_meshes(
+/// 把本帧的绘制队列录制进同一个 encoder：顶点和索引只上传一次，
+/// 每个渲染目标一个 render pass，pass 内按 `MeshGroupKey` 的顺序切换管线和绑定组。
+pub fn run_batched_render_passes(
     renderer: &mut WgpuRenderer,
     sample_count: Msaa,
-    pass_data: MeshDrawData,
     sprite_shader_id: ShaderId,
     _error_shader_id: ShaderId,
 ) {
-    // 1. 准备管线
-    let pipeline_name =
-        ensure_pipeline_exists(renderer, &pass_data, sprite_shader_id, sample_count.into());
-
-    // 2. 合并所有顶点和索引
-    let mut all_vertices = Vec::<SpriteVertex>::new();
-    let mut all_indices = Vec::<u32>::new();
-    for mesh in pass_data.data {
-        let offset = all_vertices.len() as u32;
-        all_vertices.extend(mesh.vertices);
-        all_indices.extend(mesh.indices.iter().map(|idx| idx + offset));
+    let queues = consume_render_queues();
+    if queues.is_empty() {
+        return;
+    }
+
+    // 1. 准备管线并合并整帧的顶点 / 索引，y-sort 的层可能拆成多个批次
+    let mut geometry = FrameGeometry::default();
+
+    for (key, meshes) in y_sort_groups(queues, get_y_sort) {
+        let pass_data = MeshDrawData {
+            blend_mode: key.blend_mode,
+            texture: key.texture_id,
+            shader: key.shader,
+            render_target: key.render_target,
+            data: meshes,
+        };
+
+        let pipeline_name =
+            ensure_pipeline_exists(renderer, &pass_data, sprite_shader_id, sample_count.into());
+
+        geometry.push(key, pipeline_name, pass_data.data);
+    }
+
+    if geometry.batches.is_empty() {
+        return;
     }
 
-    // 3. 上传顶点 / 索引
+    // 2. 一次性上传
     renderer.vertex_buffer.ensure_size_and_copy(
         &renderer.context.device,
         &renderer.context.queue,
-        bytemuck::cast_slice(&all_vertices),
+        bytemuck::cast_slice(&geometry.vertices),
     );
     renderer.index_buffer.ensure_size_and_copy(
         &renderer.context.device,
         &renderer.context.queue,
-        bytemuck::cast_slice(&all_indices),
+        bytemuck::cast_slice(&geometry.indices),
     );
 
-    // 4. 选择渲染目标
-    let rts = get_global_render_targets().read();
-    let rt = &rts.get(&pass_data.render_target).unwrap().read();
+    // 3. 每个着色器实例的 uniform 绑定组，复用上一帧同一个实例的缓冲区，只写入变化的值
+    {
+        let shaders = renderer.shaders.lock();
+        let used: Vec<(ShaderInstanceId, &String)> = geometry
+            .batches
+            .iter()
+            .filter(|batch| batch.key.shader.0 > 0)
+            .map(|batch| (batch.key.shader, &batch.pipeline_name))
+            .unique_by(|(shader, _)| *shader)
+            .collect();
 
-    let (color_view, depth_view, resolve_target) = if sample_count != Msaa::Off {
-        (&rt.msaa_view, &rt.msaa_depth_view, Some(&rt.resolve_view))
-    } else {
-        (&rt.resolve_view, &rt.msaa_depth_view, None)
-    };
+        renderer
+            .user_bind_groups
+            .retain(|id, _| used.iter().any(|(shader, _)| shader == id));
+
+        for (shader, pipeline_name) in used {
+            let Some(pipeline) = renderer.user_pipelines.get(pipeline_name) else {
+                continue;
+            };
+            let instance = get_shader_instance(shader);
+            let Some(shader_def) = shaders.get(instance.id) else {
+                continue;
+            };
+
+            match renderer.user_bind_groups.get_mut(&shader) {
+                Some(cached) if cached.matches(instance.id, pipeline_name) => {
+                    cached.update(&renderer.context, shader_def, &instance);
+                }
+                _ => {
+                    let bind_group = create_user_bind_group(
+                        &renderer.context,
+                        pipeline_name,
+                        pipeline,
+                        shader_def,
+                        &instance,
+                    );
+                    renderer.user_bind_groups.insert(shader, bind_group);
+                }
+            }
+        }
+    }
+
+    // 4. 锁住本帧用到的渲染目标和纹理
+    let rts = get_global_render_targets().read();
+    let rt_guards: HashMap<RenderTargetId, _> = rts.iter().map(|(id, rt)| (*id, rt.read())).collect();
+    let textures = renderer.textures.lock();
 
-    // 5. 创建 encoder & render pass
     let mut encoder = renderer
         .context
         .device
         .create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("Mesh Render Encoder"),
+            label: Some("Frame Encoder"),
         });
 
-    {
+    // 5. 每个渲染目标一个 pass
+    for target in render_target_order(&geometry.batches) {
+        let Some(rt) = rt_guards.get(&target) else {
+            warn!("Render target {:?} no longer exists, skipping its draws", target);
+            continue;
+        };
+
+        let (color_view, resolve_target) = if sample_count != Msaa::Off {
+            (&rt.msaa_view, Some(&rt.resolve_view))
+        } else {
+            (&rt.resolve_view, None)
+        };
+
         let mut rp = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Mesh Render Pass"),
             color_attachments: &[Some(RenderPassColorAttachment {
@@ -98,77 +217,256 @@ pub fn render_meshes(
                     store: StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                view: depth_view, // 同样用 MSAA 深度
-                depth_ops: Some(Operations {
-                    load: LoadOp::Clear(1.0),
-                    store: StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
+            depth_stencil_attachment: if renderer.enable_z_buffer {
+                Some(RenderPassDepthStencilAttachment {
+                    view: &rt.msaa_depth_view,
+                    // 每帧每个 RT 只清一次，跨分组的遮挡由 z_index 写入的深度决定
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(1.0),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                })
+            } else {
+                None
+            },
             ..Default::default()
         });
 
-        // 6. 设置管线与绑定组
-        let mesh_pipeline = renderer
-            .user_pipelines
-            .get(&pipeline_name)
-            .map(RenderPipeline::User)
-            .or_else(|| {
-                renderer
-                    .pipelines
-                    .get(&pipeline_name)
-                    .map(RenderPipeline::Wgpu)
-            })
-            .expect("pipeline ensured");
-
-        match &mesh_pipeline {
-            RenderPipeline::User(p) => rp.set_pipeline(&p.pipeline),
-            RenderPipeline::Wgpu(p) => rp.set_pipeline(p),
-        }
-
         rp.set_vertex_buffer(0, renderer.vertex_buffer.buffer.slice(..));
-        if !all_indices.is_empty() {
+        if !geometry.indices.is_empty() {
             rp.set_index_buffer(renderer.index_buffer.buffer.slice(..), IndexFormat::Uint32);
         }
+        rp.set_bind_group(1, renderer.camera_bind_group.as_ref(), &[]);
+
+        let mut current_pipeline: Option<&str> = None;
+
+        for batch in geometry.batches.iter().filter(|b| b.key.render_target == target) {
+            // 6. 管线相同的相邻批次不重复设置
+            let mesh_pipeline = renderer
+                .user_pipelines
+                .get(&batch.pipeline_name)
+                .map(RenderPipeline::User)
+                .or_else(|| {
+                    renderer
+                        .pipelines
+                        .get(&batch.pipeline_name)
+                        .map(RenderPipeline::Wgpu)
+                })
+                .expect("pipeline ensured");
+
+            if current_pipeline != Some(batch.pipeline_name.as_str()) {
+                match &mesh_pipeline {
+                    RenderPipeline::User(p) => rp.set_pipeline(&p.pipeline),
+                    RenderPipeline::Wgpu(p) => rp.set_pipeline(p),
+                }
 
-        // 7. 纹理绑定
-        let textures = renderer.textures.lock();
-        let tex_bind_group = match pass_data.texture {
-            TextureHandle::RenderTarget(rt_id) => {
-                &if let Some(rt) = rts.get(&rt_id) {
-                    rt.read()
-                } else if let Some(rt) = rts.get(&RenderTargetId(0)) {
-                    rt.read()
-                } else {
-                    panic!("No Default RendererTarget");
-                }.blit_bind_group
+                current_pipeline = Some(&batch.pipeline_name);
             }
-            TextureHandle::Path(id) | TextureHandle::Raw(id) => {
-                &textures
-                    .get(&pass_data.texture)
-                    .unwrap_or_else(|| textures.get(&texture_id("error")).unwrap())
-                    .bind_group
+
+            if let RenderPipeline::User(p) = &mesh_pipeline {
+                let bind_group = renderer
+                    .user_bind_groups
+                    .get(&batch.key.shader)
+                    .map_or(&p.bind_group, |cached| &cached.bind_group);
+                rp.set_bind_group(2, bind_group, &[]);
             }
-        };
-        
-        rp.set_bind_group(0, tex_bind_group, &[]);
-        rp.set_bind_group(1, renderer.camera_bind_group.as_ref(), &[]);
 
-        if let RenderPipeline::User(p) = &mesh_pipeline {
-            rp.set_bind_group(2, &p.bind_group, &[]);
-        }
+            // 7. 纹理绑定
+            let tex_bind_group = match batch.key.texture_id {
+                TextureHandle::RenderTarget(rt_id) => {
+                    &rt_guards
+                        .get(&rt_id)
+                        .unwrap_or_else(|| &rt_guards[&RenderTargetId(0)])
+                        .blit_bind_group
+                }
+                TextureHandle::Path(_) | TextureHandle::Raw(_) => {
+                    // 纹理已被卸载时退回到 "error" 纹理，没有加载它时用 "1px"
+                    &textures
+                        .get(&batch.key.texture_id)
+                        .or_else(|| texture_id_safe("error").and_then(|h| textures.get(&h)))
+                        .unwrap_or_else(|| textures.get(&texture_id("1px")).unwrap())
+                        .bind_group
+                }
+            };
 
-        // 8. 绘制
-        if all_indices.is_empty() {
-            rp.draw(0..all_vertices.len() as u32, 0..1);
-        } else {
-            rp.draw_indexed(0..all_indices.len() as u32, 0, 0..1);
+            rp.set_bind_group(0, tex_bind_group, &[]);
+
+            // 8. 绘制
+            if batch.indices.is_empty() {
+                rp.draw(batch.vertices.clone(), 0..1);
+            } else {
+                rp.draw_indexed(batch.indices.clone(), 0, 0..1);
+            }
         }
     }
 
+    drop(textures);
+    drop(rt_guards);
+
     renderer
         .context
         .queue
         .submit(std::iter::once(encoder.finish()));
 }
+
+#[cfg(test)]
+fn test_batch(render_target: u32, texture_id: TextureHandle) -> DrawBatch {
+    DrawBatch {
+        key: MeshGroupKey {
+            z_index: 0,
+            blend_mode: BlendMode::Alpha,
+            texture_id,
+            shader: ShaderInstanceId::default(),
+            render_target: RenderTargetId(render_target),
+        },
+        pipeline_name: String::new(),
+        vertices: 0..3,
+        indices: 0..0,
+    }
+}
+
+#[test]
+fn frame_geometry_offsets_indices() {
+    let mesh = |n: usize| Mesh {
+        vertices: smallvec![SpriteVertex::new(Vec3::ZERO, Vec2::ZERO, WHITE); n],
+        indices: (0..n as u32).collect(),
+        ..Default::default()
+    };
+
+    let key = test_batch(0, TextureHandle::Raw(0)).key;
+    let mut geometry = FrameGeometry::default();
+    geometry.push(key, "a".to_owned(), vec![mesh(3), mesh(4)]);
+    geometry.push(key, "b".to_owned(), vec![]);
+    geometry.push(key, "c".to_owned(), vec![mesh(3)]);
+
+    assert_eq!(geometry.vertices.len(), 10);
+    assert_eq!(geometry.indices, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    // 空的分组不产生批次
+    assert_eq!(
+        geometry
+            .batches
+            .iter()
+            .map(|b| (b.pipeline_name.as_str(), b.vertices.clone(), b.indices.clone()))
+            .collect::<Vec<_>>(),
+        vec![("a", 0..7, 0..7), ("c", 7..10, 7..10)]
+    );
+}
+
+#[test]
+fn render_targets_sampled_by_others_are_drawn_first() {
+    let rt = |id| TextureHandle::RenderTarget(RenderTargetId(id));
+
+    let batches = [
+        test_batch(0, TextureHandle::Raw(1)),
+        test_batch(0, rt(2)),
+        test_batch(1, TextureHandle::Raw(1)),
+        test_batch(2, rt(3)),
+        test_batch(3, TextureHandle::Raw(1)),
+    ];
+    let order = render_target_order(&batches);
+    assert_eq!(order, [3, 2, 0, 1].map(RenderTargetId));
+
+    // 循环依赖时每个目标仍然只出现一次
+    let batches = [test_batch(1, rt(2)), test_batch(2, rt(1)), test_batch(2, rt(2))];
+    assert_eq!(render_target_order(&batches), [2, 1].map(RenderTargetId));
+}
+
+#[test]
+fn z_index_orders_overlaps_across_groups() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    // 只用 0 / 1 的分量，sRGB 编码前后一致
+    let [red, green, blue, yellow] = [
+        Color::new(1.0, 0.0, 0.0, 1.0),
+        Color::new(0.0, 1.0, 0.0, 1.0),
+        Color::new(0.0, 0.0, 1.0, 1.0),
+        Color::new(1.0, 1.0, 0.0, 1.0),
+    ];
+
+    let quad = |x: f32, y: f32, size: u32, color: Color, z_index: i32, blend_mode: BlendMode| {
+        draw_quad(RawDrawParams {
+            position: vec3(x, y, 0.0),
+            dest_size: Some(uvec2(size, size)),
+            color,
+            z_index,
+            blend_mode,
+            ..Default::default()
+        });
+    };
+
+    for enable_z_buffer in [true, false] {
+        let Some(image) = render_scene(Msaa::Off, || {
+            set_z_buffer(enable_z_buffer);
+
+            // 上一帧留下的深度不能挡住这一帧
+            quad(0.0, 0.0, 60, WHITE, 100, BlendMode::None);
+            render_headless_frame();
+
+            // 提交顺序与 z_index 相反，并且分布在不同的混合模式 / 纹理分组里
+            quad(-20.0, 0.0, 20, blue, 3000, BlendMode::Alpha);
+            quad(0.0, 0.0, 40, red, 5, BlendMode::None);
+            draw_circle(vec2(20.0, 0.0), 20.0, green, -1);
+            quad(40.0, 40.0, 10, yellow, -3000, BlendMode::Alpha);
+            // 同一层里后提交的在上面
+            quad(0.0, -30.0, 10, red, 0, BlendMode::None);
+            quad(0.0, -30.0, 10, blue, 0, BlendMode::None);
+        }) else {
+            return;
+        };
+
+        let pixel = |x: i32, y: i32| *image.get_pixel((64 + x) as u32, (64 - y) as u32);
+        let rgba = |c: Color| image::Rgba::<u8>(c.into());
+        let message = format!("enable_z_buffer = {enable_z_buffer}");
+
+        assert_eq!(pixel(-20, 0), rgba(blue), "{message}");
+        assert_eq!(pixel(5, 0), rgba(red), "{message}");
+        assert_eq!(pixel(30, 0), rgba(green), "{message}");
+        // 超出旧的 ±1000 范围的 z_index 不再被裁掉
+        assert_eq!(pixel(40, 40), rgba(yellow), "{message}");
+        assert_eq!(pixel(0, -30), rgba(blue), "{message}");
+    }
+
+    set_z_buffer(true);
+}
+
+#[test]
+fn user_shader_uniforms_update_between_frames() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let tint = |color: Color| Uniform::Vec4([color.r, color.g, color.b, color.a].map(OrderedFloat));
+    let quad = || {
+        draw_quad(RawDrawParams {
+            dest_size: Some(uvec2(16, 16)),
+            ..Default::default()
+        });
+    };
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        let shader = create_shader(
+            "golden-tint",
+            "var<uniform> tint: vec4;\n\
+             @fragment\n\
+             fn fs_main(input: FragmentInput) -> @location(0) vec4<f32> { return tint; }",
+        )
+        .unwrap();
+
+        use_shader(shader);
+        set_uniform("tint", tint(RED));
+        quad();
+        use_default_shader();
+        render_headless_frame();
+
+        // 第二帧复用同一个实例的绑定组，只更新 uniform 的值
+        use_shader(shader);
+        set_uniform("tint", tint(GREEN));
+        quad();
+    }) else {
+        return;
+    };
+
+    assert_eq!(get_global_wgpu().read().user_bind_groups.len(), 1);
+
+    let pixel = image.get_pixel(64, 64);
+    assert!(pixel[1] > 200 && pixel[0] < 50, "{:?}", pixel);
+}
@@ -0,0 +1,330 @@
+//! 音频与输入延迟校准。
+//!
+//! [`CalibrationScene`] 按固定 BPM 闪烁节拍标记（有音频输出时同时播放节拍声），
+//! 收集玩家跟拍的按下时间，剔除离群值后算出平均偏移，写入 `time.rs` 的全局偏移。
+
+use crate::*;
+
+const CLICK_SOUND: &str = "calibration_click";
+
+/// 提前多久把节拍声预约给混音器，需要大于一帧
+const CLICK_LOOKAHEAD: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationStats {
+    /// 平均偏移（秒），正值表示按下比节拍晚
+    pub mean: f64,
+    pub std_dev: f64,
+    pub used: usize,
+    pub rejected: usize,
+}
+
+/// 用中位数绝对偏差剔除离群值后计算平均偏移和标准差。
+///
+/// 与中位数相差超过 `k` 倍（换算成标准差的）MAD 的样本被剔除，
+/// 有效样本少于 3 个时返回 `None`。
+pub fn calibrate_offsets(offsets: &[f64], k: f64) -> Option<CalibrationStats> {
+    fn median(sorted: &[f64]) -> f64 {
+        let mid = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        }
+    }
+
+    let mut sorted: Vec<f64> = offsets.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.len() < 3 {
+        return None;
+    }
+    sorted.sort_by(f64::total_cmp);
+
+    let center = median(&sorted);
+
+    let mut deviations: Vec<f64> = sorted.iter().map(|v| (v - center).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+
+    // 1.4826 把 MAD 换算为正态分布的标准差；样本几乎相同时保留 5ms 的下限
+    let threshold = (k * 1.4826 * median(&deviations)).max(0.005);
+
+    let kept: Vec<f64> = sorted
+        .iter()
+        .copied()
+        .filter(|v| (v - center).abs() <= threshold)
+        .collect();
+
+    if kept.len() < 3 {
+        return None;
+    }
+
+    let mean = kept.iter().sum::<f64>() / kept.len() as f64;
+    let variance = kept.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / kept.len() as f64;
+
+    Some(CalibrationStats {
+        mean,
+        std_dev: variance.sqrt(),
+        used: kept.len(),
+        rejected: offsets.len() - kept.len(),
+    })
+}
+
+/// 生成一个短促的节拍声
+fn click_sound(sample_rate: u32) -> SoundData {
+    let length = (sample_rate as f64 * 0.03) as usize;
+
+    let frames = (0..length)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let v = ((std::f64::consts::TAU * 1500.0 * t).sin() * (-t * 150.0).exp() * 0.8) as f32;
+            [v, v]
+        })
+        .collect();
+
+    SoundData {
+        sample_rate,
+        frames,
+    }
+}
+
+/// 校准场景，在 `GameLoop::update` 中每帧调用 `update` 和 `draw`
+#[derive(Clone, Debug)]
+pub struct CalibrationScene {
+    pub bpm: f64,
+    /// 收集多少次按下后结束
+    pub taps_required: usize,
+    /// 离群值剔除的 MAD 倍数
+    pub rejection: f64,
+    pub marker_size: UVec2,
+    pub z_index: i32,
+
+    // 第 0 拍在帧计时器上的时间
+    start_time: Option<f64>,
+    // 最后一个已经预约节拍声的拍子
+    last_click_beat: Option<i64>,
+    offsets: Vec<f64>,
+}
+
+impl CalibrationScene {
+    pub fn new(bpm: f64, taps_required: usize) -> Self {
+        Self {
+            bpm,
+            taps_required,
+            rejection: 3.0,
+            marker_size: uvec2(160, 160),
+            z_index: 0,
+            start_time: None,
+            last_click_beat: None,
+            offsets: Vec::new(),
+        }
+    }
+
+    pub fn beat_interval(&self) -> f64 {
+        60.0 / self.bpm
+    }
+
+    /// 从下一拍开始计时，并清空之前的记录
+    pub fn start(&mut self) {
+        self.start_at(get_precise_time() + self.beat_interval());
+
+        if check_audio_init() && get_hit_sounds().read().get(CLICK_SOUND).is_none() {
+            let sample_rate = get_global_mixer().lock().sample_rate();
+            get_hit_sounds()
+                .write()
+                .insert(CLICK_SOUND, click_sound(sample_rate), 2);
+        }
+    }
+
+    /// `start_time` 为第 0 拍在帧计时器上的时间
+    pub fn start_at(&mut self, start_time: f64) {
+        self.start_time = Some(start_time);
+        self.last_click_beat = None;
+        self.offsets.clear();
+    }
+
+    /// 记录一次按下（帧计时器时间），返回相对最近一拍的偏移
+    pub fn add_tap(&mut self, time: f64) -> Option<f64> {
+        let start = self.start_time?;
+        let interval = self.beat_interval();
+        let beat = ((time - start) / interval).round();
+
+        // 第 0 拍之前半拍以上的按下不算
+        if beat < 0.0 || self.is_finished() {
+            return None;
+        }
+
+        let offset = time - (start + beat * interval);
+        self.offsets.push(offset);
+        Some(offset)
+    }
+
+    pub fn offsets(&self) -> &[f64] {
+        &self.offsets
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.offsets.len() >= self.taps_required
+    }
+
+    pub fn stats(&self) -> Option<CalibrationStats> {
+        calibrate_offsets(&self.offsets, self.rejection)
+    }
+
+    /// 把平均偏移写入全局偏移，结果无效时保持原值
+    pub fn apply(&self) -> Option<CalibrationStats> {
+        let stats = self.stats()?;
+        set_global_offset(stats.mean);
+        info!(
+            "Calibrated offset {:.1}ms (±{:.1}ms, {} rejected)",
+            stats.mean * 1000.0,
+            stats.std_dev * 1000.0,
+            stats.rejected
+        );
+        Some(stats)
+    }
+
+    /// 取出 `now` 之后 `CLICK_LOOKAHEAD` 内还没有预约的拍子时间，已经过去的拍子直接跳过
+    fn due_clicks(&mut self, now: f64) -> Vec<f64> {
+        let Some(start) = self.start_time else {
+            return Vec::new();
+        };
+
+        let interval = self.beat_interval();
+        let mut beat = self
+            .last_click_beat
+            .map_or(0, |last| last + 1)
+            .max(((now - start) / interval).ceil() as i64);
+
+        let mut clicks = Vec::new();
+        while start + beat as f64 * interval <= now + CLICK_LOOKAHEAD {
+            clicks.push(start + beat as f64 * interval);
+            self.last_click_beat = Some(beat);
+            beat += 1;
+        }
+
+        clicks
+    }
+
+    /// 预约节拍声并收集本帧的按下
+    pub fn update(&mut self) {
+        // 节拍声按拍子的准确时间预约，不受帧率影响
+        for click in self.due_clicks(get_precise_time()) {
+            if check_audio_init() && !self.is_finished() {
+                schedule_hit_sound(CLICK_SOUND, click, PlaySoundParams::default());
+            }
+        }
+
+        for event in input_events() {
+            if matches!(
+                event.kind,
+                InputEventKind::KeyDown(_)
+                    | InputEventKind::MouseDown(..)
+                    | InputEventKind::TouchBegan { .. }
+            ) {
+                self.add_tap(event.time);
+            }
+        }
+    }
+
+    /// 在屏幕中心绘制闪烁的节拍标记，下方是进度条
+    pub fn draw(&self) {
+        let Some(start) = self.start_time else {
+            return;
+        };
+
+        let beats = (get_time_f64() - start) / self.beat_interval();
+        // 每拍开始时最亮，四分之一拍内淡出
+        let flash = if beats >= 0.0 {
+            (1.0 - beats.fract() * 4.0).clamp(0.0, 1.0) as f32
+        } else {
+            0.0
+        };
+
+        draw_sprite_ex(
+            texture_id("1px"),
+            DrawTextureParams {
+                raw_draw_params: RawDrawParams {
+                    dest_size: Some(self.marker_size),
+                    pivot: Some(vec2(0.5, 0.5)),
+                    color: Color::new(1.0, 1.0, 1.0, 0.15 + 0.85 * flash),
+                    z_index: self.z_index,
+                    blend_mode: BlendMode::Alpha,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        let progress = self.offsets.len() as f32 / self.taps_required.max(1) as f32;
+        let width = self.marker_size.x * 2;
+
+        draw_sprite_ex(
+            texture_id("1px"),
+            DrawTextureParams {
+                raw_draw_params: RawDrawParams {
+                    position: vec3(-(width as f32) / 2.0, -(self.marker_size.y as f32), 0.0),
+                    dest_size: Some(uvec2((width as f32 * progress) as u32, 8)),
+                    pivot: Some(vec2(0.0, 0.5)),
+                    color: GREEN,
+                    z_index: self.z_index,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+}
+
+#[test]
+fn calibration_rejects_outliers() {
+    // 录制的按下偏移（秒）：稳定在 +30ms 左右，其中两次明显失误
+    let offsets = [
+        0.031, 0.027, 0.034, 0.029, 0.030, 0.250, 0.026, 0.033, -0.180, 0.032, 0.028, 0.031,
+    ];
+
+    let stats = calibrate_offsets(&offsets, 3.0).unwrap();
+
+    assert_eq!(stats.rejected, 2);
+    assert_eq!(stats.used, 10);
+    assert!((stats.mean - 0.0301).abs() < 1e-9, "{stats:?}");
+    assert!(stats.std_dev < 0.003, "{stats:?}");
+
+    assert_eq!(calibrate_offsets(&[0.01, 0.02], 3.0), None);
+}
+
+#[test]
+fn calibration_scene_collects_taps() {
+    let mut scene = CalibrationScene::new(120.0, 8);
+    scene.start_at(10.0);
+
+    // 第 0 拍之前太早的按下被忽略
+    assert_eq!(scene.add_tap(9.5), None);
+
+    // 每拍 0.5 秒，按下稳定晚 40ms，第 3 拍抢拍到前一拍附近
+    let taps = [10.04, 10.54, 11.04, 11.27, 12.04, 12.54, 13.04, 13.54, 14.04];
+    for tap in taps {
+        scene.add_tap(tap);
+    }
+
+    assert!(scene.is_finished());
+    assert_eq!(scene.offsets().len(), 8);
+    assert!((scene.offsets()[3] - (-0.23)).abs() < 1e-9);
+
+    let stats = scene.stats().unwrap();
+    assert_eq!(stats.rejected, 1);
+    assert!((stats.mean - 0.04).abs() < 1e-9);
+}
+
+#[test]
+fn calibration_clicks_are_scheduled_on_the_beat() {
+    let mut scene = CalibrationScene::new(120.0, 8);
+    scene.start_at(10.0);
+
+    assert!(scene.due_clicks(9.85).is_empty());
+    assert_eq!(scene.due_clicks(9.95), vec![10.0]);
+    assert!(scene.due_clicks(9.96).is_empty());
+    assert_eq!(scene.due_clicks(10.45), vec![10.5]);
+
+    // 卡顿之后跳过已经过去的拍子
+    assert!(scene.due_clicks(11.3).is_empty());
+    assert_eq!(scene.due_clicks(11.42), vec![11.5]);
+}
@@ -0,0 +1,398 @@
+use crate::*;
+
+use anyhow::{Result, bail};
+
+/// BPM 变化点，以拍为单位定位
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimingPoint {
+    pub beat: f64,
+    pub bpm: f64,
+    /// 拍号的分子，例如 4/4 拍为 4
+    pub beats_per_measure: u32,
+}
+
+impl TimingPoint {
+    pub fn new(beat: f64, bpm: f64) -> Self {
+        Self {
+            beat,
+            bpm,
+            beats_per_measure: 4,
+        }
+    }
+}
+
+/// 拍与时间（秒）之间的换算表
+#[derive(Clone, Debug)]
+pub struct TimingMap {
+    /// 第 0 拍对应的歌曲时间（秒）
+    pub offset: f64,
+    points: Vec<TimingPoint>,
+    // 每个变化点开始的时间，和 points 一一对应
+    start_times: Vec<f64>,
+}
+
+impl TimingMap {
+    pub fn new(offset: f64, mut points: Vec<TimingPoint>) -> Result<Self> {
+        if points.is_empty() {
+            bail!("TimingMap needs at least one timing point");
+        }
+
+        // 0、负数和 NaN 的 BPM 会让换算出来的时间变成 inf/NaN
+        if let Some(point) = points.iter().find(|p| !(p.bpm > 0.0 && p.bpm.is_finite())) {
+            bail!("invalid bpm {} at beat {}", point.bpm, point.beat);
+        }
+
+        points.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+
+        let mut start_times = Vec::with_capacity(points.len());
+        let mut time = offset + points[0].beat * 60.0 / points[0].bpm;
+
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                let prev = &points[i - 1];
+                time += (point.beat - prev.beat) * 60.0 / prev.bpm;
+            }
+            start_times.push(time);
+        }
+
+        Ok(Self {
+            offset,
+            points,
+            start_times,
+        })
+    }
+
+    /// 固定 BPM 的换算表
+    pub fn constant(offset: f64, bpm: f64) -> Result<Self> {
+        Self::new(offset, vec![TimingPoint::new(0.0, bpm)])
+    }
+
+    pub fn points(&self) -> &[TimingPoint] {
+        &self.points
+    }
+
+    // 第一个变化点之前沿用第一个 BPM
+    fn index_at_beat(&self, beat: f64) -> usize {
+        self.points
+            .partition_point(|p| p.beat <= beat)
+            .saturating_sub(1)
+    }
+
+    fn index_at_time(&self, time: f64) -> usize {
+        self.start_times
+            .partition_point(|t| *t <= time)
+            .saturating_sub(1)
+    }
+
+    pub fn beat_to_time(&self, beat: f64) -> f64 {
+        let i = self.index_at_beat(beat);
+        let point = &self.points[i];
+
+        self.start_times[i] + (beat - point.beat) * 60.0 / point.bpm
+    }
+
+    pub fn time_to_beat(&self, time: f64) -> f64 {
+        let i = self.index_at_time(time);
+        let point = &self.points[i];
+
+        point.beat + (time - self.start_times[i]) * point.bpm / 60.0
+    }
+
+    pub fn bpm_at(&self, time: f64) -> f64 {
+        self.points[self.index_at_time(time)].bpm
+    }
+
+    /// 把拍换算成（小节号，小节内的拍），小节按各自的拍号累加
+    pub fn beat_to_measure(&self, beat: f64) -> (u32, f64) {
+        let mut measure = 0;
+        let mut measure_start = self.points[0].beat.min(0.0);
+
+        for (i, point) in self.points.iter().enumerate() {
+            let end = self.points.get(i + 1).map_or(f64::INFINITY, |p| p.beat).min(beat);
+            let length = point.beats_per_measure.max(1) as f64;
+
+            while measure_start + length <= end {
+                measure_start += length;
+                measure += 1;
+            }
+
+            if end >= beat {
+                break;
+            }
+        }
+
+        (measure, beat - measure_start)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteKind {
+    Tap,
+    Hold { end_time: f64 },
+    /// 划过即可判定（也叫 slide）
+    Drag,
+    Flick,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    /// 歌曲时间（秒）
+    pub time: f64,
+    pub kind: NoteKind,
+    pub lane: u32,
+    /// 轨道上的横向位置，按轨道的音游一般为 0
+    pub x: f32,
+}
+
+impl Note {
+    pub fn new(time: f64, kind: NoteKind, lane: u32) -> Self {
+        Self {
+            time,
+            kind,
+            lane,
+            x: 0.0,
+        }
+    }
+
+    pub fn end_time(&self) -> f64 {
+        match self.kind {
+            NoteKind::Hold { end_time } => end_time,
+            _ => self.time,
+        }
+    }
+
+    pub fn is_hold(&self) -> bool {
+        matches!(self.kind, NoteKind::Hold { .. })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ChartMetadata {
+    pub title: String,
+    pub artist: String,
+    pub charter: String,
+    pub difficulty: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Chart {
+    pub metadata: ChartMetadata,
+    pub timing: TimingMap,
+    pub lane_count: u32,
+    /// 按开始时间排序
+    pub notes: Vec<Note>,
+}
+
+impl Chart {
+    pub fn new(metadata: ChartMetadata, timing: TimingMap, lane_count: u32, mut notes: Vec<Note>) -> Self {
+        notes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Self {
+            metadata,
+            timing,
+            lane_count,
+            notes,
+        }
+    }
+
+    /// 以拍为单位添加音符，时间由换算表计算
+    pub fn note_at_beat(&self, beat: f64, kind: NoteKind, lane: u32) -> Note {
+        Note::new(self.timing.beat_to_time(beat), kind, lane)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.notes.iter().map(Note::end_time).fold(0.0, f64::max)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VisibleNote<'a> {
+    /// 在 `Chart::notes` 中的下标
+    pub index: usize,
+    pub note: &'a Note,
+    /// 距离判定的时间（秒），已经过去时为负
+    pub time_until: f64,
+}
+
+/// 找出某个时间窗口内可见的音符
+#[derive(Clone, Debug)]
+pub struct NoteScheduler {
+    /// 提前多久出现（秒）
+    pub look_ahead: f64,
+    /// 过了判定时间后还保留多久（秒）
+    pub look_behind: f64,
+    max_hold_duration: f64,
+}
+
+impl NoteScheduler {
+    pub fn new(chart: &Chart, look_ahead: f64, look_behind: f64) -> Self {
+        let max_hold_duration = chart
+            .notes
+            .iter()
+            .map(|note| note.end_time() - note.time)
+            .fold(0.0, f64::max);
+
+        Self {
+            look_ahead,
+            look_behind,
+            max_hold_duration,
+        }
+    }
+
+    pub fn visible<'a>(
+        &self,
+        chart: &'a Chart,
+        song_time: f64,
+    ) -> impl Iterator<Item = VisibleNote<'a>> + 'a {
+        let window_start = song_time - self.look_behind;
+        let window_end = song_time + self.look_ahead;
+
+        // 长条可能早于窗口开始，所以按最长的长条往前多找一段
+        let first = chart
+            .notes
+            .partition_point(|note| note.time < window_start - self.max_hold_duration);
+
+        chart.notes[first..]
+            .iter()
+            .enumerate()
+            .take_while(move |(_, note)| note.time <= window_end)
+            .filter(move |(_, note)| note.end_time() >= window_start)
+            .map(move |(i, note)| VisibleNote {
+                index: first + i,
+                note,
+                time_until: note.time - song_time,
+            })
+    }
+}
+
+/// 下落式轨道的布局，把音符换算成屏幕坐标
+#[derive(Clone, Copy, Debug)]
+pub struct NoteLayout {
+    pub judge_line_y: f32,
+    pub lane_width: f32,
+    /// 第 0 轨中心的 x 坐标
+    pub first_lane_x: f32,
+    /// 每秒移动的像素
+    pub scroll_speed: f32,
+    pub note_size: UVec2,
+    pub z_index: i32,
+}
+
+impl Default for NoteLayout {
+    fn default() -> Self {
+        Self {
+            judge_line_y: -250.0,
+            lane_width: 120.0,
+            first_lane_x: -180.0,
+            scroll_speed: 800.0,
+            note_size: uvec2(110, 12),
+            z_index: 0,
+        }
+    }
+}
+
+impl NoteLayout {
+    pub fn note_position(&self, note: &Note, song_time: f64) -> Vec2 {
+        vec2(
+            self.first_lane_x + note.lane as f32 * self.lane_width + note.x,
+            self.judge_line_y + (note.time - song_time) as f32 * self.scroll_speed,
+        )
+    }
+
+    /// 用 `draw_sprite_ex` 绘制可见的音符，长条会被拉伸到结束时间
+    pub fn draw_notes(
+        &self,
+        chart: &Chart,
+        scheduler: &NoteScheduler,
+        song_time: f64,
+        texture: TextureHandle,
+    ) {
+        for visible in scheduler.visible(chart, song_time) {
+            let note = visible.note;
+
+            // 已经开始的长条从判定线开始画
+            let start = note.time.max(song_time);
+            let head = self.note_position(&Note { time: start, ..*note }, song_time);
+            let length = (note.end_time() - start) as f32 * self.scroll_speed;
+
+            draw_sprite_ex(
+                texture,
+                DrawTextureParams {
+                    raw_draw_params: RawDrawParams {
+                        position: head.extend(0.0),
+                        dest_size: Some(uvec2(
+                            self.note_size.x,
+                            self.note_size.y + length.max(0.0) as u32,
+                        )),
+                        pivot: Some(vec2(0.5, 0.0)),
+                        z_index: self.z_index,
+                        blend_mode: BlendMode::Alpha,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn timing_map_conversion() {
+    // 0 拍 120BPM，8 拍开始 240BPM，第 0 拍在 1 秒
+    let timing = TimingMap::new(
+        1.0,
+        vec![TimingPoint::new(0.0, 120.0), TimingPoint::new(8.0, 240.0)],
+    )
+    .unwrap();
+
+    assert_eq!(timing.beat_to_time(0.0), 1.0);
+    assert_eq!(timing.beat_to_time(8.0), 5.0);
+    assert_eq!(timing.beat_to_time(12.0), 6.0);
+    assert_eq!(timing.beat_to_time(-2.0), 0.0);
+
+    for beat in [-1.0, 0.0, 3.5, 8.0, 10.25] {
+        assert!((timing.time_to_beat(timing.beat_to_time(beat)) - beat).abs() < 1e-9);
+    }
+
+    assert_eq!(timing.bpm_at(5.5), 240.0);
+    assert_eq!(timing.beat_to_measure(9.0), (2, 1.0));
+}
+
+#[test]
+fn invalid_timing_maps_are_errors() {
+    assert!(TimingMap::new(0.0, vec![]).is_err());
+
+    for bpm in [0.0, -120.0, f64::NAN, f64::INFINITY] {
+        let points = vec![TimingPoint::new(0.0, 120.0), TimingPoint::new(4.0, bpm)];
+        let err = TimingMap::new(0.0, points).unwrap_err();
+        assert!(err.to_string().contains("at beat 4"), "{}", err);
+    }
+}
+
+#[test]
+fn scheduler_window() {
+    let chart = Chart::new(
+        ChartMetadata::default(),
+        TimingMap::constant(0.0, 120.0).unwrap(),
+        4,
+        vec![
+            Note::new(0.5, NoteKind::Tap, 0),
+            Note::new(1.0, NoteKind::Hold { end_time: 4.0 }, 1),
+            Note::new(3.0, NoteKind::Flick, 2),
+            Note::new(5.0, NoteKind::Drag, 3),
+        ],
+    );
+
+    let scheduler = NoteScheduler::new(&chart, 1.0, 0.2);
+
+    // 窗口 [2.8, 4.0]：进行中的长条和 3 秒的 flick
+    let visible: Vec<_> = scheduler.visible(&chart, 3.0).map(|v| v.index).collect();
+    assert_eq!(visible, vec![1, 2]);
+
+    let visible: Vec<_> = scheduler.visible(&chart, 0.0).map(|v| v.index).collect();
+    assert_eq!(visible, vec![0, 1]);
+
+    let layout = NoteLayout::default();
+    let position = layout.note_position(&chart.notes[2], 2.5);
+    assert_eq!(position, vec2(-180.0 + 240.0, -250.0 + 400.0));
+}
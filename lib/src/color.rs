@@ -101,7 +101,7 @@ impl Color {
     /// # Example
     ///
     /// ```
-    /// use macroquad::prelude::*;
+    /// use wgpu_android_lib::Color;
     ///
     /// let pink = Color::new(1.00, 0.43, 0.76, 1.00);
     /// assert_eq!(pink.r, 1.00);
@@ -133,7 +133,7 @@ impl Color {
     /// # Example
     ///
     /// ```
-    /// use macroquad::prelude::*;
+    /// use wgpu_android_lib::Color;
     ///
     /// let light_blue = Color::from_hex(0x3CA7D5);
     /// assert_eq!(light_blue.r, 0.23529412);
@@ -57,6 +57,10 @@ impl From<Msaa> for u32 {
 pub struct InitGameConfig {
     pub version: &'static str,
     pub window_config: WindowConfig,
+    pub audio_config: AudioConfig,
+    pub graphics_config: GraphicsConfig,
+    /// 设置后以自动游玩模式启动，运行时可以用 `set_autoplay` 切换
+    pub autoplay: Option<AutoplayConfig>,
 }
 
 impl Default for InitGameConfig {
@@ -64,16 +68,83 @@ impl Default for InitGameConfig {
         Self {
             version: "New Version",
             window_config: WindowConfig::default(),
+            audio_config: AudioConfig::default(),
+            graphics_config: GraphicsConfig::default(),
+            autoplay: None,
         }
     }
 }
 
+/// 图形后端和适配器的选择。
+///
+/// 设置了 `WGPU_BACKEND`（如 `vulkan,gl`）时只尝试环境变量指定的后端，
+/// `WGPU_POWER_PREF`（`low` / `high` / `none`）覆盖 `power_preference`，
+/// `WGPU_ADAPTER_NAME` 按名称选择适配器。
+#[derive(Debug, Clone)]
+pub struct GraphicsConfig {
+    /// 按优先级依次尝试的后端，每项可以是多个后端的组合
+    pub backends: Vec<Backends>,
+    pub power_preference: PowerPreference,
+    /// 只使用软件适配器（lavapipe、llvmpipe、WARP 等）
+    pub force_fallback_adapter: bool,
+    /// 所有后端都没有硬件适配器时，是否再尝试软件适配器
+    pub allow_software_fallback: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec![
+                Backends::VULKAN,
+                Backends::METAL,
+                Backends::DX12,
+                Backends::GL,
+            ],
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            allow_software_fallback: true,
+        }
+    }
+}
+
+impl GraphicsConfig {
+    /// 应用环境变量覆盖后实际尝试的后端顺序，去掉空项和重复项
+    pub fn backend_priority(&self) -> Vec<Backends> {
+        backend_priority(&self.backends, Backends::from_env())
+    }
+
+    pub fn power_preference(&self) -> PowerPreference {
+        PowerPreference::from_env().unwrap_or(self.power_preference)
+    }
+}
+
+fn backend_priority(backends: &[Backends], env_override: Option<Backends>) -> Vec<Backends> {
+    if let Some(backends) = env_override.filter(|b| !b.is_empty()) {
+        return vec![backends];
+    }
+
+    let mut list: Vec<Backends> = Vec::new();
+    for &backends in backends {
+        if !backends.is_empty() && !list.contains(&backends) {
+            list.push(backends);
+        }
+    }
+
+    if list.is_empty() {
+        list.push(Backends::all());
+    }
+
+    list
+}
+
 #[derive(Debug, Clone)]
 pub struct WindowConfig {
     pub title_name: String,
     pub fullscreen: bool,
     pub resolution: Size,
     pub min_resolution: Option<Size>,
+    /// Linux 上的应用 ID（Wayland 的 app_id、X11 的 WM_CLASS），桌面环境靠它匹配 .desktop 文件，为空时使用标题
+    pub app_id: Option<String>,
 }
 
 impl Default for WindowConfig {
@@ -83,6 +154,7 @@ impl Default for WindowConfig {
             fullscreen: false,
             resolution: Size::Physical(PhysicalSize::new(1280, 720)),
             min_resolution: Some(Size::Physical(PhysicalSize::new(1280, 720))),
+            app_id: None,
         }
     }
 }
@@ -91,6 +163,8 @@ impl Default for WindowConfig {
 pub struct RunTimeContext {
     pub target_frame_rate: Option<u32>,
     pub sample_count: Msaa,
+    /// 关闭后不使用深度缓冲，完全按提交顺序（z_index、再按分组）绘制
+    pub enable_z_buffer: bool,
 
     pub clear_color: Color,
     pub main_camera: Option<Arc<Mutex<dyn camera::Camera>>>,
@@ -101,6 +175,7 @@ impl Default for RunTimeContext {
         Self { 
             target_frame_rate: Some(120),
             sample_count: Msaa::default(),
+            enable_z_buffer: true,
             clear_color: BLACK,
             main_camera: None,
         }
@@ -142,4 +217,73 @@ pub fn set_default_camera() {
 
 pub fn set_target_frame_rate(target_frame_rate: u32) {
     get_run_time_context().write().target_frame_rate = Some(target_frame_rate);
-}
\ No newline at end of file
+}
+
+/// 修改 MSAA 采样数，已创建的 RT 会按新的采样数重建（内容会丢失）。
+///
+/// 设备不支持时退到不超过它的最大可用采样数。
+pub fn set_sample_count(sample_count: Msaa) {
+    get_run_time_context().write().sample_count = sample_count;
+
+    if !check_wgpu_init() {
+        return;
+    }
+
+    let wr = get_global_wgpu().read();
+    clamp_sample_count(&wr.context.capabilities);
+
+    for (id, rt) in get_global_render_targets().read().iter() {
+        let mut rt = rt.write();
+        let size = rt.size;
+
+        rt.update(
+            &wr.context,
+            &wr.texture_layout,
+            &RenderTargetParams {
+                label: format!("RT {}", id.0),
+                size,
+            },
+        );
+    }
+}
+
+/// 切换深度缓冲。
+///
+/// 开启时 z_index 同时写进深度，较大的 z_index 总在前面，3D 旋转的精灵会互相遮挡；
+/// 关闭时是纯 2D 的画家算法，后绘制的覆盖先绘制的。两种模式下跨分组的 z_index 顺序一致。
+pub fn set_z_buffer(enabled: bool) {
+    get_run_time_context().write().enable_z_buffer = enabled;
+
+    if check_wgpu_init() {
+        get_global_wgpu().write().enable_z_buffer = enabled;
+    }
+}
+
+pub(crate) fn clamp_sample_count(capabilities: &GraphicsCapabilities) {
+    let binding = get_run_time_context();
+    let mut ctx = binding.write();
+    let supported = capabilities.clamp_msaa(ctx.sample_count);
+
+    if supported != ctx.sample_count {
+        warn!(
+            "MSAA {:?} is not supported by this device, using {:?}",
+            ctx.sample_count, supported
+        );
+        ctx.sample_count = supported;
+    }
+}
+
+#[test]
+fn backend_priority_honours_env_override() {
+    let backends = [Backends::VULKAN, Backends::empty(), Backends::GL, Backends::VULKAN];
+
+    assert_eq!(
+        backend_priority(&backends, None),
+        vec![Backends::VULKAN, Backends::GL]
+    );
+    assert_eq!(
+        backend_priority(&backends, Some(Backends::from_comma_list("gl,metal"))),
+        vec![Backends::GL | Backends::METAL]
+    );
+    assert_eq!(backend_priority(&[], None), vec![Backends::all()]);
+}
@@ -1,59 +1,22 @@
 use wgpu::{
-    Features, Limits, RequestAdapterOptions, SamplerBindingType, TextureSampleType, TextureUsages,
+    AdapterInfo, Features, Limits, RequestAdapterOptions, SamplerBindingType, TextureSampleType, TextureUsages,
     TextureViewDimension,
 };
 
 use crate::*;
 
-pub async fn create_graphics_context(window: Arc<Window>) -> GraphicsContext {
-    let size = window.inner_size();
-
-    let default_backends = Backends::VULKAN;
-
-    let instance = Instance::new(&InstanceDescriptor {
-        backends: default_backends,
-        ..Default::default()
-    });
-
-    let surface = instance
-        .create_surface(window)
-        .expect("Failed to create surface");
-
-    trace!("Requesting adapter");
-
-    let adapter = instance
-        .request_adapter(&RequestAdapterOptions {
-            power_preference: PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        })
-        .await
-        .expect("adapter config must be valid");
+use anyhow::{Result, anyhow};
 
-    info!("Using adapter: {:?}", adapter.get_info().name);
-
-    trace!("Requesting device");
-
-    let limits = Limits {
-        max_texture_dimension_2d: 4096,
-        ..Limits::downlevel_defaults()
-    };
-
-    // TODO: adapter.features();
+pub async fn create_graphics_context(
+    window: Arc<Window>,
+    graphics_config: &GraphicsConfig,
+) -> Result<GraphicsContext> {
+    let size = window.inner_size();
 
-    let (device, queue) = adapter
-        .request_device(
-            &DeviceDescriptor {
-                label: None,
-                required_features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                required_limits: limits,
+    let (instance, adapter, surface) = request_adapter(graphics_config, Some(window)).await?;
+    let surface = surface.expect("surface is created together with the adapter");
 
-                ..Default::default()
-            },
-            None,
-        )
-        .await
-        .expect("failed to create wgpu adapter");
+    let (device, queue) = request_device(&adapter).await?;
 
     let caps = surface.get_capabilities(&adapter);
 
@@ -78,11 +41,9 @@ pub async fn create_graphics_context(window: Arc<Window>) -> GraphicsContext {
 
     let _ = DEFAULT_TEXTURE_FORMAT.set(format);
 
-    #[cfg(any(target_os = "android", target_os = "ios"))]
-    let present_mode = PresentMode::Mailbox;
+    let present_mode = choose_present_mode(&caps.present_modes);
 
-    #[cfg(any(target_os = "windows", target_os = "macos"))]
-    let present_mode = PresentMode::Immediate;
+    info!("Present mode: {:?} (supported {:?})", present_mode, caps.present_modes);
 
     let config = SurfaceConfiguration {
         format,
@@ -103,6 +64,257 @@ pub async fn create_graphics_context(window: Arc<Window>) -> GraphicsContext {
 
     surface.configure(&device, &config);
 
+    Ok(build_graphics_context(instance, adapter, device, queue, Some(surface), config))
+}
+
+/// 从 Surface 支持的模式中选择不等待垂直同步的模式，都不支持时退回所有后端都保证支持的 `Fifo`
+fn choose_present_mode(supported: &[PresentMode]) -> PresentMode {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let preferred = [PresentMode::Mailbox, PresentMode::Immediate];
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let preferred = [PresentMode::Immediate, PresentMode::Mailbox];
+
+    preferred
+        .into_iter()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// 创建不带 Surface 的图形上下文，用于 CI / 渲染服务器等没有窗口的环境。
+pub async fn create_headless_graphics_context(
+    size: UVec2,
+    graphics_config: &GraphicsConfig,
+) -> Result<GraphicsContext> {
+    let (instance, adapter, _) = request_adapter(graphics_config, None).await?;
+
+    let (device, queue) = request_device(&adapter).await?;
+
+    // 离屏渲染固定使用 sRGB RGBA，方便回读
+    let format = TextureFormat::Rgba8UnormSrgb;
+
+    let _ = DEFAULT_TEXTURE_FORMAT.set(format);
+
+    let config = SurfaceConfiguration {
+        format,
+        present_mode: PresentMode::Fifo,
+        width: size.x.max(1),
+        height: size.y.max(1),
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        desired_maximum_frame_latency: 2,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: vec![],
+    };
+
+    Ok(build_graphics_context(instance, adapter, device, queue, None, config))
+}
+
+/// 按 `GraphicsConfig` 的优先级依次尝试各个后端，返回第一个可用的适配器。
+///
+/// 传入窗口时同时创建 Surface，并要求适配器能向它输出。所有后端都没有硬件适配器时，
+/// 再尝试软件适配器（lavapipe、llvmpipe 等）。
+async fn request_adapter(
+    graphics_config: &GraphicsConfig,
+    window: Option<Arc<Window>>,
+) -> Result<(Instance, Adapter, Option<Surface<'static>>)> {
+    let priority = graphics_config.backend_priority();
+    let power_preference = graphics_config.power_preference();
+
+    let mut force_fallback = vec![graphics_config.force_fallback_adapter];
+    if !graphics_config.force_fallback_adapter && graphics_config.allow_software_fallback {
+        force_fallback.push(true);
+    }
+
+    for force_fallback_adapter in force_fallback {
+        for &backends in &priority {
+            let instance = Instance::new(&InstanceDescriptor {
+                backends,
+                ..InstanceDescriptor::from_env_or_default()
+            });
+
+            let surface = match &window {
+                Some(window) => match instance.create_surface(window.clone()) {
+                    Ok(surface) => Some(surface),
+                    Err(e) => {
+                        warn!("Failed to create surface with {:?}: {}", backends, e);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            trace!("Requesting adapter from {:?}", backends);
+
+            let adapter = match wgpu::util::initialize_adapter_from_env(&instance, surface.as_ref()) {
+                Some(adapter) => Some(adapter),
+                None => {
+                    instance
+                        .request_adapter(&RequestAdapterOptions {
+                            power_preference,
+                            compatible_surface: surface.as_ref(),
+                            force_fallback_adapter,
+                        })
+                        .await
+                }
+            };
+
+            let Some(adapter) = adapter else {
+                info!(
+                    "No {}adapter available for {:?}",
+                    if force_fallback_adapter { "software " } else { "" },
+                    backends
+                );
+                continue;
+            };
+
+            let adapter_info = adapter.get_info();
+            info!(
+                "Using adapter: {} ({:?}, {:?}, driver {} {})",
+                adapter_info.name,
+                adapter_info.backend,
+                adapter_info.device_type,
+                adapter_info.driver,
+                adapter_info.driver_info
+            );
+
+            return Ok((instance, adapter, surface));
+        }
+    }
+
+    Err(anyhow!("No graphics adapter available for backends {:?}", priority))
+}
+
+/// 可选的设备特性，适配器支持时才会请求
+const OPTIONAL_FEATURES: Features = Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+
+async fn request_device(adapter: &Adapter) -> Result<(Device, Queue)> {
+    trace!("Requesting device");
+
+    let adapter_limits = adapter.limits();
+
+    // 优先使用 downlevel 默认值，GLES 等达不到的适配器退到 WebGL2 默认值；
+    // 纹理尺寸相关的限制按适配器实际支持的最大值请求
+    let base_limits = if Limits::downlevel_defaults().check_limits(&adapter_limits) {
+        Limits::downlevel_defaults()
+    } else {
+        Limits::downlevel_webgl2_defaults()
+    };
+    let limits = base_limits.using_resolution(adapter_limits);
+
+    let features = adapter.features() & OPTIONAL_FEATURES;
+
+    adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                required_features: features,
+                required_limits: limits,
+
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to create wgpu device on {}: {}", adapter.get_info().name, e))
+}
+
+/// 设备创建后实际得到的特性和限制
+#[derive(Clone, Debug)]
+pub struct GraphicsCapabilities {
+    pub adapter_info: AdapterInfo,
+    pub features: Features,
+    pub limits: Limits,
+    /// 颜色格式和深度格式都支持的 MSAA 采样数，从小到大
+    pub msaa_sample_counts: Vec<u32>,
+}
+
+impl GraphicsCapabilities {
+    fn new(adapter: &Adapter, device: &Device, color_format: TextureFormat) -> Self {
+        let features = device.features();
+
+        // 没有 TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES 时只能用 WebGPU 保证的采样数
+        let format_sample_counts = |format: TextureFormat| {
+            if features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                adapter
+                    .get_texture_format_features(format)
+                    .flags
+                    .supported_sample_counts()
+            } else {
+                format
+                    .guaranteed_format_features(features)
+                    .flags
+                    .supported_sample_counts()
+            }
+        };
+
+        let depth_counts = format_sample_counts(Texture::DEPTH_FORMAT);
+        let msaa_sample_counts = format_sample_counts(color_format)
+            .into_iter()
+            .filter(|count| depth_counts.contains(count))
+            .collect();
+
+        Self {
+            adapter_info: adapter.get_info(),
+            features,
+            limits: device.limits(),
+            msaa_sample_counts,
+        }
+    }
+
+    pub fn max_texture_size(&self) -> u32 {
+        self.limits.max_texture_dimension_2d
+    }
+
+    pub fn supports_msaa(&self, msaa: Msaa) -> bool {
+        self.msaa_sample_counts.contains(&msaa.into())
+    }
+
+    /// 不超过 `requested` 的最大可用采样数
+    pub fn clamp_msaa(&self, requested: Msaa) -> Msaa {
+        [Msaa::Sample8, Msaa::Sample4, Msaa::Sample2]
+            .into_iter()
+            .find(|&msaa| u32::from(msaa) <= u32::from(requested) && self.supports_msaa(msaa))
+            .unwrap_or(Msaa::Off)
+    }
+
+    pub fn check_texture_size(&self, size: UVec2) -> Result<()> {
+        let max = self.max_texture_size();
+
+        if size.x == 0 || size.y == 0 || size.x > max || size.y > max {
+            return Err(anyhow!(
+                "Texture size {}x{} is outside the device limit 1..={}",
+                size.x,
+                size.y,
+                max
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// 把尺寸限制在设备支持的范围内
+    pub fn clamp_texture_size(&self, size: UVec2) -> UVec2 {
+        size.clamp(UVec2::ONE, UVec2::splat(self.max_texture_size()))
+    }
+}
+
+/// 渲染器初始化之前返回 `None`
+pub fn get_graphics_capabilities() -> Option<Arc<GraphicsCapabilities>> {
+    if !check_wgpu_init() {
+        return None;
+    }
+
+    Some(get_global_wgpu().read().context.capabilities.clone())
+}
+
+fn build_graphics_context(
+    instance: Instance,
+    adapter: Adapter,
+    device: Device,
+    queue: Queue,
+    surface: Option<Surface<'static>>,
+    config: SurfaceConfiguration,
+) -> GraphicsContext {
     let texture_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         entries: &[
             BindGroupLayoutEntry {
@@ -127,6 +339,15 @@ pub async fn create_graphics_context(window: Arc<Window>) -> GraphicsContext {
 
     let textures = Arc::new(Mutex::new(HashMap::new()));
 
+    let capabilities = GraphicsCapabilities::new(&adapter, &device, config.format);
+
+    info!(
+        "Max texture size {}, MSAA sample counts {:?}, features {:?}",
+        capabilities.max_texture_size(),
+        capabilities.msaa_sample_counts,
+        capabilities.features
+    );
+
     let device = Arc::new(device);
     let queue = Arc::new(queue);
     let texture_layout = Arc::new(texture_bind_group_layout);
@@ -136,9 +357,51 @@ pub async fn create_graphics_context(window: Arc<Window>) -> GraphicsContext {
         device,
         texture_layout,
         adapter: Arc::new(adapter),
-        surface: Some(Arc::new(surface)),
+        surface: surface.map(Arc::new),
         instance: Arc::new(instance),
         config: Arc::new(RwLock::new(config)),
         textures,
+        capabilities: Arc::new(capabilities),
     }
 }
+
+#[test]
+fn present_mode_falls_back_to_fifo() {
+    // GL 后端和部分 Wayland 驱动只提供 Fifo
+    assert_eq!(choose_present_mode(&[PresentMode::Fifo]), PresentMode::Fifo);
+    assert_eq!(choose_present_mode(&[]), PresentMode::Fifo);
+
+    let mode = choose_present_mode(&[PresentMode::Fifo, PresentMode::Mailbox]);
+    assert_eq!(mode, PresentMode::Mailbox);
+
+    let all = [PresentMode::Fifo, PresentMode::Mailbox, PresentMode::Immediate];
+    assert_ne!(choose_present_mode(&all), PresentMode::Fifo);
+}
+
+#[test]
+fn unsupported_msaa_falls_back() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    // 大多数软件适配器不支持 8x，渲染时不应 panic
+    let Some(_) = render_scene(Msaa::Sample8, || {
+        draw_quad(RawDrawParams {
+            dest_size: Some(uvec2(80, 40)),
+            color: WHITE,
+            ..Default::default()
+        });
+    }) else {
+        return;
+    };
+
+    let capabilities = get_graphics_capabilities().unwrap();
+    let sample_count = get_run_time_context().read().sample_count;
+
+    assert_eq!(sample_count, capabilities.clamp_msaa(Msaa::Sample8));
+    assert!(sample_count == Msaa::Off || capabilities.supports_msaa(sample_count));
+
+    let max = capabilities.max_texture_size();
+    assert!(capabilities.check_texture_size(uvec2(max, 1)).is_ok());
+    assert!(capabilities.check_texture_size(uvec2(max + 1, 1)).is_err());
+
+    set_sample_count(Msaa::Off);
+}
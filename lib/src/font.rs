@@ -0,0 +1,833 @@
+//! 字体加载、文字排版和字形缓存。
+//!
+//! 字体用 ab_glyph 解析（TTF、OTF 以及 TTC 中的第一个字体）。每个字符依次在指定字体、
+//! 默认字体和回退字体里查找字形，西文字体里混排的中日文标题会落到 CJK 回退字体上。
+//! 排版只做基础的工作：`kern` 表字距、换行（CJK 字符之间可以断行）和对齐。
+//! 字形按需栅格化进 GPU 上的缓存页，`draw_text` 为每个缓存页生成一个网格交给绘制队列。
+
+use crate::*;
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont, point};
+use anyhow::{Result, anyhow, bail};
+use image::{Rgba, RgbaImage};
+use std::ops::Range;
+use wgpu::FilterMode;
+
+const GLYPH_PAGE_SIZE: UVec2 = uvec2(1024, 1024);
+// 字形之间留一圈透明像素，线性采样时不会混进相邻的字形
+const GLYPH_PADDING: u32 = 1;
+
+/// 常见系统自带的中日韩字体，按顺序尝试
+const SYSTEM_CJK_FONTS: &[&str] = &[
+    // Android
+    "/system/fonts/NotoSansCJK-Regular.ttc",
+    "/system/fonts/NotoSansSC-Regular.otf",
+    "/system/fonts/DroidSansFallback.ttf",
+    // Windows
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\YuGothM.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    // macOS
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    // Linux
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+];
+
+// 不能出现在行首的标点
+const NO_BREAK_BEFORE: &str =
+    "、。，．！？：；）」』】〕〉》ー…・ぁぃぅぇぉっゃゅょァィゥェォッャュョ,.!?:;)]}";
+// 不能出现在行尾的标点
+const NO_BREAK_AFTER: &str = "（「『【〔〈《([{";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FontId(pub u32);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TextParams {
+    /// 为空时使用默认字体
+    pub font: Option<FontId>,
+    /// 字号，即字体的像素高度
+    pub size: f32,
+    pub color: Color,
+    /// 文字框的锚点，水平方向按 `align`、竖直方向按 `vertical_align` 对齐到这里
+    pub position: Vec2,
+    pub align: TextAlign,
+    pub vertical_align: VerticalAlign,
+    /// 一行超过这个宽度时换行
+    pub max_width: Option<f32>,
+    /// 行高的倍数
+    pub line_spacing: f32,
+    pub z_index: i32,
+    pub blend_mode: BlendMode,
+}
+
+impl Default for TextParams {
+    fn default() -> Self {
+        Self {
+            font: None,
+            size: 24.0,
+            color: WHITE,
+            position: Vec2::ZERO,
+            align: TextAlign::Left,
+            vertical_align: VerticalAlign::Top,
+            max_width: None,
+            line_spacing: 1.0,
+            z_index: 0,
+            blend_mode: BlendMode::Alpha,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LaidOutGlyph {
+    pub font: FontId,
+    pub glyph: u16,
+    pub ch: char,
+    /// 字形原点（基线上）相对锚点的位置，y 向下
+    pub position: Vec2,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    /// 不含空白字符
+    pub glyphs: Vec<LaidOutGlyph>,
+    pub line_widths: Vec<f32>,
+    pub line_height: f32,
+    /// 文字框左上角相对锚点的位置，y 向下
+    pub offset: Vec2,
+    pub size: Vec2,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ShapedChar {
+    ch: char,
+    font: FontId,
+    glyph: GlyphId,
+    advance: f32,
+    // 与同一段落中前一个字符之间的字距
+    kern: f32,
+}
+
+fn line_width(line: &[ShapedChar]) -> f32 {
+    line.iter()
+        .enumerate()
+        .map(|(i, c)| c.advance + if i > 0 { c.kern } else { 0.0 })
+        .sum()
+}
+
+pub fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch as u32,
+        0x1100..=0x11FF | 0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x3FFFF
+    )
+}
+
+/// 两个字符之间是否可以断行：空白之后、CJK 字符前后，并避开行首行尾禁则的标点
+pub fn can_break_before(prev: char, ch: char) -> bool {
+    if ch.is_whitespace() || NO_BREAK_BEFORE.contains(ch) || NO_BREAK_AFTER.contains(prev) {
+        return false;
+    }
+
+    prev.is_whitespace() || is_cjk(prev) || is_cjk(ch)
+}
+
+/// 贪心断行，返回每一行在段落中的范围（不含行尾空白）
+fn break_lines(shaped: &[ShapedChar], max_width: Option<f32>) -> Vec<Range<usize>> {
+    let trim_end = |start: usize, mut end: usize| {
+        while end > start && shaped[end - 1].ch.is_whitespace() {
+            end -= 1;
+        }
+        start..end
+    };
+
+    let Some(max_width) = max_width else {
+        return vec![trim_end(0, shaped.len())];
+    };
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut last_break = None;
+    // shaped[start..=i] 的宽度，与 line_width 的累加顺序一致
+    let mut width = 0.0;
+    let mut i = 0;
+
+    while i < shaped.len() {
+        if i > start && can_break_before(shaped[i - 1].ch, shaped[i].ch) {
+            last_break = Some(i);
+        }
+
+        width += shaped[i].advance + if i > start { shaped[i].kern } else { 0.0 };
+
+        if i > start && !shaped[i].ch.is_whitespace() && width > max_width {
+            // 没有可以断行的位置时在当前字符前硬断
+            let end = last_break.unwrap_or(i);
+            lines.push(trim_end(start, end));
+
+            start = end;
+            while start < shaped.len() && shaped[start].ch.is_whitespace() {
+                start += 1;
+            }
+
+            last_break = None;
+            width = 0.0;
+            i = start;
+            continue;
+        }
+
+        i += 1;
+    }
+
+    lines.push(trim_end(start, shaped.len()));
+    lines
+}
+
+/// 已加载的字体、默认字体和回退链
+#[derive(Default)]
+pub struct FontSystem {
+    fonts: Vec<Arc<FontVec>>,
+    names: HashMap<String, FontId>,
+    default_font: Option<FontId>,
+    fallbacks: Vec<FontId>,
+}
+
+impl FontSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 加载字体，字体集（TTC）取第一个。第一个加载的字体会成为默认字体。
+    ///
+    /// 同名字体重新加载时分配新的 id，默认字体和回退链里的旧 id 会被替换。
+    pub fn load_from_bytes(&mut self, name: &str, bytes: Vec<u8>) -> Result<FontId> {
+        let font = FontVec::try_from_vec_and_index(bytes, 0)
+            .map_err(|e| anyhow!("Failed to parse font '{}': {}", name, e))?;
+
+        let id = FontId(self.fonts.len() as u32);
+        self.fonts.push(Arc::new(font));
+
+        if let Some(old) = self.names.insert(name.to_owned(), id) {
+            if self.default_font == Some(old) {
+                self.default_font = Some(id);
+            }
+            for fallback in self.fallbacks.iter_mut().filter(|f| **f == old) {
+                *fallback = id;
+            }
+        }
+
+        self.default_font.get_or_insert(id);
+
+        Ok(id)
+    }
+
+    pub fn font_id(&self, name: &str) -> Option<FontId> {
+        self.names.get(name).copied()
+    }
+
+    pub fn font(&self, id: FontId) -> Option<&Arc<FontVec>> {
+        self.fonts.get(id.0 as usize)
+    }
+
+    pub fn default_font(&self) -> Option<FontId> {
+        self.default_font
+    }
+
+    pub fn set_default_font(&mut self, id: FontId) {
+        self.default_font = Some(id);
+    }
+
+    pub fn fallbacks(&self) -> &[FontId] {
+        &self.fallbacks
+    }
+
+    /// 追加到回退链末尾
+    pub fn add_fallback(&mut self, id: FontId) {
+        if !self.fallbacks.contains(&id) {
+            self.fallbacks.push(id);
+        }
+    }
+
+    /// 找到第一个包含该字符的字体，都没有时用首选字体的缺字字形
+    fn resolve(&self, primary: FontId, ch: char) -> (FontId, GlyphId) {
+        let candidates = std::iter::once(primary)
+            .chain(self.default_font)
+            .chain(self.fallbacks.iter().copied());
+
+        for id in candidates {
+            if let Some(font) = self.font(id) {
+                let glyph = font.glyph_id(ch);
+                if glyph.0 != 0 {
+                    return (id, glyph);
+                }
+            }
+        }
+
+        (primary, GlyphId(0))
+    }
+
+    fn shape(&self, text: &str, primary: FontId, scale: PxScale) -> Vec<ShapedChar> {
+        let mut shaped = Vec::with_capacity(text.len());
+        let mut prev: Option<(FontId, GlyphId)> = None;
+
+        for ch in text.chars() {
+            let ch = if ch == '\t' { ' ' } else { ch };
+            if ch.is_control() {
+                continue;
+            }
+
+            let (font_id, glyph) = self.resolve(primary, ch);
+            let font = self.fonts[font_id.0 as usize].as_scaled(scale);
+
+            // 只在同一个字体的相邻字形之间调整字距
+            let kern = match prev {
+                Some((prev_font, prev_glyph)) if prev_font == font_id => {
+                    font.kern(prev_glyph, glyph)
+                }
+                _ => 0.0,
+            };
+
+            shaped.push(ShapedChar {
+                ch,
+                font: font_id,
+                glyph,
+                advance: font.h_advance(glyph),
+                kern,
+            });
+
+            prev = Some((font_id, glyph));
+        }
+
+        shaped
+    }
+
+    pub fn layout(&self, text: &str, params: &TextParams) -> Result<TextLayout> {
+        let primary = params
+            .font
+            .or(self.default_font)
+            .ok_or_else(|| anyhow!("No font loaded"))?;
+        let primary_font = self
+            .font(primary)
+            .ok_or_else(|| anyhow!("Font {:?} is not loaded", primary))?;
+
+        let scale = PxScale::from(params.size);
+        let metrics = primary_font.as_scaled(scale);
+        let ascent = metrics.ascent();
+        let line_height = (ascent - metrics.descent() + metrics.line_gap()) * params.line_spacing;
+
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let shaped = self.shape(paragraph, primary, scale);
+            for range in break_lines(&shaped, params.max_width) {
+                lines.push(shaped[range].to_vec());
+            }
+        }
+
+        let line_widths: Vec<f32> = lines.iter().map(|line| line_width(line)).collect();
+        let width = line_widths.iter().copied().fold(0.0, f32::max);
+        let height = (lines.len() - 1) as f32 * line_height + ascent - metrics.descent();
+
+        let align_x = |w: f32| match params.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => -w / 2.0,
+            TextAlign::Right => -w,
+        };
+        let top = match params.vertical_align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Center => -height / 2.0,
+            VerticalAlign::Bottom => -height,
+        };
+
+        let mut glyphs = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = top + ascent + i as f32 * line_height;
+            let mut x = align_x(line_widths[i]);
+
+            for (j, c) in line.iter().enumerate() {
+                if j > 0 {
+                    x += c.kern;
+                }
+
+                if !c.ch.is_whitespace() {
+                    glyphs.push(LaidOutGlyph {
+                        font: c.font,
+                        glyph: c.glyph.0,
+                        ch: c.ch,
+                        position: vec2(x, baseline),
+                    });
+                }
+
+                x += c.advance;
+            }
+        }
+
+        Ok(TextLayout {
+            glyphs,
+            line_widths,
+            line_height,
+            offset: vec2(align_x(width), top),
+            size: vec2(width, height),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CachedGlyph {
+    page: usize,
+    uv_min: Vec2,
+    uv_max: Vec2,
+    size: Vec2,
+    // 位图左上角相对字形原点的位置，y 向下
+    offset: Vec2,
+}
+
+struct GlyphPage {
+    handle: TextureHandle,
+    packer: ShelfPacker,
+    size: UVec2,
+}
+
+/// GPU 上的字形缓存，页满了就新开一页，不做淘汰
+#[derive(Default)]
+struct GlyphCache {
+    pages: Vec<GlyphPage>,
+    // 键为 (字体, 字形, 字号的 1/64 像素)，没有轮廓的字形（比如空格）记为 None
+    glyphs: HashMap<(FontId, u16, u32), Option<CachedGlyph>>,
+}
+
+impl GlyphCache {
+    fn get_or_insert(
+        &mut self,
+        font: &FontVec,
+        font_id: FontId,
+        glyph: u16,
+        scale: PxScale,
+    ) -> Result<Option<CachedGlyph>> {
+        let key = (font_id, glyph, (scale.y * 64.0).round() as u32);
+
+        if let Some(cached) = self.glyphs.get(&key) {
+            return Ok(*cached);
+        }
+
+        let cached = self.rasterize(font, glyph, scale)?;
+        self.glyphs.insert(key, cached);
+
+        Ok(cached)
+    }
+
+    fn rasterize(
+        &mut self,
+        font: &FontVec,
+        glyph: u16,
+        scale: PxScale,
+    ) -> Result<Option<CachedGlyph>> {
+        let Some(outlined) =
+            font.outline_glyph(GlyphId(glyph).with_scale_and_position(scale, point(0.0, 0.0)))
+        else {
+            return Ok(None);
+        };
+
+        let bounds = outlined.px_bounds();
+        let size = uvec2(bounds.width() as u32, bounds.height() as u32);
+        if size.x == 0 || size.y == 0 {
+            return Ok(None);
+        }
+
+        let pad = GLYPH_PADDING;
+        let mut image =
+            RgbaImage::from_pixel(size.x + pad * 2, size.y + pad * 2, Rgba([255, 255, 255, 0]));
+        outlined.draw(|x, y, coverage| {
+            let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+            image.put_pixel(x + pad, y + pad, Rgba([255, 255, 255, alpha]));
+        });
+
+        let padded = uvec2(image.width(), image.height());
+
+        let allocation = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, page)| page.packer.allocate(padded).map(|p| (i, p)));
+
+        let (page, position) = match allocation {
+            Some(allocation) => allocation,
+            None => {
+                let index = self.pages.len();
+                let (handle, page_size) = create_page_texture(
+                    &format!("__glyph_cache_{index}"),
+                    GLYPH_PAGE_SIZE,
+                    FilterMode::Linear,
+                )?;
+
+                info!(
+                    "Created glyph cache page {} ({}x{})",
+                    index, page_size.x, page_size.y
+                );
+
+                let mut packer = ShelfPacker::new(page_size);
+                let Some(position) = packer.allocate(padded) else {
+                    bail!(
+                        "Glyph {}x{} does not fit into a glyph cache page",
+                        size.x,
+                        size.y
+                    );
+                };
+
+                self.pages.push(GlyphPage {
+                    handle,
+                    packer,
+                    size: page_size,
+                });
+
+                (index, position)
+            }
+        };
+
+        write_texture_region(self.pages[page].handle, position, &image)?;
+
+        let page_size = self.pages[page].size.as_vec2();
+        let position = position + UVec2::splat(pad);
+
+        Ok(Some(CachedGlyph {
+            page,
+            uv_min: position.as_vec2() / page_size,
+            uv_max: (position + size).as_vec2() / page_size,
+            size: size.as_vec2(),
+            offset: vec2(bounds.min.x, bounds.min.y),
+        }))
+    }
+}
+
+static FONTS: Lazy<RwLock<FontSystem>> = Lazy::new(|| RwLock::new(FontSystem::new()));
+static GLYPH_CACHE: Lazy<Mutex<GlyphCache>> = Lazy::new(|| Mutex::new(GlyphCache::default()));
+
+pub fn get_fonts() -> &'static RwLock<FontSystem> {
+    &FONTS
+}
+
+pub fn load_font(name: &str, path: impl AsRef<std::path::Path>) -> Result<FontId> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| anyhow!("Failed to read {:?}: {}", path, e))?;
+
+    FONTS.write().load_from_bytes(name, bytes)
+}
+
+pub fn load_font_from_bytes(name: &str, bytes: &[u8]) -> Result<FontId> {
+    FONTS.write().load_from_bytes(name, bytes.to_vec())
+}
+
+pub fn font_id(name: &str) -> Option<FontId> {
+    FONTS.read().font_id(name)
+}
+
+pub fn set_default_font(id: FontId) {
+    FONTS.write().set_default_font(id);
+}
+
+pub fn add_fallback_font(id: FontId) {
+    FONTS.write().add_fallback(id);
+}
+
+/// 在系统字体目录里找一个中日韩字体加入回退链，找不到时返回 `None`
+pub fn load_system_cjk_fallback() -> Option<FontId> {
+    for path in SYSTEM_CJK_FONTS {
+        if !std::path::Path::new(path).exists() {
+            continue;
+        }
+
+        match load_font("__system_cjk", path) {
+            Ok(id) => {
+                info!("Using system CJK fallback font {}", path);
+                add_fallback_font(id);
+                return Some(id);
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+
+    warn!("No system CJK font found, load one with load_font and add_fallback_font");
+    None
+}
+
+pub fn layout_text(text: &str, params: &TextParams) -> Result<TextLayout> {
+    FONTS.read().layout(text, params)
+}
+
+/// 文字框的尺寸，没有可用字体时为 0
+pub fn measure_text(text: &str, params: &TextParams) -> Vec2 {
+    layout_text(text, params)
+        .map(|layout| layout.size)
+        .unwrap_or(Vec2::ZERO)
+}
+
+/// 排版并把文字加入绘制队列，每个字形缓存页一个网格
+pub fn draw_text(text: &str, params: TextParams) {
+    let fonts = FONTS.read();
+
+    let layout = match fonts.layout(text, &params) {
+        Ok(layout) => layout,
+        Err(e) => {
+            error!("Failed to draw text: {}", e);
+            return;
+        }
+    };
+
+    let scale = PxScale::from(params.size);
+    let z = z_index_depth(params.z_index);
+
+    let mut cache = GLYPH_CACHE.lock();
+    let mut meshes: BTreeMap<usize, Mesh> = BTreeMap::new();
+
+    for glyph in &layout.glyphs {
+        let Some(font) = fonts.font(glyph.font) else {
+            continue;
+        };
+
+        let cached = match cache.get_or_insert(font, glyph.font, glyph.glyph, scale) {
+            Ok(Some(cached)) => cached,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to cache glyph '{}': {}", glyph.ch, e);
+                continue;
+            }
+        };
+
+        // 原点对齐到整像素，字形不会因为采样而发虚
+        let origin = vec2(
+            params.position.x + glyph.position.x,
+            params.position.y - glyph.position.y,
+        )
+        .round();
+
+        let left = origin.x + cached.offset.x;
+        let right = left + cached.size.x;
+        let top = origin.y - cached.offset.y;
+        let bottom = top - cached.size.y;
+
+        let page_handle = cache.pages[cached.page].handle;
+        let mesh = meshes.entry(cached.page).or_insert_with(|| Mesh {
+            origin: params.position.extend(0.0),
+            z_index: params.z_index,
+            texture: Some(page_handle),
+            ..Default::default()
+        });
+
+        let base = mesh.vertices.len() as u32;
+
+        // 顺时针：左下、左上、右上、右下
+        mesh.vertices.extend([
+            SpriteVertex::new(
+                vec3(left, bottom, z),
+                vec2(cached.uv_min.x, cached.uv_max.y),
+                params.color,
+            ),
+            SpriteVertex::new(vec3(left, top, z), cached.uv_min, params.color),
+            SpriteVertex::new(
+                vec3(right, top, z),
+                vec2(cached.uv_max.x, cached.uv_min.y),
+                params.color,
+            ),
+            SpriteVertex::new(vec3(right, bottom, z), cached.uv_max, params.color),
+        ]);
+        mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+    }
+
+    drop(cache);
+    drop(fonts);
+
+    for mesh in meshes.into_values() {
+        draw_mesh_ex(mesh, params.blend_mode);
+    }
+}
+
+/// 释放所有字形缓存页。已经加入本帧绘制队列的文字会失效，应在两帧之间调用。
+pub fn clear_glyph_cache() {
+    let mut cache = GLYPH_CACHE.lock();
+
+    if check_wgpu_init() {
+        let wr = get_global_wgpu().read();
+        let mut textures = wr.context.textures.lock();
+
+        for page in &cache.pages {
+            if let Some(texture) = textures.remove(&page.handle) {
+                texture.texture.texture.destroy();
+            }
+        }
+    }
+
+    *cache = GlyphCache::default();
+}
+
+#[cfg(test)]
+fn test_font_system() -> (FontSystem, FontId) {
+    // DejaVu Sans，Bitstream Vera 许可证，见 tests/fonts/DejaVuSans-LICENSE.txt
+    let bytes = std::fs::read("tests/fonts/DejaVuSans-ExtraLight.ttf").unwrap();
+
+    let mut system = FontSystem::new();
+    let id = system.load_from_bytes("dejavu", bytes).unwrap();
+
+    (system, id)
+}
+
+#[test]
+fn text_layout_aligns_and_wraps() {
+    let (system, _) = test_font_system();
+    let params = TextParams {
+        size: 20.0,
+        ..Default::default()
+    };
+
+    let left = system.layout("Hello", &params).unwrap();
+    assert_eq!(left.glyphs.len(), 5);
+    assert_eq!(left.glyphs[0].position.x, 0.0);
+    assert_eq!(left.line_widths.len(), 1);
+    assert!(left.size.x > 20.0 && left.size.y > 15.0);
+
+    let width = left.size.x;
+    for (align, start) in [
+        (TextAlign::Center, -width / 2.0),
+        (TextAlign::Right, -width),
+    ] {
+        let layout = system
+            .layout("Hello", &TextParams { align, ..params })
+            .unwrap();
+        assert!(
+            (layout.glyphs[0].position.x - start).abs() < 1e-3,
+            "{align:?}"
+        );
+        assert!((layout.offset.x - start).abs() < 1e-3, "{align:?}");
+    }
+
+    let centered = system
+        .layout(
+            "Hello",
+            &TextParams {
+                vertical_align: VerticalAlign::Center,
+                ..params
+            },
+        )
+        .unwrap();
+    assert!((centered.offset.y + left.size.y / 2.0).abs() < 1e-3);
+
+    // 在空格处断行，行尾空格不计入宽度
+    let first_line = system.layout("one two", &params).unwrap().size.x;
+    let wrapped = system
+        .layout(
+            "one two three",
+            &TextParams {
+                max_width: Some(first_line + 1.0),
+                ..params
+            },
+        )
+        .unwrap();
+    assert_eq!(wrapped.line_widths.len(), 2);
+    assert_eq!(wrapped.line_widths[0], first_line);
+    let second: String = wrapped
+        .glyphs
+        .iter()
+        .filter(|g| g.position.y > wrapped.glyphs[0].position.y)
+        .map(|g| g.ch)
+        .collect();
+    assert_eq!(second, "three");
+
+    // 没有断行机会的长词按字符硬断，每行至少一个字符
+    let long = system
+        .layout(
+            "abcdefgh",
+            &TextParams {
+                max_width: Some(25.0),
+                ..params
+            },
+        )
+        .unwrap();
+    assert!(long.line_widths.len() > 2);
+    assert!(long.line_widths.iter().all(|w| *w <= 25.0));
+    assert_eq!(long.glyphs.len(), 8);
+
+    // 空行保留
+    let paragraphs = system.layout("a\n\nb", &params).unwrap();
+    assert_eq!(paragraphs.line_widths.len(), 3);
+    assert!(
+        (paragraphs.glyphs[1].position.y
+            - paragraphs.glyphs[0].position.y
+            - 2.0 * paragraphs.line_height)
+            .abs()
+            < 1e-3
+    );
+}
+
+#[test]
+fn text_layout_kerning_and_fallback() {
+    let (mut system, dejavu) = test_font_system();
+    let params = TextParams {
+        size: 40.0,
+        ..Default::default()
+    };
+
+    // "AV" 有字距调整，比两个字单独排开更窄
+    let pair = system.layout("AV", &params).unwrap().size.x;
+    let apart =
+        system.layout("A", &params).unwrap().size.x + system.layout("V", &params).unwrap().size.x;
+    assert!(pair < apart, "{pair} >= {apart}");
+
+    // 只包含 "A" 的字体，缺少的字符落到回退字体上
+    let only_a = system
+        .load_from_bytes("only_a", std::fs::read("tests/fonts/only_a.ttf").unwrap())
+        .unwrap();
+    let params = TextParams {
+        font: Some(only_a),
+        ..params
+    };
+
+    let fonts = |system: &FontSystem| {
+        system
+            .layout("AB", &params)
+            .unwrap()
+            .glyphs
+            .iter()
+            .map(|g| (g.font, g.glyph != 0))
+            .collect_vec()
+    };
+
+    // 默认字体（第一个加载的）也参与回退
+    assert_eq!(fonts(&system), vec![(only_a, true), (dejavu, true)]);
+
+    system.set_default_font(only_a);
+    assert_eq!(fonts(&system), vec![(only_a, true), (only_a, false)]);
+
+    system.add_fallback(dejavu);
+    assert_eq!(fonts(&system), vec![(only_a, true), (dejavu, true)]);
+}
+
+#[test]
+fn line_breaks_follow_cjk_rules() {
+    assert!(can_break_before('中', '文'));
+    assert!(can_break_before('a', '中'));
+    assert!(can_break_before('の', 'a'));
+    assert!(can_break_before(' ', 'b'));
+    assert!(!can_break_before('a', 'b'));
+    assert!(!can_break_before('b', ' '));
+    assert!(!can_break_before('中', '。'));
+    assert!(!can_break_before('「', '中'));
+    assert!(!can_break_before('ア', 'ー'));
+}
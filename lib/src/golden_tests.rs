@@ -0,0 +1,387 @@
+//! 精灵管线的参考图（golden image）回归测试。
+//!
+//! 每个场景都在 headless 渲染器里离屏绘制，再与 `tests/golden/` 中的参考 PNG 逐像素比较。
+//! 不一致时会把实际结果和差异图写到 `target/golden/`。
+//!
+//! 设置环境变量 `KKRD_UPDATE_GOLDEN=1` 可以重新生成参考图。
+//! 其他模块里需要 GPU 的测试也通过 [`render_scene`] 和 [`GOLDEN_LOCK`] 渲染并检查像素。
+//! 没有可用的图形适配器时测试会失败；确实无法提供适配器的环境可以设置
+//! `KKRD_SKIP_GPU_TESTS=1` 显式跳过 GPU 相关的测试。
+
+use crate::*;
+use crate::font::*;
+use crate::readback::*;
+
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+
+pub(crate) const SCENE_SIZE: UVec2 = uvec2(128, 128);
+
+// 单个通道允许的误差
+const TOLERANCE: u8 = 8;
+// 允许不一致的像素比例，用来吸收不同光栅器在边缘上的差异
+const MAX_MISMATCH_RATIO: f32 = 0.002;
+
+// 渲染器和绘制队列都是全局的，场景之间必须串行执行
+pub(crate) static GOLDEN_LOCK: Mutex<()> = Mutex::new(());
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+/// 在默认 RT 上渲染一个场景并回读，设置了 `KKRD_SKIP_GPU_TESTS` 且没有适配器时返回 `None`。
+///
+/// `scene` 里可以自行调用 [`render_headless_frame`] 分多帧绘制。
+pub(crate) fn render_scene(sample_count: Msaa, scene: impl FnOnce()) -> Option<RgbaImage> {
+    if let Err(e) = init_headless(SCENE_SIZE, RunTimeContext::default()) {
+        if std::env::var_os("KKRD_SKIP_GPU_TESTS").is_some() {
+            warn!("Skipping GPU test: {:#}", e);
+            return None;
+        }
+
+        panic!("No graphics adapter for GPU tests ({:#}), set KKRD_SKIP_GPU_TESTS=1 to skip them", e);
+    }
+
+    if get_run_time_context().read().sample_count != sample_count {
+        set_sample_count(sample_count);
+    }
+
+    use_default_shader();
+    use_default_render_target();
+    clear_background(BLACK);
+
+    scene();
+
+    use_default_shader();
+    use_default_render_target();
+    render_headless_frame();
+
+    Some(read_render_target(RenderTargetId(0)).expect("readback failed"))
+}
+
+pub(crate) fn assert_golden(name: &str, actual: &RgbaImage) {
+    let reference_path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("KKRD_UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = image::open(&reference_path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Missing reference image {:?} ({}), run with KKRD_UPDATE_GOLDEN=1 to create it",
+                reference_path, e
+            )
+        })
+        .to_rgba8();
+
+    assert_eq!(
+        reference.dimensions(),
+        actual.dimensions(),
+        "Reference image {name} has a different size"
+    );
+
+    let (diff, mismatched) = diff_images(&reference, actual);
+    let total = actual.width() * actual.height();
+
+    if mismatched as f32 > total as f32 * MAX_MISMATCH_RATIO {
+        std::fs::create_dir_all(output_dir()).unwrap();
+
+        let actual_path = output_dir().join(format!("{name}.actual.png"));
+        let diff_path = output_dir().join(format!("{name}.diff.png"));
+
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "Golden image {name} differs in {mismatched}/{total} pixels, see {:?} and {:?}",
+            actual_path, diff_path
+        );
+    }
+}
+
+/// 生成差异图：不一致的像素标红，其余像素按灰度淡化显示。
+fn diff_images(reference: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, u32) {
+    let mut mismatched = 0;
+
+    let diff = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = reference.get_pixel(x, y);
+        let b = actual.get_pixel(x, y);
+
+        let differs = a.0.iter().zip(b.0.iter()).any(|(a, b)| a.abs_diff(*b) > TOLERANCE);
+
+        if differs {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (b[0] as u32 + b[1] as u32 + b[2] as u32) / 3 / 4;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        }
+    });
+
+    (diff, mismatched)
+}
+
+fn tap_params(position: Vec3) -> DrawTextureParams {
+    DrawTextureParams {
+        raw_draw_params: RawDrawParams {
+            position,
+            dest_size: Some(uvec2(100, 10)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn golden_sprite_pivots() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        for (i, pivot) in [vec2(0.0, 0.0), vec2(0.5, 0.5), vec2(1.0, 1.0)].into_iter().enumerate() {
+            let mut params = tap_params(vec3(0.0, 40.0 - i as f32 * 40.0, 0.0));
+            params.raw_draw_params.pivot = Some(pivot);
+            params.raw_draw_params.dest_size = Some(uvec2(40, 20));
+            params.raw_draw_params.color = [RED, GREEN, BLUE][i];
+
+            draw_sprite_ex(texture_id("1px"), params);
+        }
+
+        draw_sprite_ex(texture_id("Tap"), tap_params(vec3(0.0, -55.0, 0.0)));
+    }) else {
+        return;
+    };
+
+    assert_golden("sprite_pivots", &image);
+}
+
+#[test]
+fn golden_sprite_flips() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        for (i, (flip_x, flip_y)) in [(false, false), (true, false), (false, true), (true, true)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut params = tap_params(vec3(0.0, 45.0 - i as f32 * 30.0, 0.0));
+            params.raw_draw_params.dest_size = Some(uvec2(120, 24));
+            params.raw_draw_params.flip_x = flip_x;
+            params.raw_draw_params.flip_y = flip_y;
+
+            draw_sprite_ex(texture_id("1"), params);
+        }
+    }) else {
+        return;
+    };
+
+    assert_golden("sprite_flips", &image);
+}
+
+#[test]
+fn golden_sprite_rotations() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let q = Quat::from_rotation_z(-30f32.to_radians());
+
+    let rotations = [
+        (vec3(-32.0, 32.0, 0.0), Rotation::Z(30.0)),
+        (vec3(32.0, 32.0, 0.0), Rotation::Euler(0.0, 0.0, 45.0)),
+        (vec3(-32.0, -32.0, 0.0), Rotation::Quaternion(q.x, q.y, q.z, q.w)),
+        (vec3(32.0, -32.0, 0.0), Rotation::Euler(60.0, 0.0, 20.0)),
+    ];
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        for (position, rotation) in rotations {
+            let mut params = tap_params(position);
+            params.raw_draw_params.dest_size = Some(uvec2(40, 20));
+            params.raw_draw_params.rotation = rotation;
+            params.raw_draw_params.color = GOLD;
+
+            draw_sprite_ex(texture_id("1px"), params);
+        }
+    }) else {
+        return;
+    };
+
+    assert_golden("sprite_rotations", &image);
+}
+
+#[test]
+fn golden_polys_and_lines() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        draw_circle(vec2(-30.0, 30.0), 20.0, RED, 0);
+        draw_poly2_z(vec2(30.0, 30.0), 6, vec2(25.0, 15.0), 30.0, GREEN, 0, BlendMode::Alpha);
+        draw_line(vec2(-50.0, -20.0), vec2(50.0, -40.0), 4.0, WHITE, 0);
+        draw_line_tex(vec2(-50.0, -50.0), vec2(50.0, -50.0), 10.0, 0, BLUE, Some(texture_id("1")));
+    }) else {
+        return;
+    };
+
+    assert_golden("polys_and_lines", &image);
+}
+
+#[test]
+fn golden_blend_modes() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let blend_modes = [BlendMode::None, BlendMode::Alpha, BlendMode::Additive];
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        for (i, blend_mode) in blend_modes.into_iter().enumerate() {
+            let y = 40.0 - i as f32 * 40.0;
+
+            draw_quad(RawDrawParams {
+                position: vec3(-15.0, y, 0.0),
+                dest_size: Some(uvec2(50, 30)),
+                color: Color::new(0.9, 0.1, 0.1, 0.6),
+                blend_mode,
+                ..Default::default()
+            });
+
+            draw_quad(RawDrawParams {
+                position: vec3(15.0, y, 0.0),
+                dest_size: Some(uvec2(50, 30)),
+                color: Color::new(0.1, 0.3, 0.9, 0.6),
+                z_index: 1,
+                blend_mode,
+                ..Default::default()
+            });
+        }
+    }) else {
+        return;
+    };
+
+    assert_golden("blend_modes", &image);
+}
+
+#[test]
+fn golden_msaa_levels() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    for msaa in [Msaa::Off, Msaa::Sample4] {
+        let Some(image) = render_scene(msaa, || {
+            draw_quad(RawDrawParams {
+                dest_size: Some(uvec2(80, 40)),
+                rotation: Rotation::Z(17.0),
+                color: WHITE,
+                ..Default::default()
+            });
+        }) else {
+            return;
+        };
+
+        assert_golden(&format!("msaa_x{}", u32::from(msaa)), &image);
+    }
+
+    set_sample_count(Msaa::Off);
+}
+
+#[test]
+fn golden_user_render_target() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        let rt = UserRenderTarget::new(&RenderTargetParams {
+            label: "golden-rt".to_owned(),
+            size: uvec2(64, 32),
+        });
+
+        use_render_target(rt);
+        clear_background(DARKBLUE);
+
+        draw_quad(RawDrawParams {
+            position: vec3(-16.0, 0.0, 0.0),
+            dest_size: Some(uvec2(16, 16)),
+            color: RED,
+            ..Default::default()
+        });
+
+        // 先把 RT 渲染完，再在下一帧采样它
+        render_headless_frame();
+
+        use_default_render_target();
+
+        draw_sprite_ex(
+            TextureHandle::RenderTarget(rt),
+            DrawTextureParams {
+                raw_draw_params: RawDrawParams {
+                    rotation: Rotation::Z(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }) else {
+        return;
+    };
+
+    assert_golden("user_render_target", &image);
+}
+
+#[test]
+fn golden_text() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let params = TextParams {
+        size: 32.0,
+        align: TextAlign::Center,
+        vertical_align: VerticalAlign::Center,
+        ..Default::default()
+    };
+
+    let mut size = Vec2::ZERO;
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        let font = load_font("golden_dejavu", "tests/fonts/DejaVuSans-ExtraLight.ttf").unwrap();
+        let params = TextParams {
+            font: Some(font),
+            ..params
+        };
+
+        draw_text("Perfect", TextParams {
+            position: vec2(0.0, 30.0),
+            ..params
+        });
+        size = measure_text("Perfect", &TextParams {
+            position: vec2(0.0, 30.0),
+            ..params
+        });
+
+        // 换行、右对齐和加色混合
+        draw_text("x1024 combo", TextParams {
+            size: 16.0,
+            position: vec2(60.0, -20.0),
+            align: TextAlign::Right,
+            max_width: Some(60.0),
+            color: Color::new(1.0, 1.0, 0.0, 1.0),
+            blend_mode: BlendMode::Additive,
+            ..params
+        });
+    }) else {
+        return;
+    };
+
+    assert_golden("text", &image);
+
+    // 第一行文字实际覆盖的范围与 measure_text 一致
+    let lit: Vec<(u32, u32)> = (0..SCENE_SIZE.y / 2)
+        .flat_map(|y| (0..SCENE_SIZE.x).map(move |x| (x, y)))
+        .filter(|&(x, y)| image.get_pixel(x, y)[0] > 64)
+        .collect();
+    let (min_x, max_x) = lit.iter().map(|p| p.0).minmax().into_option().unwrap();
+    let (min_y, max_y) = lit.iter().map(|p| p.1).minmax().into_option().unwrap();
+
+    assert!((max_x - min_x + 1) as f32 <= size.x + 1.0);
+    assert!((max_x - min_x + 1) as f32 > size.x - 6.0);
+    assert!(((min_x + max_x) as f32 / 2.0 - 64.0).abs() <= 2.0);
+    assert!(((max_y - min_y + 1) as f32) < size.y);
+}
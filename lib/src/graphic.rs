@@ -5,7 +5,7 @@ use crate::*;
 use anyhow::Result;
 use tokio::sync::watch::error;
 use wgpu::{
-    AddressMode, BindingResource, BlendState, ColorTargetState, ColorWrites, CommandEncoderDescriptor, FragmentState, IndexFormat, LoadOp, MultisampleState, Operations, PrimitiveState, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, ShaderModuleDescriptor, ShaderSource, StoreOp, TextureView, TextureViewDescriptor, VertexState
+    AddressMode, BindingResource, BlendState, ColorTargetState, ColorWrites, CommandEncoderDescriptor, FragmentState, IndexFormat, LoadOp, MultisampleState, Operations, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, ShaderModuleDescriptor, ShaderSource, StoreOp, TextureView, TextureViewDescriptor, VertexState
 };
 
 pub type PipelineMap = HashMap<String, wgpu::RenderPipeline>;
@@ -67,6 +67,7 @@ pub struct GraphicsContext {
 
     pub config: Arc<RwLock<SurfaceConfiguration>>,
     pub textures: Arc<Mutex<TextureMap>>,
+    pub capabilities: Arc<GraphicsCapabilities>,
 }
 
 impl GraphicsContext {
@@ -91,12 +92,10 @@ impl GraphicsContext {
     }
 }
 
-pub fn create_default_rt() {
-    let size = get_window_size();
-
+pub fn create_default_rt(size: UVec2) {
     UserRenderTarget::new(&RenderTargetParams {
         label: "Default RT".to_owned(),
-        size: uvec2(size.width.max(1), size.height.max(1)),
+        size: uvec2(size.x.max(1), size.y.max(1)),
     });
 }
 
@@ -105,6 +104,8 @@ pub struct WgpuRenderer {
 
     pub pipelines: PipelineMap,
     pub user_pipelines: UserPipelineMap,
+    /// 按着色器实例缓存的 uniform 绑定组
+    pub user_bind_groups: HashMap<ShaderInstanceId, UserBindGroup>,
     pub shaders: Arc<Mutex<ShaderMap>>,
 
     pub vertex_buffer: SizedBuffer,
@@ -128,9 +129,26 @@ pub struct WgpuRenderer {
 }
 
 impl WgpuRenderer {
-    pub async fn new(window: Arc<Window>) {
+    pub async fn new(window: Arc<Window>, graphics_config: &GraphicsConfig) -> Result<()> {
         let size = window.inner_size();
-        let context = create_graphics_context(window).await;
+        let context = create_graphics_context(window, graphics_config).await?;
+
+        Self::init(context, uvec2(size.width, size.height));
+
+        Ok(())
+    }
+
+    /// 不依赖窗口创建渲染器，场景只渲染到固定尺寸的默认 RT（`RenderTargetId(0)`），不会 present。
+    pub async fn new_headless(size: UVec2, graphics_config: &GraphicsConfig) -> Result<()> {
+        let context = create_headless_graphics_context(size, graphics_config).await?;
+
+        Self::init(context, size);
+
+        Ok(())
+    }
+
+    fn init(context: GraphicsContext, size: UVec2) {
+        clamp_sample_count(&context.capabilities);
 
         trace!("Loading builtin engine textures");
 
@@ -229,7 +247,7 @@ impl WgpuRenderer {
         let error_shader_id =
             create_shader1(&mut shaders, "error", &include_str!("shaders/error.wgsl")).unwrap();
 
-        let size = uvec2(size.width.max(1), size.height.max(1));
+        let size = uvec2(size.x.max(1), size.y.max(1));
 
         let wr = Arc::new(RwLock::new(Self {
             size,
@@ -241,12 +259,13 @@ impl WgpuRenderer {
 
             pipelines: HashMap::new(),
             user_pipelines: HashMap::new(),
+            user_bind_groups: HashMap::new(),
 
             shaders: Arc::new(Mutex::new(shaders)),
 
             vertex_buffer,
             index_buffer,
-            enable_z_buffer: true,
+            enable_z_buffer: get_run_time_context().read().enable_z_buffer,
 
             sprite_shader_id,
             error_shader_id,
@@ -261,7 +280,7 @@ impl WgpuRenderer {
 
         let _ = WGPU_RENDERER.set(wr.clone());
 
-        create_default_rt();
+        create_default_rt(size);
 
         wr.write().resize(size, true);
     }
@@ -270,6 +289,7 @@ impl WgpuRenderer {
         if !is_first && self.size == size { return; }
 
         self.size = size;
+        set_render_size(size);
 
         // 相机固定尺寸不参与缩放
         // if let Some(main_camera) = &get_run_time_context().read().main_camera {
@@ -357,7 +377,7 @@ impl WgpuRenderer {
         }
 
         // 更新零号rt
-        let size = get_window_size();
+        let size = self.size;
 
         let rts = get_global_render_targets().read();
         let default_rt = rts.get(&RenderTargetId(0)).unwrap();
@@ -367,7 +387,7 @@ impl WgpuRenderer {
             &self.texture_layout,
             &RenderTargetParams {
                 label: "Default RT".to_owned(),
-                size: uvec2(size.width.max(1), size.height.max(1)),
+                size: uvec2(size.x.max(1), size.y.max(1)),
             },
         );
     }
@@ -405,10 +425,11 @@ impl WgpuRenderer {
                 )),
             });
 
-        let (color_view, depth_view) = if get_run_time_context().read().sample_count != Msaa::Off {
-            (&cur_rt.msaa_view, &cur_rt.msaa_depth_view)
+        // 深度在每帧绘制这个 RT 的 pass 开始时清除，这里只清颜色
+        let color_view = if get_run_time_context().read().sample_count != Msaa::Off {
+            &cur_rt.msaa_view
         } else {
-            (&cur_rt.resolve_view, &cur_rt.msaa_depth_view)
+            &cur_rt.resolve_view
         };
 
         encoder.begin_render_pass(&RenderPassDescriptor {
@@ -421,18 +442,6 @@ impl WgpuRenderer {
                     store: StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: if self.enable_z_buffer {
-                Some(RenderPassDepthStencilAttachment {
-                    view: depth_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                })
-            } else {
-                None
-            },
             ..Default::default()
         });
 
@@ -440,21 +449,20 @@ impl WgpuRenderer {
     }
 
     pub(crate) fn draw(&mut self) {
-        let output = match self.context.surface.as_ref().unwrap().get_current_texture() {
+        let Some(surface) = self.context.surface.clone() else {
+            // 没有 Surface（headless）时只渲染到默认 RT
+            self.draw_offscreen();
+            return;
+        };
+
+        let output = match surface.get_current_texture() {
             Ok(t) => t,
             Err(_) => return,
         };
         let surface_view = output.texture.create_view(&Default::default());
 
-        let sample_count = get_run_time_context().read().sample_count;
-
         // 1. 场景渲染
-        run_batched_render_passes(
-            self,
-            sample_count,
-            self.sprite_shader_id,
-            self.error_shader_id,
-        );
+        self.draw_offscreen();
 
         let rts = get_global_render_targets().read();
 
@@ -525,6 +533,18 @@ impl WgpuRenderer {
         output.present();
     }
 
+    /// 执行本帧所有批次的渲染，结果留在各个 RT 中，不做 blit 和 present。
+    pub fn draw_offscreen(&mut self) {
+        let sample_count = get_run_time_context().read().sample_count;
+
+        run_batched_render_passes(
+            self,
+            sample_count,
+            self.sprite_shader_id,
+            self.error_shader_id,
+        );
+    }
+
     pub(crate) fn end_frame(&mut self) {
         self.clear_buffer();
     }
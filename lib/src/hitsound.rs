@@ -0,0 +1,269 @@
+//! 打击音效和按键音。
+//!
+//! 音效预先解码进 [`SampleBank`]，触发时直接交给混音器；按时间预约的音效
+//! 会被换算成混音器的帧号，在对应的那一帧开始发声，而不是等到下一帧渲染。
+
+use crate::*;
+
+use anyhow::Result;
+
+#[derive(Clone, Debug)]
+pub struct HitSample {
+    pub sound: Arc<SoundData>,
+    pub volume: f32,
+    /// 同一个音效最多同时发声的数量，超出时抢占最早开始的
+    pub max_voices: usize,
+    group_id: u64,
+}
+
+/// 预先解码的音效表
+#[derive(Debug, Default)]
+pub struct SampleBank {
+    samples: HashMap<String, HitSample>,
+    next_group_id: u64,
+}
+
+impl SampleBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, sound: SoundData, max_voices: usize) {
+        // 替换同名音效时沿用分组，正在播放的旧声部仍然计入复音数
+        let group_id = match self.samples.get(name) {
+            Some(sample) => sample.group_id,
+            None => {
+                self.next_group_id += 1;
+                self.next_group_id
+            }
+        };
+
+        self.samples.insert(
+            name.to_owned(),
+            HitSample {
+                sound: Arc::new(sound),
+                volume: 1.0,
+                max_voices,
+                group_id,
+            },
+        );
+    }
+
+    pub fn load_from_bytes(&mut self, name: &str, bytes: &[u8], max_voices: usize) -> Result<()> {
+        let sound = decode_sound(bytes.to_vec(), None)?;
+        self.insert(name, sound, max_voices);
+        Ok(())
+    }
+
+    pub fn load(&mut self, name: &str, path: impl AsRef<std::path::Path>, max_voices: usize) -> Result<()> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str());
+        let sound = decode_sound(std::fs::read(path)?, extension)?;
+
+        self.insert(name, sound, max_voices);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.samples.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HitSample> {
+        self.samples.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut HitSample> {
+        self.samples.get_mut(name)
+    }
+
+    /// 从混音器下一次输出的第一帧开始播放
+    pub fn play(&self, mixer: &mut Mixer, name: &str, params: PlaySoundParams) -> Option<VoiceId> {
+        let start_frame = mixer.frames_mixed();
+        self.play_at_frame(mixer, name, params, start_frame)
+    }
+
+    /// 在帧计时器时间 `game_time` 发声，混音器还没有时间锚点或时间已过时立即播放
+    pub fn schedule(
+        &self,
+        mixer: &mut Mixer,
+        name: &str,
+        game_time: f64,
+        params: PlaySoundParams,
+    ) -> Option<VoiceId> {
+        let start_frame = mixer
+            .game_time_to_frame(game_time)
+            .unwrap_or(0)
+            .max(mixer.frames_mixed());
+
+        self.play_at_frame(mixer, name, params, start_frame)
+    }
+
+    pub fn play_at_frame(
+        &self,
+        mixer: &mut Mixer,
+        name: &str,
+        params: PlaySoundParams,
+        start_frame: u64,
+    ) -> Option<VoiceId> {
+        let Some(sample) = self.samples.get(name) else {
+            error!("Hit sound '{}' not loaded", name);
+            return None;
+        };
+
+        Some(mixer.schedule_sound(
+            sample.sound.clone(),
+            PlaySoundParams {
+                volume: params.volume * sample.volume,
+                ..params
+            },
+            start_frame,
+            Some(VoiceGroup {
+                id: sample.group_id,
+                max_voices: sample.max_voices,
+            }),
+        ))
+    }
+}
+
+static HIT_SOUNDS: Lazy<RwLock<SampleBank>> = Lazy::new(|| RwLock::new(SampleBank::new()));
+
+pub fn get_hit_sounds() -> &'static RwLock<SampleBank> {
+    &HIT_SOUNDS
+}
+
+pub fn load_hit_sound(name: &str, path: impl AsRef<std::path::Path>, max_voices: usize) -> Result<()> {
+    HIT_SOUNDS.write().load(name, path, max_voices)
+}
+
+pub fn load_hit_sound_from_bytes(name: &str, bytes: &[u8], max_voices: usize) -> Result<()> {
+    HIT_SOUNDS.write().load_from_bytes(name, bytes, max_voices)
+}
+
+pub fn unload_hit_sound(name: &str) {
+    HIT_SOUNDS.write().remove(name);
+}
+
+/// 立即播放打击音效，在按下的那一帧调用
+pub fn play_hit_sound(name: &str) -> Option<VoiceId> {
+    let mixer = get_global_mixer();
+    let mut mixer = mixer.lock();
+
+    HIT_SOUNDS
+        .read()
+        .play(&mut mixer, name, PlaySoundParams::default())
+}
+
+/// 在帧计时器时间 `game_time`（与 `get_time()` 同一时间轴）播放
+pub fn schedule_hit_sound(name: &str, game_time: f64, params: PlaySoundParams) -> Option<VoiceId> {
+    let mixer = get_global_mixer();
+    let mut mixer = mixer.lock();
+
+    HIT_SOUNDS.read().schedule(&mut mixer, name, game_time, params)
+}
+
+/// 在谱面的歌曲时间播放按键音
+pub fn schedule_keysound(name: &str, song_time: f64) -> Option<VoiceId> {
+    schedule_hit_sound(name, song_time_to_game_time(song_time), PlaySoundParams::default())
+}
+
+#[cfg(test)]
+fn impulse_sound(value: f32) -> SoundData {
+    let mut frames = vec![[0.0; 2]; 64];
+    frames[0] = [value, value];
+
+    SoundData {
+        sample_rate: 48000,
+        frames,
+    }
+}
+
+#[cfg(test)]
+fn render_offline(mixer: &mut Mixer, frames: usize, block: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(frames * AUDIO_CHANNELS);
+    let mut buffer = vec![0.0; block * AUDIO_CHANNELS];
+
+    while out.len() < frames * AUDIO_CHANNELS {
+        mixer.mix(&mut buffer);
+        out.extend_from_slice(&buffer);
+    }
+
+    out
+}
+
+#[cfg(test)]
+fn onsets(samples: &[f32]) -> Vec<(usize, f32)> {
+    samples
+        .chunks_exact(AUDIO_CHANNELS)
+        .enumerate()
+        .filter(|(_, frame)| frame[0] != 0.0)
+        .map(|(i, frame)| (i, frame[0]))
+        .collect()
+}
+
+#[test]
+fn hit_sounds_are_sample_accurate() {
+    let mut mixer = Mixer::new(48000);
+    let mut bank = SampleBank::new();
+    bank.insert("tap", impulse_sound(0.5), 8);
+
+    // 第 0 帧对应帧计时器的 2 秒
+    let mut first = vec![0.0; 100 * AUDIO_CHANNELS];
+    mixer.mix_at(&mut first, 2.0);
+
+    bank.schedule(&mut mixer, "tap", 2.0 + 1000.0 / 48000.0, PlaySoundParams::default());
+    bank.schedule(&mut mixer, "tap", 2.0 + 1234.0 / 48000.0, PlaySoundParams::default());
+    // 已经过去的时间立即播放
+    bank.schedule(&mut mixer, "tap", 1.0, PlaySoundParams::default());
+
+    // 块大小不影响发声位置
+    let out = render_offline(&mut mixer, 2000, 256);
+    assert_eq!(onsets(&out), vec![(0, 0.5), (900, 0.5), (1134, 0.5)]);
+}
+
+#[test]
+fn hit_sound_polyphony_steals_oldest() {
+    let mut mixer = Mixer::new(48000);
+    let mut bank = SampleBank::new();
+    bank.insert("tap", impulse_sound(0.25), 2);
+    bank.insert("clap", impulse_sound(1.0), 4);
+
+    for frame in [10, 20, 30] {
+        bank.play_at_frame(&mut mixer, "tap", PlaySoundParams::default(), frame);
+    }
+    bank.play_at_frame(&mut mixer, "clap", PlaySoundParams::default(), 40);
+
+    // 预约的声部开始发声时才抢占：tap 的复音数为 2，最早的 10 被抢占，clap 不受影响
+    assert_eq!(mixer.active_voices(), 4);
+    let out = render_offline(&mut mixer, 100, 64);
+    assert_eq!(onsets(&out), vec![(20, 0.25), (30, 0.25), (40, 1.0)]);
+
+    // 总声部数上限同样抢占最早的
+    mixer.max_voices = 2;
+    for frame in [200, 210, 220] {
+        bank.play_at_frame(&mut mixer, "clap", PlaySoundParams::default(), frame);
+    }
+    let out = render_offline(&mut mixer, 200, 64);
+    assert_eq!(onsets(&out), vec![(210 - 128, 1.0), (220 - 128, 1.0)]);
+}
+
+#[test]
+fn scheduled_hit_sounds_do_not_steal_playing_voices() {
+    let mut mixer = Mixer::new(48000);
+    let mut bank = SampleBank::new();
+    bank.insert("tap", impulse_sound(0.25), 8);
+    mixer.max_voices = 2;
+
+    let music = Arc::new(SoundData {
+        sample_rate: 48000,
+        frames: vec![[0.5; 2]; 4000],
+    });
+    mixer.play_sound(music, PlaySoundParams::default());
+
+    // 提前预约一串音效，开始发声之前不计入复音数
+    for frame in [1000, 1010, 1020] {
+        bank.play_at_frame(&mut mixer, "tap", PlaySoundParams::default(), frame);
+    }
+
+    let out = render_offline(&mut mixer, 960, 64);
+    assert!(out.iter().all(|&sample| sample == 0.5));
+}
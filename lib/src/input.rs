@@ -0,0 +1,578 @@
+//! 键盘、鼠标和多点触控输入。
+//!
+//! winit 线程在 `App::window_event` 中把事件写入全局状态，游戏循环在每帧
+//! `GameLoop::update` 之前调用 [`begin_input_frame`] 生成本帧快照，
+//! 因此 `update` 中读取到的状态在整帧内保持不变。
+//!
+//! 每个输入事件还会在 winit 线程收到时打上时间戳（与 `get_time()` 同一时间轴），
+//! 按顺序放入 [`input_events`]，判定可以使用真实的按下时间而不是帧时间。
+
+use crate::*;
+
+use std::collections::HashSet;
+
+pub use winit::keyboard::KeyCode;
+use winit::keyboard::PhysicalKey;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TouchState {
+    /// 本帧刚按下
+    Began,
+    Moved,
+    Stationary,
+    /// 本帧抬起，下一帧移除
+    Ended,
+    Cancelled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEventKind {
+    KeyDown(KeyCode),
+    KeyUp(KeyCode),
+    /// 位置为窗口像素坐标
+    MouseDown(MouseButton, Vec2),
+    MouseUp(MouseButton, Vec2),
+    MouseMove(Vec2),
+    /// `id` 与 [`TouchPoint::id`] 相同
+    TouchBegan { id: u64, position: Vec2 },
+    TouchMoved { id: u64, position: Vec2 },
+    TouchEnded { id: u64, position: Vec2 },
+    TouchCancelled { id: u64, position: Vec2 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    /// 与 `get_time()` 同一时间轴的时间（秒）
+    pub time: f64,
+    pub kind: InputEventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TouchPoint {
+    /// 从按下到抬起保持不变，不会被之后的触点复用
+    pub id: u64,
+    pub phase: TouchState,
+    /// 窗口像素坐标，原点在左上角
+    pub position: Vec2,
+    pub start_position: Vec2,
+    /// 本帧刚按下。一帧内按下又抬起的触点 `phase` 为 `Ended`，这里仍然为 true
+    pub began: bool,
+}
+
+impl TouchPoint {
+    pub fn is_active(&self) -> bool {
+        !matches!(self.phase, TouchState::Ended | TouchState::Cancelled)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ButtonSet<T> {
+    down: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T> Default for ButtonSet<T> {
+    fn default() -> Self {
+        Self {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Copy + Eq + std::hash::Hash> ButtonSet<T> {
+    // 返回是否为新的按下，按住时系统的重复事件不算
+    fn press(&mut self, button: T) -> bool {
+        let new = self.down.insert(button);
+        if new {
+            self.pressed.insert(button);
+        }
+        new
+    }
+
+    fn release(&mut self, button: T) -> bool {
+        let was_down = self.down.remove(&button);
+        if was_down {
+            self.released.insert(button);
+        }
+        was_down
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.down.drain());
+    }
+
+    // 取出两帧之间的边沿，一帧内按下又抬起的按键仍然算作按下
+    fn take_frame(&mut self) -> ButtonSet<T> {
+        ButtonSet {
+            down: self.down.clone(),
+            pressed: std::mem::take(&mut self.pressed),
+            released: std::mem::take(&mut self.released),
+        }
+    }
+}
+
+/// 某一帧的输入快照
+#[derive(Clone, Debug, Default)]
+pub struct FrameInput {
+    keys: ButtonSet<KeyCode>,
+    mouse_buttons: ButtonSet<MouseButton>,
+    pub mouse_position: Vec2,
+    /// 本帧滚轮的累计值（行）
+    pub mouse_wheel: Vec2,
+    pub touches: Vec<TouchPoint>,
+    /// 上一帧之后发生的事件，按时间排序
+    pub events: Vec<InputEvent>,
+}
+
+impl FrameInput {
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys.down.contains(&key)
+    }
+
+    pub fn is_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys.pressed.contains(&key)
+    }
+
+    pub fn is_key_released(&self, key: KeyCode) -> bool {
+        self.keys.released.contains(&key)
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.down.contains(&button)
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed.contains(&button)
+    }
+
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.released.contains(&button)
+    }
+}
+
+/// winit 线程写入的实时状态
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys: ButtonSet<KeyCode>,
+    mouse_buttons: ButtonSet<MouseButton>,
+    mouse_position: Vec2,
+    mouse_wheel: Vec2,
+
+    // winit 的触点 id（Android 上是 pointer id，会被复用）到稳定 id 的映射
+    finger_ids: HashMap<u64, u64>,
+    next_touch_id: u64,
+    touches: Vec<TouchPoint>,
+
+    events: Vec<InputEvent>,
+
+    frame: FrameInput,
+}
+
+impl InputState {
+    /// 处理一个窗口事件，时间戳取收到事件的时刻，返回是否为输入事件
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        self.handle_window_event_at(event, get_precise_time())
+    }
+
+    /// 以指定的时间戳处理窗口事件，用于回放等注入事件的场景
+    pub fn handle_window_event_at(&mut self, event: &WindowEvent, time: f64) -> bool {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => {
+                            if self.keys.press(code) {
+                                self.push_event(time, InputEventKind::KeyDown(code));
+                            }
+                        }
+                        ElementState::Released => {
+                            if self.keys.release(code) {
+                                self.push_event(time, InputEventKind::KeyUp(code));
+                            }
+                        }
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let position = self.mouse_position;
+
+                match state {
+                    ElementState::Pressed => {
+                        if self.mouse_buttons.press(*button) {
+                            self.push_event(time, InputEventKind::MouseDown(*button, position));
+                        }
+                    }
+                    ElementState::Released => {
+                        if self.mouse_buttons.release(*button) {
+                            self.push_event(time, InputEventKind::MouseUp(*button, position));
+                        }
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse_position = vec2(position.x as f32, position.y as f32);
+                self.push_event(time, InputEventKind::MouseMove(self.mouse_position));
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.mouse_wheel += match delta {
+                    MouseScrollDelta::LineDelta(x, y) => vec2(*x, *y),
+                    // 按每行 20 像素折算
+                    MouseScrollDelta::PixelDelta(p) => vec2(p.x as f32, p.y as f32) / 20.0,
+                };
+            }
+            WindowEvent::Touch(touch) => {
+                let position = vec2(touch.location.x as f32, touch.location.y as f32);
+                self.handle_touch_at(touch.id, touch.phase, position, time);
+            }
+            // 失去焦点时收不到抬起事件
+            WindowEvent::Focused(false) => {
+                for key in self.keys.down.clone() {
+                    self.keys.release(key);
+                    self.push_event(time, InputEventKind::KeyUp(key));
+                }
+                for button in self.mouse_buttons.down.clone() {
+                    self.mouse_buttons.release(button);
+                    self.push_event(time, InputEventKind::MouseUp(button, self.mouse_position));
+                }
+                self.cancel_touches(time);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// 注入一个已经解析好的输入事件（回放、自动游玩），效果与对应的窗口事件相同。
+    ///
+    /// 注入的触点使用独立的 id 空间，不会和真实手指冲突。
+    pub fn inject_event(&mut self, event: InputEvent) {
+        const INJECTED_FINGER_BASE: u64 = 1 << 63;
+
+        let time = event.time;
+
+        match event.kind {
+            InputEventKind::KeyDown(key) => {
+                if self.keys.press(key) {
+                    self.push_event(time, event.kind);
+                }
+            }
+            InputEventKind::KeyUp(key) => {
+                if self.keys.release(key) {
+                    self.push_event(time, event.kind);
+                }
+            }
+            InputEventKind::MouseDown(button, position) => {
+                self.mouse_position = position;
+                if self.mouse_buttons.press(button) {
+                    self.push_event(time, event.kind);
+                }
+            }
+            InputEventKind::MouseUp(button, position) => {
+                self.mouse_position = position;
+                if self.mouse_buttons.release(button) {
+                    self.push_event(time, event.kind);
+                }
+            }
+            InputEventKind::MouseMove(position) => {
+                self.mouse_position = position;
+                self.push_event(time, event.kind);
+            }
+            InputEventKind::TouchBegan { id, position } => {
+                self.handle_touch_at(INJECTED_FINGER_BASE | id, TouchPhase::Started, position, time)
+            }
+            InputEventKind::TouchMoved { id, position } => {
+                self.handle_touch_at(INJECTED_FINGER_BASE | id, TouchPhase::Moved, position, time)
+            }
+            InputEventKind::TouchEnded { id, position } => {
+                self.handle_touch_at(INJECTED_FINGER_BASE | id, TouchPhase::Ended, position, time)
+            }
+            InputEventKind::TouchCancelled { id, position } => self.handle_touch_at(
+                INJECTED_FINGER_BASE | id,
+                TouchPhase::Cancelled,
+                position,
+                time,
+            ),
+        }
+    }
+
+    fn push_event(&mut self, time: f64, kind: InputEventKind) {
+        // 注入的事件可能早于已有事件，保持队列按时间排序
+        let index = self.events.partition_point(|e| e.time <= time);
+        self.events.insert(index, InputEvent { time, kind });
+    }
+
+    pub fn handle_touch(&mut self, finger_id: u64, phase: TouchPhase, position: Vec2) {
+        self.handle_touch_at(finger_id, phase, position, get_precise_time());
+    }
+
+    pub fn handle_touch_at(&mut self, finger_id: u64, phase: TouchPhase, position: Vec2, time: f64) {
+        if phase == TouchPhase::Started {
+            let id = self.next_touch_id;
+            self.next_touch_id += 1;
+            self.finger_ids.insert(finger_id, id);
+
+            self.touches.push(TouchPoint {
+                id,
+                phase: TouchState::Began,
+                position,
+                start_position: position,
+                began: true,
+            });
+            self.push_event(time, InputEventKind::TouchBegan { id, position });
+            return;
+        }
+
+        let Some(&id) = self.finger_ids.get(&finger_id) else {
+            return;
+        };
+
+        let Some(touch) = self.touches.iter_mut().find(|t| t.id == id) else {
+            return;
+        };
+
+        touch.position = position;
+
+        let kind = match phase {
+            TouchPhase::Moved => {
+                // 本帧刚按下的触点保持 Began，保证游戏能看到按下
+                if touch.phase != TouchState::Began {
+                    touch.phase = TouchState::Moved;
+                }
+                InputEventKind::TouchMoved { id, position }
+            }
+            TouchPhase::Ended => {
+                touch.phase = TouchState::Ended;
+                self.finger_ids.remove(&finger_id);
+                InputEventKind::TouchEnded { id, position }
+            }
+            TouchPhase::Cancelled => {
+                touch.phase = TouchState::Cancelled;
+                self.finger_ids.remove(&finger_id);
+                InputEventKind::TouchCancelled { id, position }
+            }
+            TouchPhase::Started => unreachable!(),
+        };
+
+        self.push_event(time, kind);
+    }
+
+    fn cancel_touches(&mut self, time: f64) {
+        self.finger_ids.clear();
+
+        let mut cancelled = Vec::new();
+        for touch in self.touches.iter_mut().filter(|t| t.is_active()) {
+            touch.phase = TouchState::Cancelled;
+            cancelled.push(InputEventKind::TouchCancelled {
+                id: touch.id,
+                position: touch.position,
+            });
+        }
+
+        for kind in cancelled {
+            self.push_event(time, kind);
+        }
+    }
+
+    /// 生成新一帧的快照并清除上一帧的边沿
+    pub fn begin_frame(&mut self) {
+        self.frame = FrameInput {
+            keys: self.keys.take_frame(),
+            mouse_buttons: self.mouse_buttons.take_frame(),
+            mouse_position: self.mouse_position,
+            mouse_wheel: std::mem::take(&mut self.mouse_wheel),
+            touches: self.touches.clone(),
+            events: std::mem::take(&mut self.events),
+        };
+
+        self.touches.retain(TouchPoint::is_active);
+        for touch in &mut self.touches {
+            touch.phase = TouchState::Stationary;
+            touch.began = false;
+        }
+    }
+
+    pub fn frame(&self) -> &FrameInput {
+        &self.frame
+    }
+}
+
+static INPUT: Lazy<RwLock<InputState>> = Lazy::new(|| RwLock::new(InputState::default()));
+
+pub fn get_input() -> &'static RwLock<InputState> {
+    &INPUT
+}
+
+/// 由游戏循环在 `GameLoop::update` 之前调用
+pub fn begin_input_frame() {
+    INPUT.write().begin_frame();
+}
+
+pub fn is_key_down(key: KeyCode) -> bool {
+    INPUT.read().frame.is_key_down(key)
+}
+
+/// 本帧是否刚按下
+pub fn is_key_pressed(key: KeyCode) -> bool {
+    INPUT.read().frame.is_key_pressed(key)
+}
+
+pub fn is_key_released(key: KeyCode) -> bool {
+    INPUT.read().frame.is_key_released(key)
+}
+
+pub fn is_mouse_button_down(button: MouseButton) -> bool {
+    INPUT.read().frame.is_mouse_button_down(button)
+}
+
+pub fn is_mouse_button_pressed(button: MouseButton) -> bool {
+    INPUT.read().frame.is_mouse_button_pressed(button)
+}
+
+pub fn is_mouse_button_released(button: MouseButton) -> bool {
+    INPUT.read().frame.is_mouse_button_released(button)
+}
+
+/// 窗口像素坐标，原点在左上角
+pub fn mouse_position() -> Vec2 {
+    INPUT.read().frame.mouse_position
+}
+
+pub fn mouse_wheel() -> Vec2 {
+    INPUT.read().frame.mouse_wheel
+}
+
+/// 本帧的所有触点，包括本帧抬起的
+pub fn touches() -> Vec<TouchPoint> {
+    INPUT.read().frame.touches.clone()
+}
+
+/// 上一帧之后的输入事件，按时间排序。
+///
+/// 时间戳与 `get_time()` 同一时间轴，可以用 `game_time_to_song_time` 换算成歌曲时间。
+pub fn input_events() -> Vec<InputEvent> {
+    INPUT.read().frame.events.clone()
+}
+
+/// 把窗口像素坐标换算到默认 2D 相机的坐标（原点在屏幕中心，y 向上）
+pub fn screen_to_world(position: Vec2) -> Vec2 {
+    let size = get_window_size();
+    let half = vec2(size.width as f32, size.height as f32) / 2.0;
+
+    vec2(position.x - half.x, half.y - position.y)
+}
+
+#[test]
+fn input_key_edges() {
+    let mut input = InputState::default();
+
+    // 一帧内按下又抬起，仍然能看到按下
+    input.keys.press(KeyCode::KeyD);
+    input.keys.press(KeyCode::KeyF);
+    input.keys.release(KeyCode::KeyF);
+    input.begin_frame();
+
+    let frame = input.frame();
+    assert!(frame.is_key_pressed(KeyCode::KeyD) && frame.is_key_down(KeyCode::KeyD));
+    assert!(frame.is_key_pressed(KeyCode::KeyF) && frame.is_key_released(KeyCode::KeyF));
+    assert!(!frame.is_key_down(KeyCode::KeyF));
+
+    // 按住时的重复按下不产生新的边沿
+    input.keys.press(KeyCode::KeyD);
+    input.begin_frame();
+    assert!(!input.frame().is_key_pressed(KeyCode::KeyD));
+    assert!(input.frame().is_key_down(KeyCode::KeyD));
+}
+
+#[test]
+fn input_touch_ids_and_phases() {
+    let mut input = InputState::default();
+
+    input.handle_touch(0, TouchPhase::Started, vec2(10.0, 10.0));
+    input.handle_touch(0, TouchPhase::Moved, vec2(12.0, 10.0));
+    input.handle_touch(1, TouchPhase::Started, vec2(50.0, 50.0));
+    input.begin_frame();
+
+    let touches = &input.frame().touches;
+    assert_eq!(touches.len(), 2);
+    assert_eq!(touches[0].phase, TouchState::Began);
+    assert_eq!(touches[0].position, vec2(12.0, 10.0));
+    assert_eq!(touches[0].start_position, vec2(10.0, 10.0));
+
+    input.handle_touch(0, TouchPhase::Ended, vec2(12.0, 10.0));
+    input.begin_frame();
+
+    let touches = &input.frame().touches;
+    assert_eq!(touches[0].phase, TouchState::Ended);
+    assert!(!touches[0].began);
+    assert_eq!(touches[1].phase, TouchState::Stationary);
+
+    // Android 会复用 pointer id，新的触点拿到新的 id
+    input.handle_touch(0, TouchPhase::Started, vec2(0.0, 0.0));
+    input.begin_frame();
+
+    let ids: Vec<_> = input.frame().touches.iter().map(|t| t.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn input_event_queue_is_timestamped_and_ordered() {
+    let mut input = InputState::default();
+
+    input.handle_touch_at(7, TouchPhase::Started, vec2(1.0, 2.0), 1.010);
+    input.handle_touch_at(7, TouchPhase::Ended, vec2(1.0, 2.0), 1.030);
+    // 晚到的注入事件按时间插入
+    input.handle_touch_at(8, TouchPhase::Started, vec2(5.0, 5.0), 1.020);
+    input.begin_frame();
+
+    let times: Vec<_> = input.frame().events.iter().map(|e| e.time).collect();
+    assert_eq!(times, vec![1.010, 1.020, 1.030]);
+    assert_eq!(
+        input.frame().events[2].kind,
+        InputEventKind::TouchEnded { id: 0, position: vec2(1.0, 2.0) }
+    );
+
+    // 事件只在一帧内可见
+    input.begin_frame();
+    assert!(input.frame().events.is_empty());
+}
+
+#[test]
+fn input_fast_tap_and_focus_loss() {
+    let mut input = InputState::default();
+
+    // 一帧内按下又抬起，仍然能看到按下
+    input.handle_touch(0, TouchPhase::Started, vec2(10.0, 10.0));
+    input.handle_touch(0, TouchPhase::Ended, vec2(10.0, 10.0));
+    input.handle_touch(1, TouchPhase::Started, vec2(50.0, 50.0));
+    input.begin_frame();
+
+    let touches = &input.frame().touches;
+    assert_eq!(touches[0].phase, TouchState::Ended);
+    assert!(touches[0].began);
+    assert!(touches[1].began);
+
+    // 失去焦点时收不到抬起事件，按住的触点被取消
+    input.handle_window_event_at(&WindowEvent::Focused(false), 1.0);
+    input.begin_frame();
+
+    let touches = &input.frame().touches;
+    assert_eq!(touches.len(), 1);
+    assert_eq!(touches[0].phase, TouchState::Cancelled);
+    assert_eq!(
+        input.frame().events,
+        vec![InputEvent {
+            time: 1.0,
+            kind: InputEventKind::TouchCancelled { id: 1, position: vec2(50.0, 50.0) },
+        }]
+    );
+
+    input.begin_frame();
+    assert!(input.frame().touches.is_empty());
+
+    // 之后同一个手指的事件被忽略
+    input.handle_touch(1, TouchPhase::Ended, vec2(50.0, 50.0));
+    assert!(input.touches.is_empty());
+}
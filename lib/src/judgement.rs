@@ -0,0 +1,774 @@
+//! 判定与计分。
+//!
+//! [`JudgementEngine`] 只处理歌曲时间和外部传入的输入，不读取全局时钟，
+//! 同样的谱面和输入序列总是得到同样的结果。
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Bad,
+    Miss,
+}
+
+impl Judgement {
+    pub const ALL: [Judgement; 5] = [
+        Judgement::Perfect,
+        Judgement::Great,
+        Judgement::Good,
+        Judgement::Bad,
+        Judgement::Miss,
+    ];
+
+    /// Bad 和 Miss 会断连
+    pub fn breaks_combo(&self) -> bool {
+        matches!(self, Judgement::Bad | Judgement::Miss)
+    }
+}
+
+/// 各判定的时间窗口（秒，单侧），不需要 Great 时把它设为和 Perfect 相同即可
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JudgementWindows {
+    pub perfect: f64,
+    pub great: f64,
+    pub good: f64,
+    pub bad: f64,
+}
+
+impl Default for JudgementWindows {
+    fn default() -> Self {
+        Self {
+            perfect: 0.045,
+            great: 0.09,
+            good: 0.135,
+            bad: 0.18,
+        }
+    }
+}
+
+impl JudgementWindows {
+    /// 按偏差判定，超出 Bad 窗口时返回 `None`
+    pub fn judge(&self, offset: f64) -> Option<Judgement> {
+        let offset = offset.abs();
+
+        if offset <= self.perfect {
+            Some(Judgement::Perfect)
+        } else if offset <= self.great {
+            Some(Judgement::Great)
+        } else if offset <= self.good {
+            Some(Judgement::Good)
+        } else if offset <= self.bad {
+            Some(Judgement::Bad)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JudgementConfig {
+    pub windows: JudgementWindows,
+    /// 长条在结束前这么久（秒）以内松开也算完成
+    pub hold_release_tolerance: f64,
+    /// 设置后还要求输入的 x 与音符的 x 足够接近，用于判定线类的谱面
+    pub x_tolerance: Option<f32>,
+    /// 按住后移动超过该距离算一次 flick，单位与输入位置相同
+    pub flick_distance: f32,
+}
+
+impl Default for JudgementConfig {
+    fn default() -> Self {
+        Self {
+            windows: JudgementWindows::default(),
+            hold_release_tolerance: 0.1,
+            x_tolerance: None,
+            flick_distance: 40.0,
+        }
+    }
+}
+
+/// 区分同时按下的多个手指或按键
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PointerId {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Touch(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JudgeAction {
+    Press,
+    Move,
+    Release,
+}
+
+/// 已经换算到歌曲时间和轨道的输入
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JudgeInput {
+    /// 歌曲时间（秒）
+    pub time: f64,
+    pub pointer: PointerId,
+    pub action: JudgeAction,
+    pub lane: u32,
+    /// 轨道上的横向位置，对应 [`Note::x`]
+    pub x: f32,
+    /// 用于 flick 的位置，单位自定，和 `flick_distance` 一致即可
+    pub position: Vec2,
+}
+
+impl JudgeInput {
+    /// 从输入事件生成判定输入。
+    ///
+    /// `keys` 的下标即按键对应的轨道，`locate` 把窗口坐标换算成（轨道，x），
+    /// 返回 `None` 表示不在任何轨道上。
+    pub fn from_event(
+        event: &InputEvent,
+        song_time: f64,
+        keys: &[KeyCode],
+        locate: impl Fn(Vec2) -> Option<(u32, f32)>,
+    ) -> Option<JudgeInput> {
+        let key_input = |key: KeyCode, action| {
+            let lane = keys.iter().position(|k| *k == key)? as u32;
+
+            Some(JudgeInput {
+                time: song_time,
+                pointer: PointerId::Key(key),
+                action,
+                lane,
+                x: 0.0,
+                position: Vec2::ZERO,
+            })
+        };
+
+        let pointer_input = |pointer, action, position: Vec2| {
+            // 松开时即使不在轨道上也要通知，否则长条不会结束
+            let (lane, x) = match locate(position) {
+                Some(located) => located,
+                None if action == JudgeAction::Release => (u32::MAX, 0.0),
+                None => return None,
+            };
+
+            Some(JudgeInput {
+                time: song_time,
+                pointer,
+                action,
+                lane,
+                x,
+                position,
+            })
+        };
+
+        match event.kind {
+            InputEventKind::KeyDown(key) => key_input(key, JudgeAction::Press),
+            InputEventKind::KeyUp(key) => key_input(key, JudgeAction::Release),
+            InputEventKind::MouseDown(button, p) => {
+                pointer_input(PointerId::Mouse(button), JudgeAction::Press, p)
+            }
+            InputEventKind::MouseUp(button, p) => {
+                pointer_input(PointerId::Mouse(button), JudgeAction::Release, p)
+            }
+            InputEventKind::MouseMove(p) => {
+                pointer_input(PointerId::Mouse(MouseButton::Left), JudgeAction::Move, p)
+            }
+            InputEventKind::TouchBegan { id, position } => {
+                pointer_input(PointerId::Touch(id), JudgeAction::Press, position)
+            }
+            InputEventKind::TouchMoved { id, position } => {
+                pointer_input(PointerId::Touch(id), JudgeAction::Move, position)
+            }
+            InputEventKind::TouchEnded { id, position }
+            | InputEventKind::TouchCancelled { id, position } => {
+                pointer_input(PointerId::Touch(id), JudgeAction::Release, position)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JudgeResult {
+    /// 在 `Chart::notes` 中的下标
+    pub note_index: usize,
+    pub judgement: Judgement,
+    /// 输入时间减去音符时间（秒），没有对应输入的 Miss 为 `None`
+    pub offset: Option<f64>,
+    /// 产生结果的歌曲时间
+    pub time: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScoreStats {
+    pub perfect: u32,
+    pub great: u32,
+    pub good: u32,
+    pub bad: u32,
+    pub miss: u32,
+    pub combo: u32,
+    pub max_combo: u32,
+}
+
+impl ScoreStats {
+    pub fn count(&self, judgement: Judgement) -> u32 {
+        match judgement {
+            Judgement::Perfect => self.perfect,
+            Judgement::Great => self.great,
+            Judgement::Good => self.good,
+            Judgement::Bad => self.bad,
+            Judgement::Miss => self.miss,
+        }
+    }
+
+    pub fn judged(&self) -> u32 {
+        Judgement::ALL.iter().map(|j| self.count(*j)).sum()
+    }
+
+    fn record(&mut self, judgement: Judgement) {
+        *match judgement {
+            Judgement::Perfect => &mut self.perfect,
+            Judgement::Great => &mut self.great,
+            Judgement::Good => &mut self.good,
+            Judgement::Bad => &mut self.bad,
+            Judgement::Miss => &mut self.miss,
+        } += 1;
+
+        if judgement.breaks_combo() {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+    }
+}
+
+/// 可替换的计分公式
+pub trait ScoringFormula: Send + Sync {
+    /// 已判定部分的准确率，0..1
+    fn accuracy(&self, stats: &ScoreStats) -> f64;
+    /// 按全谱 `total_notes` 个音符计算的分数
+    fn score(&self, stats: &ScoreStats, total_notes: usize) -> u32;
+}
+
+/// 按判定加权的分数，再加上一部分最大连击分
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightedScoring {
+    /// 依次为 Perfect、Great、Good、Bad、Miss 的权重
+    pub weights: [f64; 5],
+    pub max_score: u32,
+    /// 最大连击占总分的比例
+    pub combo_ratio: f64,
+}
+
+impl Default for WeightedScoring {
+    fn default() -> Self {
+        Self {
+            weights: [1.0, 0.75, 0.5, 0.25, 0.0],
+            max_score: 1_000_000,
+            combo_ratio: 0.0,
+        }
+    }
+}
+
+impl WeightedScoring {
+    /// Phigros 的计分：Good 算 65%，最大连击占 10%
+    pub fn phigros() -> Self {
+        Self {
+            weights: [1.0, 1.0, 0.65, 0.0, 0.0],
+            max_score: 1_000_000,
+            combo_ratio: 0.1,
+        }
+    }
+
+    fn weighted(&self, stats: &ScoreStats) -> f64 {
+        Judgement::ALL
+            .iter()
+            .zip(self.weights)
+            .map(|(j, w)| stats.count(*j) as f64 * w)
+            .sum()
+    }
+}
+
+impl ScoringFormula for WeightedScoring {
+    fn accuracy(&self, stats: &ScoreStats) -> f64 {
+        match stats.judged() {
+            0 => 1.0,
+            judged => self.weighted(stats) / judged as f64,
+        }
+    }
+
+    fn score(&self, stats: &ScoreStats, total_notes: usize) -> u32 {
+        if total_notes == 0 {
+            return 0;
+        }
+
+        let total = total_notes as f64;
+        let ratio = (1.0 - self.combo_ratio) * self.weighted(stats) / total
+            + self.combo_ratio * stats.max_combo as f64 / total;
+
+        (ratio * self.max_score as f64).round() as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NoteState {
+    Pending,
+    /// 长条头部已判定，等待松开或结束
+    Holding { pointer: PointerId, head: Judgement, offset: f64 },
+    /// drag/flick 已满足条件，到音符时间时给出结果
+    Caught { offset: f64 },
+    Done,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pointer {
+    id: PointerId,
+    lane: u32,
+    x: f32,
+    // flick 的起点，触发一次后重置到当前位置
+    flick_anchor: Vec2,
+    flicked_at: Option<f64>,
+}
+
+pub struct JudgementEngine {
+    pub config: JudgementConfig,
+    formula: Box<dyn ScoringFormula>,
+    notes: Vec<Note>,
+    states: Vec<NoteState>,
+    // 之前的音符都已经判定完
+    first_pending: usize,
+    pointers: Vec<Pointer>,
+    stats: ScoreStats,
+    results: Vec<JudgeResult>,
+    now: f64,
+}
+
+impl JudgementEngine {
+    pub fn new(chart: &Chart, config: JudgementConfig) -> Self {
+        Self {
+            config,
+            formula: Box::new(WeightedScoring::default()),
+            notes: chart.notes.clone(),
+            states: vec![NoteState::Pending; chart.notes.len()],
+            first_pending: 0,
+            pointers: Vec::new(),
+            stats: ScoreStats::default(),
+            results: Vec::new(),
+            now: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn with_formula(mut self, formula: impl ScoringFormula + 'static) -> Self {
+        self.formula = Box::new(formula);
+        self
+    }
+
+    pub fn stats(&self) -> &ScoreStats {
+        &self.stats
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.formula.accuracy(&self.stats)
+    }
+
+    pub fn score(&self) -> u32 {
+        self.formula.score(&self.stats, self.notes.len())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.first_pending >= self.notes.len()
+    }
+
+    /// 取出上次调用之后产生的结果，按产生顺序排列
+    pub fn drain_results(&mut self) -> Vec<JudgeResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    fn lane_matches(&self, note: &Note, lane: u32, x: f32) -> bool {
+        note.lane == lane
+            && self
+                .config
+                .x_tolerance
+                .is_none_or(|tolerance| (note.x - x).abs() <= tolerance)
+    }
+
+    fn finish(&mut self, index: usize, judgement: Judgement, offset: Option<f64>, time: f64) {
+        self.states[index] = NoteState::Done;
+        self.stats.record(judgement);
+        self.results.push(JudgeResult {
+            note_index: index,
+            judgement,
+            offset,
+            time,
+        });
+
+        while self.first_pending < self.notes.len()
+            && self.states[self.first_pending] == NoteState::Done
+        {
+            self.first_pending += 1;
+        }
+    }
+
+    /// 推进到歌曲时间 `now`：结算到时的 drag/flick 和长条，超时的音符记为 Miss
+    pub fn update(&mut self, now: f64) {
+        if now < self.now {
+            return;
+        }
+        self.now = now;
+
+        let windows = self.config.windows;
+
+        for i in self.first_pending..self.notes.len() {
+            let note = self.notes[i];
+
+            if note.time - windows.bad > now {
+                break;
+            }
+
+            match self.states[i] {
+                NoteState::Pending => match note.kind {
+                    NoteKind::Drag => {
+                        // 判定时刻已经按在轨道上的手指直接接住 drag
+                        if note.time <= now && self.pointer_in_lane(&note) {
+                            self.finish(i, Judgement::Perfect, Some(0.0), note.time);
+                        } else if now > note.time + windows.good {
+                            self.finish(i, Judgement::Miss, None, note.time + windows.good);
+                        }
+                    }
+                    NoteKind::Flick => {
+                        if now > note.time + windows.good {
+                            self.finish(i, Judgement::Miss, None, note.time + windows.good);
+                        }
+                    }
+                    NoteKind::Tap | NoteKind::Hold { .. } => {
+                        if now > note.time + windows.bad {
+                            self.finish(i, Judgement::Miss, None, note.time + windows.bad);
+                        }
+                    }
+                },
+                NoteState::Caught { offset } => {
+                    if note.time <= now {
+                        let time = note.time.max(note.time + offset);
+                        self.finish(i, Judgement::Perfect, Some(offset), time);
+                    }
+                }
+                NoteState::Holding { head, offset, .. } => {
+                    let end_time = note.end_time();
+                    if end_time <= now {
+                        self.finish(i, head, Some(offset), end_time);
+                    }
+                }
+                NoteState::Done => (),
+            }
+        }
+    }
+
+    fn pointer_in_lane(&self, note: &Note) -> bool {
+        self.pointers
+            .iter()
+            .any(|p| self.lane_matches(note, p.lane, p.x))
+    }
+
+    /// 处理一个输入，输入应当按时间顺序传入
+    pub fn handle_input(&mut self, input: &JudgeInput) {
+        // 先结算输入之前已经超时的音符，结果和每帧调用 update 的频率无关
+        self.update(input.time);
+
+        match input.action {
+            JudgeAction::Press => self.press(input),
+            JudgeAction::Move => self.move_pointer(input),
+            JudgeAction::Release => self.release(input),
+        }
+
+        // drag/flick 可能在音符时间之后才被接住
+        self.update(input.time);
+    }
+
+    fn press(&mut self, input: &JudgeInput) {
+        self.pointers.retain(|p| p.id != input.pointer);
+        self.pointers.push(Pointer {
+            id: input.pointer,
+            lane: input.lane,
+            x: input.x,
+            flick_anchor: input.position,
+            flicked_at: None,
+        });
+
+        let windows = self.config.windows;
+
+        // 只有 tap 和长条头部会消耗按下，按最早的音符判定
+        let target = (self.first_pending..self.notes.len())
+            .take_while(|i| self.notes[*i].time - windows.bad <= input.time)
+            .find(|i| {
+                let note = &self.notes[*i];
+                self.states[*i] == NoteState::Pending
+                    && matches!(note.kind, NoteKind::Tap | NoteKind::Hold { .. })
+                    && (input.time - note.time).abs() <= windows.bad
+                    && self.lane_matches(note, input.lane, input.x)
+            });
+
+        if let Some(i) = target {
+            let note = self.notes[i];
+            let offset = input.time - note.time;
+            let judgement = windows.judge(offset).unwrap_or(Judgement::Bad);
+
+            if note.is_hold() && !judgement.breaks_combo() {
+                self.states[i] = NoteState::Holding {
+                    pointer: input.pointer,
+                    head: judgement,
+                    offset,
+                };
+            } else {
+                self.finish(i, judgement, Some(offset), input.time);
+            }
+        }
+
+        self.catch_drags(input);
+    }
+
+    fn move_pointer(&mut self, input: &JudgeInput) {
+        let flick_distance = self.config.flick_distance;
+
+        let Some(pointer) = self.pointers.iter_mut().find(|p| p.id == input.pointer) else {
+            return;
+        };
+
+        pointer.lane = input.lane;
+        pointer.x = input.x;
+
+        if input.position.distance(pointer.flick_anchor) >= flick_distance {
+            pointer.flick_anchor = input.position;
+            pointer.flicked_at = Some(input.time);
+        }
+
+        let flicked = pointer.flicked_at.take().is_some();
+
+        self.catch_drags(input);
+
+        if flicked {
+            self.catch_flick(input);
+        }
+    }
+
+    fn release(&mut self, input: &JudgeInput) {
+        self.pointers.retain(|p| p.id != input.pointer);
+
+        let tolerance = self.config.hold_release_tolerance;
+
+        for i in self.first_pending..self.notes.len() {
+            if let NoteState::Holding { pointer, head, offset } = self.states[i] {
+                if pointer != input.pointer {
+                    continue;
+                }
+
+                let end_time = self.notes[i].end_time();
+
+                if input.time >= end_time - tolerance {
+                    self.finish(i, head, Some(offset), input.time);
+                } else {
+                    self.finish(i, Judgement::Miss, Some(offset), input.time);
+                }
+            }
+        }
+    }
+
+    // 在窗口内经过轨道的手指接住 drag
+    fn catch_drags(&mut self, input: &JudgeInput) {
+        let good = self.config.windows.good;
+
+        for i in self.first_pending..self.notes.len() {
+            let note = self.notes[i];
+
+            if note.time - good > input.time {
+                break;
+            }
+
+            if self.states[i] == NoteState::Pending
+                && note.kind == NoteKind::Drag
+                && (input.time - note.time).abs() <= good
+                && self.lane_matches(&note, input.lane, input.x)
+            {
+                self.states[i] = NoteState::Caught {
+                    offset: input.time - note.time,
+                };
+            }
+        }
+    }
+
+    // 一次 flick 只接住一个音符
+    fn catch_flick(&mut self, input: &JudgeInput) {
+        let good = self.config.windows.good;
+
+        let target = (self.first_pending..self.notes.len())
+            .take_while(|i| self.notes[*i].time - good <= input.time)
+            .find(|i| {
+                let note = &self.notes[*i];
+                self.states[*i] == NoteState::Pending
+                    && note.kind == NoteKind::Flick
+                    && (input.time - note.time).abs() <= good
+                    && self.lane_matches(note, input.lane, input.x)
+            });
+
+        if let Some(i) = target {
+            self.states[i] = NoteState::Caught {
+                offset: input.time - self.notes[i].time,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+fn judge_test_chart() -> Chart {
+    Chart::new(
+        ChartMetadata::default(),
+        TimingMap::constant(0.0, 120.0).unwrap(),
+        4,
+        vec![
+            Note::new(1.0, NoteKind::Tap, 0),
+            Note::new(1.5, NoteKind::Tap, 1),
+            Note::new(2.0, NoteKind::Hold { end_time: 3.0 }, 2),
+            Note::new(4.0, NoteKind::Drag, 3),
+            Note::new(5.0, NoteKind::Flick, 0),
+            Note::new(6.0, NoteKind::Tap, 1),
+        ],
+    )
+}
+
+#[cfg(test)]
+fn judge_input(time: f64, pointer: u64, action: JudgeAction, lane: u32, position: Vec2) -> JudgeInput {
+    JudgeInput {
+        time,
+        pointer: PointerId::Touch(pointer),
+        action,
+        lane,
+        x: 0.0,
+        position,
+    }
+}
+
+#[cfg(test)]
+fn judge_test_inputs() -> Vec<JudgeInput> {
+    use JudgeAction::*;
+
+    vec![
+        // tap: 早 20ms → Perfect；晚 60ms → Great
+        judge_input(0.98, 0, Press, 0, Vec2::ZERO),
+        judge_input(1.0, 0, Release, 0, Vec2::ZERO),
+        judge_input(1.56, 1, Press, 1, Vec2::ZERO),
+        judge_input(1.62, 1, Release, 1, Vec2::ZERO),
+        // 长条按到结束前 50ms，在容差内
+        judge_input(2.03, 2, Press, 2, Vec2::ZERO),
+        judge_input(2.95, 2, Release, 2, Vec2::ZERO),
+        // drag: 提前按在轨道上，到时间自动接住
+        judge_input(3.9, 3, Press, 3, Vec2::ZERO),
+        judge_input(4.1, 3, Release, 3, Vec2::ZERO),
+        // flick: 按住后划动
+        judge_input(4.95, 4, Press, 0, Vec2::ZERO),
+        judge_input(5.02, 4, Move, 0, vec2(60.0, 0.0)),
+        judge_input(5.05, 4, Release, 0, vec2(60.0, 0.0)),
+        // 最后一个 tap 没有输入
+    ]
+}
+
+#[test]
+fn judgement_windows_and_note_kinds() {
+    let chart = judge_test_chart();
+    let mut engine = JudgementEngine::new(&chart, JudgementConfig::default());
+
+    for input in judge_test_inputs() {
+        engine.handle_input(&input);
+    }
+    engine.update(10.0);
+
+    let results: Vec<_> = engine
+        .drain_results()
+        .iter()
+        .map(|r| (r.note_index, r.judgement))
+        .collect();
+
+    assert_eq!(
+        results,
+        vec![
+            (0, Judgement::Perfect),
+            (1, Judgement::Great),
+            (2, Judgement::Perfect),
+            (3, Judgement::Perfect),
+            (4, Judgement::Perfect),
+            (5, Judgement::Miss),
+        ]
+    );
+
+    let stats = engine.stats();
+    assert_eq!((stats.combo, stats.max_combo), (0, 5));
+    assert!(engine.is_finished());
+}
+
+#[test]
+fn judgement_hold_break_and_stray_press() {
+    use JudgeAction::*;
+
+    let chart = judge_test_chart();
+    let mut engine = JudgementEngine::new(&chart, JudgementConfig::default());
+
+    // 太早的按下不消耗音符
+    engine.handle_input(&judge_input(0.5, 0, Press, 0, Vec2::ZERO));
+    engine.handle_input(&judge_input(1.0, 0, Release, 0, Vec2::ZERO));
+    assert!(engine.drain_results().is_empty());
+
+    // 长条中途松开
+    engine.handle_input(&judge_input(2.0, 1, Press, 2, Vec2::ZERO));
+    engine.handle_input(&judge_input(2.5, 1, Release, 2, Vec2::ZERO));
+
+    let results = engine.drain_results();
+    assert_eq!(results[0].judgement, Judgement::Miss);
+    assert_eq!(results[0].offset, None);
+    assert_eq!(results[2].note_index, 2);
+    assert_eq!(results[2].judgement, Judgement::Miss);
+    assert_eq!(results[2].offset, Some(0.0));
+}
+
+#[test]
+fn judgement_is_independent_of_update_rate() {
+    let chart = judge_test_chart();
+
+    let run = |frame_time: f64| {
+        let mut engine = JudgementEngine::new(&chart, JudgementConfig::default())
+            .with_formula(WeightedScoring::phigros());
+        let mut inputs = judge_test_inputs().into_iter().peekable();
+        let mut now = 0.0;
+
+        while now < 10.0 {
+            while let Some(input) = inputs.next_if(|i| i.time <= now) {
+                engine.handle_input(&input);
+            }
+            engine.update(now);
+            now += frame_time;
+        }
+
+        (engine.drain_results(), engine.score())
+    };
+
+    assert_eq!(run(1.0 / 60.0), run(1.0 / 240.0));
+    assert_eq!(run(1.0 / 60.0), run(0.5));
+}
+
+#[test]
+fn scoring_formulas() {
+    let stats = ScoreStats {
+        perfect: 8,
+        great: 0,
+        good: 2,
+        bad: 0,
+        miss: 0,
+        combo: 10,
+        max_combo: 10,
+    };
+
+    let phigros = WeightedScoring::phigros();
+    assert!((phigros.accuracy(&stats) - 0.93).abs() < 1e-9);
+    assert_eq!(phigros.score(&stats, 10), 937_000);
+
+    let classic = WeightedScoring::default();
+    assert_eq!(classic.score(&stats, 10), 900_000);
+    assert_eq!(classic.score(&ScoreStats::default(), 0), 0);
+}
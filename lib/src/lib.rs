@@ -1,20 +1,37 @@
 // 内部模块的导入
 mod app_events;
 mod assets;
+mod atlas;
+mod audio;
+mod autoplay;
 mod batching;
+mod calibration;
 mod camera;
+mod chart;
 mod color;
 mod config;
 mod device;
+mod font;
 mod fpslimiter;
 mod gameloop;
+#[cfg(test)]
+mod golden_tests;
 mod graphic;
+mod hitsound;
+mod input;
+mod judgement;
+mod loader;
+mod osu;
+mod phigros;
 mod pipelines;
 mod quad;
+mod readback;
+mod replay;
 mod rect;
 mod render_pass;
 mod render_queues;
 mod shaders;
+mod song_clock;
 mod texture;
 mod time;
 mod utils;
@@ -23,26 +40,58 @@ mod y_sort;
 // 其他可能导入的模块
 use app_events::*;
 use assets::*;
+use atlas::*;
+use audio::*;
+use autoplay::*;
 use batching::*;
 use camera::*;
+use chart::*;
 use color::*;
 use colors::*;
 use config::*;
 use device::*;
 use fpslimiter::*;
 use gameloop::*;
+#[cfg(test)]
+use golden_tests::*;
 use graphic::*;
+use hitsound::*;
+use input::*;
+use judgement::*;
+use loader::*;
+use phigros::*;
 use pipelines::*;
 use quad::*;
+use replay::*;
 use rect::*;
 use render_pass::*;
 use render_queues::*;
 use shaders::*;
+use song_clock::*;
 use texture::*;
 use time::*;
 use utils::*;
 use y_sort::*;
 
+// 对外导出的接口
+pub use app_events::{init_headless, render_headless_frame};
+pub use assets::texture_id;
+pub use color::Color;
+pub use config::RunTimeContext;
+pub use judgement::{
+    JudgeAction, JudgeInput, JudgeResult, Judgement, JudgementConfig, JudgementEngine,
+    JudgementWindows, PointerId, ScoreStats, ScoringFormula, WeightedScoring,
+};
+pub use quad::{DrawTextureParams, RawDrawParams, draw_sprite_ex};
+pub use readback::read_render_target;
+pub use shaders::RenderTargetId;
+pub use song_clock::{
+    FreeRunningSource, ManualPositionSource, MusicPositionSource, PositionSource, SongClock,
+    game_time_to_song_time, get_song_clock, get_song_time, pause_song, resume_song, seek_song,
+    set_song_clock_source, set_song_offset, set_song_rate, song_time_to_game_time,
+    update_song_clock,
+};
+
 // 外部依赖库的导入
 use glam::*;
 use itertools::*;
@@ -113,12 +162,23 @@ pub fn get_global_window() -> Option<&'static Arc<Window>> {
     GLOBAL_WINDOW.get()
 }
 
+// 渲染器当前的尺寸，高 32 位为宽、低 32 位为高，不经过渲染器的锁读取
+static RENDER_SIZE: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_render_size(size: UVec2) {
+    RENDER_SIZE.store(((size.x as u64) << 32) | size.y as u64, Ordering::Relaxed);
+}
+
 pub fn get_window_size() -> PhysicalSize<u32> {
     if let Some(window) = get_global_window() {
-        window.inner_size()
-    } else {
-        PhysicalSize::new(1, 1)
+        return window.inner_size();
     }
+
+    // headless 模式下没有窗口，使用默认 RT 的尺寸
+    let size = RENDER_SIZE.load(Ordering::Relaxed);
+    let (width, height) = ((size >> 32) as u32, size as u32);
+
+    PhysicalSize::new(width.max(1), height.max(1))
 }
 
 static WGPU_RENDERER: OnceLock<Arc<RwLock<WgpuRenderer>>> = OnceLock::new();
@@ -163,15 +223,31 @@ fn main() {
             fullscreen: false,
             resolution: Size::Physical(PhysicalSize::new(1280, 720)),
             min_resolution: None,
+            app_id: None,
         },
+        audio_config: AudioConfig::default(),
+        graphics_config: GraphicsConfig::default(),
+        autoplay: None,
     };
 
     let run_time_context = RunTimeContext {
         target_frame_rate: Some(120),
         sample_count: Msaa::Sample4,
+        enable_z_buffer: true,
         clear_color: BLACK,
         main_camera: None,
     };
 
     init_game(init_game_config, run_time_context, MyGame::default());
-}
\ No newline at end of file
+}
+
+#[test]
+fn headless_window_size_ignores_renderer_lock() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    render_scene(Msaa::Off, || {
+        // 例如另一个线程正在渲染或设置相机
+        let _wr = get_global_wgpu().write();
+        assert_eq!(get_window_size(), PhysicalSize::new(SCENE_SIZE.x, SCENE_SIZE.y));
+    });
+}
@@ -0,0 +1,440 @@
+//! 后台资源加载。
+//!
+//! 图片和音频在 Tokio 的阻塞线程池（没有运行时时用独立线程）里读取和解码，
+//! 解码好的图片排队等渲染线程上传到 GPU。每个资源都属于一个分组，
+//! 加载界面可以轮询 [`group_progress`]，也可以直接 `await` [`wait_for_group`]。
+
+use crate::*;
+
+use anyhow::{Result, anyhow};
+use image::DynamicImage;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use tokio::sync::Notify;
+
+/// 渲染线程每次最多上传的纹理数，避免一帧里集中上传造成卡顿
+const MAX_UPLOADS_PER_PUMP: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetLoadState {
+    /// 正在读取和解码
+    Decoding,
+    /// 已解码，等待渲染线程上传
+    Uploading,
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LoadProgress {
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    /// 已完成（包括失败）的比例，空分组为 1
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.loaded + self.failed) as f32 / self.total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.loaded + self.failed == self.total
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AssetSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl AssetSource {
+    fn read(self) -> Result<(Vec<u8>, Option<String>)> {
+        match self {
+            AssetSource::Path(path) => {
+                let bytes = std::fs::read(&path)
+                    .map_err(|e| anyhow!("Failed to read {:?}: {}", path, e))?;
+                let extension = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(str::to_owned);
+
+                Ok((bytes, extension))
+            }
+            AssetSource::Bytes(bytes) => Ok((bytes, None)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum AssetKind {
+    Texture,
+    Sound,
+}
+
+#[derive(Debug)]
+struct AssetEntry {
+    group: String,
+    state: AssetLoadState,
+    // 同名资源重新加载时递增，旧任务的结果会被丢弃
+    generation: u64,
+}
+
+struct PendingUpload {
+    name: String,
+    generation: u64,
+    image: DynamicImage,
+}
+
+#[derive(Default)]
+struct Loader {
+    assets: HashMap<(AssetKind, String), AssetEntry>,
+    uploads: VecDeque<PendingUpload>,
+    next_generation: u64,
+}
+
+impl Loader {
+    fn begin(&mut self, kind: AssetKind, group: &str, name: &str) -> u64 {
+        self.next_generation += 1;
+
+        self.assets.insert(
+            (kind, name.to_owned()),
+            AssetEntry {
+                group: group.to_owned(),
+                state: AssetLoadState::Decoding,
+                generation: self.next_generation,
+            },
+        );
+
+        self.next_generation
+    }
+
+    /// 没有被同名的新任务取代
+    fn is_current(&self, kind: AssetKind, name: &str, generation: u64) -> bool {
+        self.assets
+            .get(&(kind, name.to_owned()))
+            .is_some_and(|entry| entry.generation == generation)
+    }
+
+    /// 任务仍然有效时更新状态
+    fn set_state(&mut self, kind: AssetKind, name: &str, generation: u64, state: AssetLoadState) -> bool {
+        match self.assets.get_mut(&(kind, name.to_owned())) {
+            Some(entry) if entry.generation == generation => {
+                if let AssetLoadState::Failed(e) = &state {
+                    error!("Failed to load {:?} '{}': {}", kind, name, e);
+                }
+
+                entry.state = state;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+static LOADER: Lazy<Mutex<Loader>> = Lazy::new(|| Mutex::new(Loader::default()));
+static LOADER_NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn finish(kind: AssetKind, name: &str, generation: u64, state: AssetLoadState) {
+    if LOADER.lock().set_state(kind, name, generation, state) {
+        LOADER_NOTIFY.notify_waiters();
+    }
+}
+
+fn spawn_decode(job: impl FnOnce() + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(job);
+        }
+        Err(_) => {
+            std::thread::spawn(job);
+        }
+    }
+}
+
+/// 在后台解码图片，之后由渲染线程上传并以 `name` 注册
+pub fn load_texture_async(group: &str, name: &str, source: AssetSource) {
+    let generation = LOADER.lock().begin(AssetKind::Texture, group, name);
+    let name = name.to_owned();
+
+    spawn_decode(move || {
+        let image = source
+            .read()
+            .and_then(|(bytes, _)| Ok(image::load_from_memory(&bytes)?));
+
+        match image {
+            Ok(image) => {
+                let mut loader = LOADER.lock();
+                if loader.set_state(AssetKind::Texture, &name, generation, AssetLoadState::Uploading) {
+                    loader.uploads.push_back(PendingUpload {
+                        name,
+                        generation,
+                        image,
+                    });
+                }
+            }
+            Err(e) => finish(
+                AssetKind::Texture,
+                &name,
+                generation,
+                AssetLoadState::Failed(e.to_string()),
+            ),
+        }
+    });
+}
+
+/// 在后台解码音频并注册到音频引擎，之后可以用 `play_sound(name, ..)` 播放
+pub fn load_sound_async(group: &str, name: &str, source: AssetSource) {
+    let generation = LOADER.lock().begin(AssetKind::Sound, group, name);
+    let name = name.to_owned();
+
+    spawn_decode(move || {
+        let sound = source.read().and_then(|(bytes, extension)| {
+            if !check_audio_init() {
+                return Err(anyhow!("Audio is not initialized"));
+            }
+
+            decode_sound(bytes, extension.as_deref())
+        });
+
+        let sound = match sound {
+            Ok(sound) => sound,
+            Err(e) => {
+                finish(AssetKind::Sound, &name, generation, AssetLoadState::Failed(e.to_string()));
+                return;
+            }
+        };
+
+        // 持有 LOADER 锁检查并注册，较早的任务晚完成时不会覆盖较新的音频
+        let mut loader = LOADER.lock();
+        if !loader.is_current(AssetKind::Sound, &name, generation) {
+            return;
+        }
+
+        get_global_audio()
+            .write()
+            .sounds
+            .insert(name.clone(), Arc::new(sound));
+
+        loader.set_state(AssetKind::Sound, &name, generation, AssetLoadState::Loaded);
+        drop(loader);
+
+        LOADER_NOTIFY.notify_waiters();
+    });
+}
+
+/// 上传已解码的纹理，由渲染线程在每帧绘制前调用
+pub(crate) fn process_texture_uploads() {
+    for _ in 0..MAX_UPLOADS_PER_PUMP {
+        let Some(upload) = LOADER.lock().uploads.pop_front() else {
+            return;
+        };
+
+        // 上传在渲染线程上按顺序进行，这里跳过已经被新任务取代的结果即可
+        if !LOADER
+            .lock()
+            .is_current(AssetKind::Texture, &upload.name, upload.generation)
+        {
+            continue;
+        }
+
+        let state = match load_texture_from_image(&upload.name, upload.image) {
+            Ok(_) => AssetLoadState::Loaded,
+            Err(e) => AssetLoadState::Failed(format!("{:#}", e)),
+        };
+
+        finish(AssetKind::Texture, &upload.name, upload.generation, state);
+    }
+}
+
+pub fn texture_load_state(name: &str) -> Option<AssetLoadState> {
+    LOADER
+        .lock()
+        .assets
+        .get(&(AssetKind::Texture, name.to_owned()))
+        .map(|entry| entry.state.clone())
+}
+
+pub fn sound_load_state(name: &str) -> Option<AssetLoadState> {
+    LOADER
+        .lock()
+        .assets
+        .get(&(AssetKind::Sound, name.to_owned()))
+        .map(|entry| entry.state.clone())
+}
+
+/// 纹理还在后台加载时返回 true
+pub fn is_texture_loading(handle: TextureHandle) -> bool {
+    LOADER.lock().assets.iter().any(|((kind, name), entry)| {
+        *kind == AssetKind::Texture
+            && matches!(entry.state, AssetLoadState::Decoding | AssetLoadState::Uploading)
+            && texture_path(name) == handle
+    })
+}
+
+pub fn group_progress(group: &str) -> LoadProgress {
+    let loader = LOADER.lock();
+    let mut progress = LoadProgress::default();
+
+    for entry in loader.assets.values().filter(|entry| entry.group == group) {
+        progress.total += 1;
+
+        match entry.state {
+            AssetLoadState::Loaded => progress.loaded += 1,
+            AssetLoadState::Failed(_) => progress.failed += 1,
+            _ => {}
+        }
+    }
+
+    progress
+}
+
+/// 分组内每个资源的名称和状态
+pub fn group_assets(group: &str) -> Vec<(String, AssetLoadState)> {
+    let loader = LOADER.lock();
+
+    loader
+        .assets
+        .iter()
+        .filter(|(_, entry)| entry.group == group)
+        .map(|((_, name), entry)| (name.clone(), entry.state.clone()))
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .collect()
+}
+
+/// 等待分组内所有资源完成，有资源失败时返回错误。
+///
+/// 纹理上传依赖渲染线程继续运行，headless 模式下需要继续调用 `render_headless_frame`。
+pub async fn wait_for_group(group: &str) -> Result<()> {
+    loop {
+        let notified = LOADER_NOTIFY.notified();
+
+        if group_progress(group).is_done() {
+            break;
+        }
+
+        notified.await;
+    }
+
+    let failed: Vec<String> = group_assets(group)
+        .into_iter()
+        .filter_map(|(name, state)| match state {
+            AssetLoadState::Failed(e) => Some(format!("{}: {}", name, e)),
+            _ => None,
+        })
+        .collect();
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Failed to load group '{}': {}",
+            group,
+            failed.join("; ")
+        ))
+    }
+}
+
+/// 忘记分组的加载记录（不会卸载已加载的资源）
+pub fn clear_group(group: &str) {
+    LOADER.lock().assets.retain(|_, entry| entry.group != group);
+}
+
+#[cfg(test)]
+pub(crate) fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+
+    while !condition() {
+        assert!(std::time::Instant::now() < deadline, "timed out");
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn loader_reports_group_progress() {
+    init_audio(&AudioConfig::default());
+
+    load_sound_async(
+        "loader_test",
+        "loader_test_sound",
+        AssetSource::Bytes(test_wav(8000, &[0, 100, 200])),
+    );
+    load_sound_async(
+        "loader_test",
+        "loader_test_missing",
+        AssetSource::Path("tests/does_not_exist.wav".into()),
+    );
+    load_texture_async(
+        "loader_test",
+        "loader_test_broken",
+        AssetSource::Bytes(b"not an image".to_vec()),
+    );
+
+    wait_until(|| group_progress("loader_test").is_done());
+
+    assert_eq!(
+        group_progress("loader_test"),
+        LoadProgress {
+            total: 3,
+            loaded: 1,
+            failed: 2,
+        }
+    );
+    assert_eq!(sound_load_state("loader_test_sound"), Some(AssetLoadState::Loaded));
+    assert!(matches!(
+        texture_load_state("loader_test_broken"),
+        Some(AssetLoadState::Failed(_))
+    ));
+    assert_eq!(
+        get_global_audio().read().sounds["loader_test_sound"].frames.len(),
+        3
+    );
+
+    let error = pollster::block_on(wait_for_group("loader_test")).unwrap_err();
+    assert!(error.to_string().contains("loader_test_missing"), "{error}");
+
+    clear_group("loader_test");
+    assert_eq!(group_progress("loader_test").total, 0);
+    assert_eq!(group_progress("loader_test").fraction(), 1.0);
+}
+
+#[test]
+fn async_texture_loading() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        load_texture_async("async_test", "async_red", AssetSource::Bytes(png));
+
+        // 解码完成后要等渲染线程上传，这期间尺寸未知，绘制会被跳过
+        wait_until(|| texture_load_state("async_red") == Some(AssetLoadState::Uploading));
+        assert!(matches!(
+            Assets::image_size(texture_path("async_red")),
+            ImageSizeResult::LoadingInProgress
+        ));
+        draw_sprite_ex(texture_path("async_red"), DrawTextureParams::default());
+
+        render_headless_frame();
+        pollster::block_on(wait_for_group("async_test")).unwrap();
+        assert_eq!(group_progress("async_test").fraction(), 1.0);
+
+        draw_sprite_ex(texture_id("async_red"), DrawTextureParams::default());
+    }) else {
+        return;
+    };
+
+    assert_eq!(image.get_pixel(64, 64), &image::Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(64 + 8, 64), &image::Rgba([0, 0, 0, 255]));
+
+    unload_texture("async_red");
+    clear_group("async_test");
+}
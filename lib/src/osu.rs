@@ -0,0 +1,499 @@
+//! osu!mania `.osu` 谱面导入。
+//!
+//! 先解析成与文件结构一一对应的 [`OsuBeatmap`]（可以再写回 `.osu` 文本），
+//! 再通过 [`OsuBeatmap::to_chart`] 转换为通用的 [`Chart`]。
+
+use crate::*;
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::{fmt::Write, str::FromStr};
+
+const MANIA_MODE: u32 = 3;
+const HIT_OBJECT_CIRCLE: u32 = 1;
+const HIT_OBJECT_MANIA_HOLD: u32 = 128;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OsuGeneral {
+    pub audio_filename: String,
+    pub audio_lead_in: i32,
+    pub preview_time: i32,
+    pub mode: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OsuMetadata {
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub creator: String,
+    pub version: String,
+    pub source: String,
+    pub tags: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsuDifficulty {
+    pub hp_drain_rate: f32,
+    /// mania 中表示键数
+    pub circle_size: f32,
+    pub overall_difficulty: f32,
+    pub approach_rate: f32,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+}
+
+impl Default for OsuDifficulty {
+    fn default() -> Self {
+        Self {
+            hp_drain_rate: 5.0,
+            circle_size: 4.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsuTimingPoint {
+    /// 毫秒
+    pub time: f64,
+    /// 非继承点为每拍毫秒数，继承点为负的速度倍率（-100 / 倍率）
+    pub beat_length: f64,
+    pub meter: u32,
+    pub sample_set: u32,
+    pub sample_index: u32,
+    pub volume: u32,
+    pub uninherited: bool,
+    pub effects: u32,
+}
+
+impl OsuTimingPoint {
+    pub fn bpm(&self) -> Option<f64> {
+        self.uninherited.then(|| 60000.0 / self.beat_length)
+    }
+
+    /// 继承点的滚动速度倍率
+    pub fn scroll_velocity(&self) -> Option<f64> {
+        (!self.uninherited).then(|| -100.0 / self.beat_length)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsuHitObject {
+    pub x: i32,
+    pub y: i32,
+    /// 毫秒
+    pub time: i32,
+    pub object_type: u32,
+    pub hit_sound: u32,
+    /// 长条结束时间（毫秒）
+    pub end_time: Option<i32>,
+    pub hit_sample: String,
+}
+
+impl OsuHitObject {
+    pub fn is_hold(&self) -> bool {
+        self.object_type & HIT_OBJECT_MANIA_HOLD != 0
+    }
+
+    pub fn lane(&self, key_count: u32) -> u32 {
+        let key_count = key_count.max(1);
+        ((self.x.max(0) as u32 * key_count) / 512).min(key_count - 1)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OsuBeatmap {
+    pub format_version: u32,
+    pub general: OsuGeneral,
+    pub metadata: OsuMetadata,
+    pub difficulty: OsuDifficulty,
+    pub timing_points: Vec<OsuTimingPoint>,
+    pub hit_objects: Vec<OsuHitObject>,
+}
+
+pub fn load_osu_chart(path: impl AsRef<std::path::Path>) -> Result<Chart> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    OsuBeatmap::parse(&source)
+        .with_context(|| format!("Failed to parse {:?}", path))?
+        .to_chart()
+}
+
+fn parse_field<T: FromStr>(value: &str, name: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid {} '{}'", name, value.trim()))
+}
+
+fn split_key_value(line: &str) -> Result<(&str, &str)> {
+    let (key, value) = line
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected 'Key: Value', got '{}'", line))?;
+
+    Ok((key.trim(), value.trim()))
+}
+
+impl OsuBeatmap {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut beatmap = OsuBeatmap::default();
+        let mut section = String::new();
+        let mut found_header = false;
+
+        for (i, raw_line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let line = raw_line.trim_start_matches('\u{feff}').trim();
+
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            if !found_header {
+                let version = line
+                    .strip_prefix("osu file format v")
+                    .ok_or_else(|| anyhow!("line {}: missing 'osu file format' header", line_number))?;
+
+                beatmap.format_version = parse_field(version, "format version")
+                    .with_context(|| format!("line {}", line_number))?;
+                found_header = true;
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_owned();
+                continue;
+            }
+
+            beatmap
+                .parse_line(&section, line)
+                .with_context(|| format!("line {} in [{}]", line_number, section))?;
+        }
+
+        if !found_header {
+            bail!("empty beatmap");
+        }
+
+        Ok(beatmap)
+    }
+
+    fn parse_line(&mut self, section: &str, line: &str) -> Result<()> {
+        match section {
+            "General" => {
+                let (key, value) = split_key_value(line)?;
+                let general = &mut self.general;
+
+                match key {
+                    "AudioFilename" => general.audio_filename = value.to_owned(),
+                    "AudioLeadIn" => general.audio_lead_in = parse_field(value, key)?,
+                    "PreviewTime" => general.preview_time = parse_field(value, key)?,
+                    "Mode" => general.mode = parse_field(value, key)?,
+                    _ => (),
+                }
+            }
+            "Metadata" => {
+                let (key, value) = split_key_value(line)?;
+                let metadata = &mut self.metadata;
+                let value = value.to_owned();
+
+                match key {
+                    "Title" => metadata.title = value,
+                    "TitleUnicode" => metadata.title_unicode = value,
+                    "Artist" => metadata.artist = value,
+                    "ArtistUnicode" => metadata.artist_unicode = value,
+                    "Creator" => metadata.creator = value,
+                    "Version" => metadata.version = value,
+                    "Source" => metadata.source = value,
+                    "Tags" => metadata.tags = value,
+                    _ => (),
+                }
+            }
+            "Difficulty" => {
+                let (key, value) = split_key_value(line)?;
+                let difficulty = &mut self.difficulty;
+
+                match key {
+                    "HPDrainRate" => difficulty.hp_drain_rate = parse_field(value, key)?,
+                    "CircleSize" => difficulty.circle_size = parse_field(value, key)?,
+                    "OverallDifficulty" => difficulty.overall_difficulty = parse_field(value, key)?,
+                    "ApproachRate" => difficulty.approach_rate = parse_field(value, key)?,
+                    "SliderMultiplier" => difficulty.slider_multiplier = parse_field(value, key)?,
+                    "SliderTickRate" => difficulty.slider_tick_rate = parse_field(value, key)?,
+                    _ => (),
+                }
+            }
+            "TimingPoints" => self.timing_points.push(parse_timing_point(line)?),
+            "HitObjects" => self.hit_objects.push(parse_hit_object(line)?),
+            // [Editor]、[Events]、[Colours] 等与玩法无关
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    pub fn key_count(&self) -> u32 {
+        self.difficulty.circle_size.round().max(1.0) as u32
+    }
+
+    /// 指定时间（毫秒）的滚动速度倍率，遇到新的非继承点时重置为 1
+    pub fn scroll_velocity_at(&self, time: f64) -> f64 {
+        let mut velocity = 1.0;
+
+        for point in self.timing_points.iter().take_while(|p| p.time <= time) {
+            velocity = point.scroll_velocity().unwrap_or(1.0);
+        }
+
+        velocity
+    }
+
+    pub fn to_chart(&self) -> Result<Chart> {
+        if self.general.mode != MANIA_MODE {
+            bail!("not an osu!mania beatmap (Mode: {})", self.general.mode);
+        }
+
+        let mut uninherited = self.timing_points.iter().filter(|p| p.uninherited).peekable();
+
+        let first = *uninherited
+            .peek()
+            .ok_or_else(|| anyhow!("beatmap has no uninherited timing point"))?;
+
+        // 非继承点按毫秒定位，换算成 TimingMap 需要的拍
+        let mut points = Vec::new();
+        let mut beat = 0.0;
+        let mut prev: Option<&OsuTimingPoint> = None;
+
+        for point in uninherited {
+            if point.beat_length <= 0.0 {
+                bail!("invalid beat length {} at {}ms", point.beat_length, point.time);
+            }
+
+            if let Some(prev) = prev {
+                beat += (point.time - prev.time) / prev.beat_length;
+            }
+
+            points.push(TimingPoint {
+                beat,
+                bpm: 60000.0 / point.beat_length,
+                beats_per_measure: point.meter.max(1),
+            });
+
+            prev = Some(point);
+        }
+
+        let timing = TimingMap::new(first.time / 1000.0, points)?;
+        let key_count = self.key_count();
+
+        let notes = self
+            .hit_objects
+            .iter()
+            .filter_map(|object| {
+                let time = object.time as f64 / 1000.0;
+                let lane = object.lane(key_count);
+
+                if object.is_hold() {
+                    let end_time = object.end_time.unwrap_or(object.time) as f64 / 1000.0;
+                    Some(Note::new(time, NoteKind::Hold { end_time }, lane))
+                } else if object.object_type & HIT_OBJECT_CIRCLE != 0 {
+                    Some(Note::new(time, NoteKind::Tap, lane))
+                } else {
+                    warn!("Skipping non-mania hit object at {}ms", object.time);
+                    None
+                }
+            })
+            .collect();
+
+        let metadata = &self.metadata;
+
+        Ok(Chart::new(
+            ChartMetadata {
+                title: if metadata.title_unicode.is_empty() {
+                    metadata.title.clone()
+                } else {
+                    metadata.title_unicode.clone()
+                },
+                artist: if metadata.artist_unicode.is_empty() {
+                    metadata.artist.clone()
+                } else {
+                    metadata.artist_unicode.clone()
+                },
+                charter: metadata.creator.clone(),
+                difficulty: metadata.version.clone(),
+            },
+            timing,
+            key_count,
+            notes,
+        ))
+    }
+
+    /// 写回 `.osu` 文本，只包含本结构解析的段落
+    pub fn to_osu_string(&self) -> String {
+        let mut out = String::new();
+        let g = &self.general;
+        let m = &self.metadata;
+        let d = &self.difficulty;
+
+        let _ = writeln!(out, "osu file format v{}\n", self.format_version);
+
+        let _ = writeln!(out, "[General]");
+        let _ = writeln!(out, "AudioFilename: {}", g.audio_filename);
+        let _ = writeln!(out, "AudioLeadIn: {}", g.audio_lead_in);
+        let _ = writeln!(out, "PreviewTime: {}", g.preview_time);
+        let _ = writeln!(out, "Mode: {}\n", g.mode);
+
+        let _ = writeln!(out, "[Metadata]");
+        let _ = writeln!(out, "Title:{}", m.title);
+        let _ = writeln!(out, "TitleUnicode:{}", m.title_unicode);
+        let _ = writeln!(out, "Artist:{}", m.artist);
+        let _ = writeln!(out, "ArtistUnicode:{}", m.artist_unicode);
+        let _ = writeln!(out, "Creator:{}", m.creator);
+        let _ = writeln!(out, "Version:{}", m.version);
+        let _ = writeln!(out, "Source:{}", m.source);
+        let _ = writeln!(out, "Tags:{}\n", m.tags);
+
+        let _ = writeln!(out, "[Difficulty]");
+        let _ = writeln!(out, "HPDrainRate:{}", d.hp_drain_rate);
+        let _ = writeln!(out, "CircleSize:{}", d.circle_size);
+        let _ = writeln!(out, "OverallDifficulty:{}", d.overall_difficulty);
+        let _ = writeln!(out, "ApproachRate:{}", d.approach_rate);
+        let _ = writeln!(out, "SliderMultiplier:{}", d.slider_multiplier);
+        let _ = writeln!(out, "SliderTickRate:{}\n", d.slider_tick_rate);
+
+        let _ = writeln!(out, "[TimingPoints]");
+        for p in &self.timing_points {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                p.time,
+                p.beat_length,
+                p.meter,
+                p.sample_set,
+                p.sample_index,
+                p.volume,
+                p.uninherited as u8,
+                p.effects
+            );
+        }
+
+        let _ = writeln!(out, "\n[HitObjects]");
+        for h in &self.hit_objects {
+            let _ = write!(out, "{},{},{},{},{},", h.x, h.y, h.time, h.object_type, h.hit_sound);
+            match h.end_time {
+                Some(end_time) => {
+                    let _ = writeln!(out, "{}:{}", end_time, h.hit_sample);
+                }
+                None => {
+                    let _ = writeln!(out, "{}", h.hit_sample);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn parse_timing_point(line: &str) -> Result<OsuTimingPoint> {
+    let fields: Vec<&str> = line.split(',').collect();
+
+    if fields.len() < 2 {
+        bail!("timing point needs at least 2 fields, got '{}'", line);
+    }
+
+    let field = |i: usize, default: u32, name: &str| -> Result<u32> {
+        fields.get(i).map_or(Ok(default), |v| parse_field(v, name))
+    };
+
+    let beat_length: f64 = parse_field(fields[1], "beat length")?;
+
+    Ok(OsuTimingPoint {
+        time: parse_field(fields[0], "time")?,
+        beat_length,
+        meter: field(2, 4, "meter")?,
+        sample_set: field(3, 0, "sample set")?,
+        sample_index: field(4, 0, "sample index")?,
+        volume: field(5, 100, "volume")?,
+        // 旧版本没有该字段，用拍长的正负判断
+        uninherited: match fields.get(6) {
+            Some(v) => parse_field::<u32>(v, "uninherited")? != 0,
+            None => beat_length > 0.0,
+        },
+        effects: field(7, 0, "effects")?,
+    })
+}
+
+fn parse_hit_object(line: &str) -> Result<OsuHitObject> {
+    let fields: Vec<&str> = line.splitn(6, ',').collect();
+
+    if fields.len() < 5 {
+        bail!("hit object needs at least 5 fields, got '{}'", line);
+    }
+
+    let object_type: u32 = parse_field(fields[3], "type")?;
+    let params = fields.get(5).copied().unwrap_or_default();
+
+    let (end_time, hit_sample) = if object_type & HIT_OBJECT_MANIA_HOLD != 0 {
+        // 长条: endTime:hitSample
+        let (end_time, hit_sample) = params.split_once(':').unwrap_or((params, ""));
+        (Some(parse_field(end_time, "hold end time")?), hit_sample.to_owned())
+    } else {
+        (None, params.to_owned())
+    };
+
+    Ok(OsuHitObject {
+        x: parse_field(fields[0], "x")?,
+        y: parse_field(fields[1], "y")?,
+        time: parse_field(fields[2], "time")?,
+        object_type,
+        hit_sound: parse_field(fields[4], "hit sound")?,
+        end_time,
+        hit_sample,
+    })
+}
+
+#[cfg(test)]
+const SAMPLE_4K: &str = include_str!("../tests/osu/sample_4k.osu");
+
+#[test]
+fn osu_fixture_to_chart() {
+    let beatmap = OsuBeatmap::parse(SAMPLE_4K).unwrap();
+
+    assert_eq!(beatmap.format_version, 14);
+    assert_eq!(beatmap.metadata.version, "Easy");
+    assert_eq!(beatmap.key_count(), 4);
+    assert_eq!(beatmap.timing_points.len(), 3);
+    assert_eq!(beatmap.scroll_velocity_at(3000.0), 2.0);
+
+    let chart = beatmap.to_chart().unwrap();
+
+    assert_eq!(chart.metadata.title, "テスト曲");
+    assert_eq!(chart.lane_count, 4);
+    assert_eq!(chart.timing.offset, 0.5);
+    // 120BPM 持续 8 拍后变为 240BPM
+    assert_eq!(chart.timing.beat_to_time(8.0), 4.5);
+    assert_eq!(chart.timing.bpm_at(5.0), 240.0);
+
+    let lanes: Vec<_> = chart.notes.iter().map(|n| n.lane).collect();
+    assert_eq!(lanes, vec![0, 1, 2, 3, 0]);
+    assert_eq!(chart.notes[2].kind, NoteKind::Hold { end_time: 2.0 });
+    assert_eq!(chart.notes[4].time, 5.0);
+}
+
+#[test]
+fn osu_round_trip() {
+    let beatmap = OsuBeatmap::parse(SAMPLE_4K).unwrap();
+    let reparsed = OsuBeatmap::parse(&beatmap.to_osu_string()).unwrap();
+
+    assert_eq!(beatmap, reparsed);
+}
+
+#[test]
+fn osu_error_has_line_number() {
+    let source = "osu file format v14\n\n[HitObjects]\n64,192,500,1,0,0:0:0:0:\n64,192,oops,1,0\n";
+    let error = format!("{:#}", OsuBeatmap::parse(source).unwrap_err());
+
+    assert!(error.contains("line 5"), "{error}");
+    assert!(error.contains("invalid time 'oops'"), "{error}");
+}
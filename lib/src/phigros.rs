@@ -0,0 +1,521 @@
+//! Phigros 官方 JSON 谱面（formatVersion 1 / 3）。
+//!
+//! 音符挂在会移动、旋转、渐隐的判定线上，时间单位是判定线 BPM 下的 1/32 拍，
+//! 音符到判定线的距离由速度事件积分得到的 floor position 决定。
+
+use crate::*;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+/// 与官方实现一致：横向 1 个单位为屏宽的 0.05625，纵向 1 个 floor 单位为屏高的 0.6
+pub const PHIGROS_X_UNIT: f32 = 0.05625;
+pub const PHIGROS_Y_UNIT: f32 = 0.6;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhigrosChart {
+    pub format_version: u32,
+    /// 秒，音符的歌曲时间为谱面时间加上偏移
+    #[serde(default)]
+    pub offset: f64,
+    pub judge_line_list: Vec<JudgeLine>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JudgeLine {
+    pub bpm: f64,
+    #[serde(default)]
+    pub notes_above: Vec<PhigrosNote>,
+    #[serde(default)]
+    pub notes_below: Vec<PhigrosNote>,
+    #[serde(default)]
+    pub speed_events: Vec<SpeedEvent>,
+    /// `start`/`end` 为 x，`start2`/`end2` 为 y，都是 0..1 的屏幕比例，y 向上
+    #[serde(default, rename = "judgeLineMoveEvents")]
+    pub move_events: Vec<LineEvent>,
+    /// 角度，逆时针为正
+    #[serde(default, rename = "judgeLineRotateEvents")]
+    pub rotate_events: Vec<LineEvent>,
+    /// 透明度 0..1
+    #[serde(default, rename = "judgeLineDisappearEvents")]
+    pub alpha_events: Vec<LineEvent>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhigrosNote {
+    #[serde(rename = "type")]
+    pub note_type: u32,
+    pub time: f64,
+    pub position_x: f32,
+    #[serde(default)]
+    pub hold_time: f64,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub floor_position: f64,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedEvent {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub value: f64,
+    /// 事件开始时判定线的 floor position，加载时重新计算
+    #[serde(default)]
+    pub floor_position: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineEvent {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub start: f64,
+    pub end: f64,
+    #[serde(default)]
+    pub start2: f64,
+    #[serde(default)]
+    pub end2: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteSide {
+    Above,
+    Below,
+}
+
+/// 判定线在某一时刻的状态
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JudgeLineState {
+    /// 0..1 的屏幕比例，原点在左下角
+    pub position: Vec2,
+    /// 角度，逆时针为正
+    pub rotation: f32,
+    pub alpha: f32,
+    pub floor_position: f64,
+}
+
+pub fn load_phigros_chart(path: impl AsRef<std::path::Path>) -> Result<PhigrosChart> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    PhigrosChart::from_json(&source).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+impl PhigrosChart {
+    pub fn from_json(source: &str) -> Result<Self> {
+        let mut chart: PhigrosChart = serde_json::from_str(source)?;
+
+        if !matches!(chart.format_version, 1 | 3) {
+            bail!("unsupported formatVersion {}", chart.format_version);
+        }
+
+        for (i, line) in chart.judge_line_list.iter_mut().enumerate() {
+            if line.bpm <= 0.0 {
+                bail!("judge line {} has invalid bpm {}", i, line.bpm);
+            }
+
+            if chart.format_version == 1 {
+                line.unpack_v1_move_events();
+            }
+
+            line.prepare();
+        }
+
+        Ok(chart)
+    }
+
+    /// 转换为通用的 [`Chart`]，lane 为判定线下标，x 为 positionX
+    pub fn to_chart(&self, metadata: ChartMetadata) -> Result<Chart> {
+        let bpm = self.judge_line_list.first().map_or(120.0, |line| line.bpm);
+        let timing = TimingMap::constant(self.offset, bpm)?;
+
+        let notes = self
+            .judge_line_list
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                line.notes_above
+                    .iter()
+                    .chain(&line.notes_below)
+                    .map(move |note| {
+                        let time = line.time_to_seconds(note.time) + self.offset;
+
+                        let kind = match note.note_type {
+                            2 => NoteKind::Drag,
+                            3 => NoteKind::Hold {
+                                end_time: time + line.time_to_seconds(note.hold_time),
+                            },
+                            4 => NoteKind::Flick,
+                            _ => NoteKind::Tap,
+                        };
+
+                        Note {
+                            time,
+                            kind,
+                            lane: i as u32,
+                            x: note.position_x,
+                        }
+                    })
+            })
+            .collect();
+
+        Ok(Chart::new(metadata, timing, self.judge_line_list.len() as u32, notes))
+    }
+}
+
+// 在已排序的事件中找到 time 所在的事件并插值，超出范围时保持最近的值
+fn sample_events(events: &[LineEvent], time: f64, second: bool) -> Option<f64> {
+    let i = events.partition_point(|e| e.start_time <= time).checked_sub(1)?;
+    let event = &events[i];
+
+    let (start, end) = if second {
+        (event.start2, event.end2)
+    } else {
+        (event.start, event.end)
+    };
+
+    let length = event.end_time - event.start_time;
+    let t = if length > 0.0 {
+        ((time - event.start_time) / length).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+
+    Some(start + (end - start) * t)
+}
+
+impl JudgeLine {
+    /// 1 个时间单位为 1/32 拍
+    pub fn time_to_seconds(&self, time: f64) -> f64 {
+        time * 1.875 / self.bpm
+    }
+
+    pub fn seconds_to_time(&self, seconds: f64) -> f64 {
+        seconds * self.bpm / 1.875
+    }
+
+    // formatVersion 1 的坐标打包为 x * 1000 + y，范围为 880x520
+    fn unpack_v1_move_events(&mut self) {
+        let unpack = |v: f64| {
+            let x = (v / 1000.0).floor();
+            ((x / 880.0), (v - x * 1000.0) / 520.0)
+        };
+
+        for event in &mut self.move_events {
+            (event.start, event.start2) = unpack(event.start);
+            (event.end, event.end2) = unpack(event.end);
+        }
+    }
+
+    fn prepare(&mut self) {
+        for events in [
+            &mut self.move_events,
+            &mut self.rotate_events,
+            &mut self.alpha_events,
+        ] {
+            events.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        }
+
+        self.speed_events
+            .sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+        // 重新积分 floor position，不信任文件中的值
+        let mut floor = 0.0;
+        for i in 0..self.speed_events.len() {
+            self.speed_events[i].floor_position = floor;
+
+            let event = &self.speed_events[i];
+            floor += self.time_to_seconds(event.end_time - event.start_time) * event.value;
+        }
+
+        for i in 0..self.notes_above.len() {
+            self.notes_above[i].floor_position = self.floor_position_at(self.notes_above[i].time);
+        }
+        for i in 0..self.notes_below.len() {
+            self.notes_below[i].floor_position = self.floor_position_at(self.notes_below[i].time);
+        }
+
+        self.notes_above.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.notes_below.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    /// 从 0 时刻积分速度得到的距离（floor 单位），`time` 为谱面时间单位
+    pub fn floor_position_at(&self, time: f64) -> f64 {
+        let Some(i) = self
+            .speed_events
+            .partition_point(|e| e.start_time <= time)
+            .checked_sub(1)
+        else {
+            return 0.0;
+        };
+
+        let event = &self.speed_events[i];
+        event.floor_position + self.time_to_seconds(time - event.start_time) * event.value
+    }
+
+    /// `seconds` 为谱面时间（秒），不含谱面偏移
+    pub fn state_at(&self, seconds: f64) -> JudgeLineState {
+        let time = self.seconds_to_time(seconds);
+
+        JudgeLineState {
+            position: vec2(
+                sample_events(&self.move_events, time, false).unwrap_or(0.5) as f32,
+                sample_events(&self.move_events, time, true).unwrap_or(0.5) as f32,
+            ),
+            rotation: sample_events(&self.rotate_events, time, false).unwrap_or(0.0) as f32,
+            alpha: sample_events(&self.alpha_events, time, false).unwrap_or(1.0) as f32,
+            floor_position: self.floor_position_at(time),
+        }
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = (NoteSide, &PhigrosNote)> {
+        self.notes_above
+            .iter()
+            .map(|n| (NoteSide::Above, n))
+            .chain(self.notes_below.iter().map(|n| (NoteSide::Below, n)))
+    }
+
+    /// 音符头部到判定线的距离（floor 单位），长条按 1 倍速下落，其余音符乘以自身速度
+    pub fn note_distance(&self, note: &PhigrosNote, line_floor: f64) -> f64 {
+        let distance = note.floor_position - line_floor;
+
+        if note.note_type == 3 {
+            distance
+        } else {
+            distance * note.speed as f64
+        }
+    }
+
+    /// 长条的长度（floor 单位）
+    pub fn hold_length(&self, note: &PhigrosNote) -> f64 {
+        self.time_to_seconds(note.hold_time) * note.speed as f64
+    }
+}
+
+/// 把判定线和音符换算到屏幕坐标（原点在屏幕中心，y 向上）并绘制
+#[derive(Clone, Copy, Debug)]
+pub struct PhigrosLayout {
+    pub screen_size: Vec2,
+    /// 判定线长度相对屏宽的倍数
+    pub line_length: f32,
+    pub line_thickness: u32,
+    pub note_size: UVec2,
+    pub z_index: i32,
+}
+
+impl Default for PhigrosLayout {
+    fn default() -> Self {
+        Self {
+            screen_size: vec2(1280.0, 720.0),
+            line_length: 4.0,
+            line_thickness: 4,
+            note_size: uvec2(180, 18),
+            z_index: 0,
+        }
+    }
+}
+
+impl PhigrosLayout {
+    pub fn line_position(&self, state: &JudgeLineState) -> Vec2 {
+        (state.position - vec2(0.5, 0.5)) * self.screen_size
+    }
+
+    /// 音符在屏幕上的中心位置，`distance` 为 floor 单位
+    pub fn note_position(
+        &self,
+        state: &JudgeLineState,
+        side: NoteSide,
+        position_x: f32,
+        distance: f64,
+    ) -> Vec2 {
+        let (sin, cos) = state.rotation.to_radians().sin_cos();
+        let along = vec2(cos, sin);
+        let normal = vec2(-sin, cos);
+
+        let side = match side {
+            NoteSide::Above => 1.0,
+            NoteSide::Below => -1.0,
+        };
+
+        self.line_position(state)
+            + along * position_x * PHIGROS_X_UNIT * self.screen_size.x
+            + normal * side * distance as f32 * PHIGROS_Y_UNIT * self.screen_size.y
+    }
+
+    /// `song_time` 为歌曲时间（秒）
+    pub fn draw(
+        &self,
+        chart: &PhigrosChart,
+        song_time: f64,
+        line_texture: TextureHandle,
+        note_texture: TextureHandle,
+    ) {
+        let chart_time = song_time - chart.offset;
+
+        for line in &chart.judge_line_list {
+            let state = line.state_at(chart_time);
+            let line_time = line.seconds_to_time(chart_time);
+
+            draw_sprite_ex(
+                line_texture,
+                DrawTextureParams {
+                    raw_draw_params: RawDrawParams {
+                        position: self.line_position(&state).extend(0.0),
+                        rotation: Rotation::Z(state.rotation),
+                        dest_size: Some(uvec2(
+                            (self.screen_size.x * self.line_length) as u32,
+                            self.line_thickness,
+                        )),
+                        color: Color::new(1.0, 1.0, 1.0, state.alpha.clamp(0.0, 1.0)),
+                        z_index: self.z_index,
+                        blend_mode: BlendMode::Alpha,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            );
+
+            for (side, note) in line.notes() {
+                let is_hold = note.note_type == 3;
+
+                // 已判定的音符不再绘制，进行中的长条从判定线开始画
+                if note.time + if is_hold { note.hold_time } else { 0.0 } < line_time {
+                    continue;
+                }
+
+                let mut distance = line.note_distance(note, state.floor_position);
+                let mut length = 0.0;
+
+                if is_hold {
+                    length = line.hold_length(note);
+                    if distance < 0.0 {
+                        length += distance;
+                        distance = 0.0;
+                    }
+                } else if distance < -1e-3 {
+                    // 速度为负时音符在判定线另一侧，官方实现不显示
+                    continue;
+                }
+
+                let position = self.note_position(&state, side, note.position_x, distance);
+                let height = length as f32 * PHIGROS_Y_UNIT * self.screen_size.y;
+
+                // 长条从头部沿远离判定线的方向延伸
+                let rotation = match side {
+                    NoteSide::Above => state.rotation,
+                    NoteSide::Below => state.rotation + 180.0,
+                };
+
+                draw_sprite_ex(
+                    note_texture,
+                    DrawTextureParams {
+                        raw_draw_params: RawDrawParams {
+                            position: position.extend(0.0),
+                            rotation: Rotation::Z(rotation),
+                            dest_size: Some(uvec2(
+                                self.note_size.x,
+                                self.note_size.y + height.max(0.0) as u32,
+                            )),
+                            pivot: Some(if is_hold {
+                                vec2(0.5, 0.0)
+                            } else {
+                                vec2(0.5, 0.5)
+                            }),
+                            z_index: self.z_index + 1,
+                            blend_mode: BlendMode::Alpha,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+const SAMPLE_CHART: &str = include_str!("../tests/phigros/sample_v3.json");
+
+#[test]
+fn phigros_events_and_floor_position() {
+    let chart = PhigrosChart::from_json(SAMPLE_CHART).unwrap();
+    let line = &chart.judge_line_list[0];
+
+    // 120BPM 下 32 个单位为 0.5 秒
+    assert_eq!(line.time_to_seconds(32.0), 0.5);
+
+    // 速度 1 持续 64 单位（1 秒），之后速度 2
+    assert_eq!(line.floor_position_at(64.0), 1.0);
+    assert_eq!(line.floor_position_at(96.0), 2.0);
+    assert_eq!(line.notes_above[1].floor_position, 2.0);
+
+    let state = line.state_at(0.5);
+    assert_eq!(state.position, vec2(0.5, 0.375));
+    assert_eq!(state.rotation, 45.0);
+    assert_eq!(state.alpha, 1.0);
+
+    // 事件结束后保持最后的值
+    assert_eq!(line.state_at(10.0).rotation, 90.0);
+
+    // 普通音符的距离乘以自身速度，长条不乘
+    assert_eq!(line.note_distance(&line.notes_above[1], 1.0), 1.0);
+    assert_eq!(line.note_distance(&line.notes_below[0], 0.0), 1.0);
+    assert_eq!(line.hold_length(&line.notes_below[0]), 1.0);
+}
+
+#[test]
+fn phigros_v1_move_events_and_chart() {
+    let source = r#"{
+        "formatVersion": 1,
+        "offset": 0.25,
+        "judgeLineList": [{
+            "bpm": 60.0,
+            "notesAbove": [{ "type": 4, "time": 32, "positionX": -1.5, "holdTime": 0, "speed": 1, "floorPosition": 0 }],
+            "notesBelow": [],
+            "speedEvents": [{ "startTime": 0, "endTime": 999999, "value": 1 }],
+            "judgeLineMoveEvents": [{ "startTime": 0, "endTime": 999999, "start": 440260, "end": 440260 }],
+            "judgeLineRotateEvents": [],
+            "judgeLineDisappearEvents": []
+        }]
+    }"#;
+
+    let chart = PhigrosChart::from_json(source).unwrap();
+    let state = chart.judge_line_list[0].state_at(0.0);
+    assert_eq!(state.position, vec2(0.5, 0.5));
+
+    let chart = chart.to_chart(ChartMetadata::default()).unwrap();
+    assert_eq!(chart.notes[0].kind, NoteKind::Flick);
+    assert_eq!(chart.notes[0].time, 1.25);
+    assert_eq!(chart.notes[0].x, -1.5);
+}
+
+#[test]
+fn phigros_note_screen_position() {
+    let layout = PhigrosLayout {
+        screen_size: vec2(1000.0, 500.0),
+        ..Default::default()
+    };
+
+    let state = JudgeLineState {
+        position: vec2(0.5, 0.5),
+        rotation: 90.0,
+        alpha: 1.0,
+        floor_position: 0.0,
+    };
+
+    // 旋转 90 度后，沿线方向为 +y，上方为 -x
+    let above = layout.note_position(&state, NoteSide::Above, 2.0, 1.0);
+    assert!((above - vec2(-300.0, 112.5)).length() < 1e-3);
+
+    let below = layout.note_position(&state, NoteSide::Below, 0.0, 1.0);
+    assert!((below - vec2(300.0, 0.0)).length() < 1e-3);
+}
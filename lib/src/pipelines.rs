@@ -79,9 +79,18 @@ impl UserRenderTarget {
         texture_layout: &BindGroupLayout,
         params: &RenderTargetParams,
     ) -> UserRenderTarget {
+        // 超出设备限制的 RT 会在创建纹理时报错，这里先缩到最大尺寸
+        let rt_size = c.capabilities.clamp_texture_size(params.size);
+        if rt_size != params.size {
+            warn!(
+                "Render target '{}' size {} exceeds the device limit, clamped to {}",
+                params.label, params.size, rt_size
+            );
+        }
+
         let size = Extent3d {
-            width: params.size.x,
-            height: params.size.y,
+            width: rt_size.x,
+            height: rt_size.y,
             depth_or_array_layers: 1,
         };
 
@@ -124,7 +133,9 @@ impl UserRenderTarget {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[format],
         });
         let resolve_view = resolve_texture.create_view(&Default::default());
@@ -155,7 +166,7 @@ impl UserRenderTarget {
         });
 
         UserRenderTarget {
-            size: params.size,
+            size: rt_size,
             msaa_texture,
             msaa_view,
             msaa_depth_texture,
@@ -194,7 +205,7 @@ pub fn ensure_pipeline_exists(
     };
 
     let name = format!(
-        "{} {:?} {:?} {:?}",
+        "{} {:?} {:?} {:?} x{}",
         if maybe_shader_instance_id.0 > 0 {
             "USER(Mesh)"
         } else {
@@ -202,29 +213,28 @@ pub fn ensure_pipeline_exists(
         },
         pass_data.blend_mode,
         maybe_shader,
-        context.enable_z_buffer
+        context.enable_z_buffer,
+        sample_count
     );
 
-    let mesh_pipeline = if let Some(shader) = maybe_shader {
-        RenderPipeline::User(
-            context
-                .user_pipelines
-                .entry(name.clone())
-                .or_insert_with(|| {
-                    create_user_pipeline(
-                        &name,
-                        pass_data,
-                        shader,
-                        &context.context,
-                        &context.texture_layout,
-                        &context.camera_bind_group_layout,
-                        context.enable_z_buffer,
-                        sample_count,
-                    )
-                }),
-        )
+    if let Some(shader) = maybe_shader {
+        context
+            .user_pipelines
+            .entry(name.clone())
+            .or_insert_with(|| {
+                create_user_pipeline(
+                    &name,
+                    pass_data,
+                    shader,
+                    &context.context,
+                    &context.texture_layout,
+                    &context.camera_bind_group_layout,
+                    context.enable_z_buffer,
+                    sample_count,
+                )
+            });
     } else {
-        RenderPipeline::Wgpu(context.pipelines.entry(name.clone()).or_insert_with(|| {
+        context.pipelines.entry(name.clone()).or_insert_with(|| {
             create_render_pipeline_with_layout(
                 &name,
                 &context.context.device,
@@ -237,97 +247,118 @@ pub fn ensure_pipeline_exists(
                 sample_count,
             )
             .unwrap()
-        }))
-    };
+        });
+    }
 
-    if let RenderPipeline::User(user_pipeline) = mesh_pipeline {
-        if maybe_shader_instance_id.0 > 0 {
-            let shader_instance = get_shader_instance(maybe_shader_instance_id);
-            let shader = shaders.get(shader_instance.id).unwrap();
-
-            for (buffer_name, buffer) in user_pipeline.buffers.iter().sorted_by_key(|x| x.0) {
-                // 尝试从实例中获取自定义uniform值
-                if let Some(uniform) = shader_instance.uniforms.get(buffer_name) {
-                    match uniform {
-                        Uniform::F32(value) => {
-                            let data = [value.0];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        Uniform::Vec2(values) => {
-                            let data = [values[0].0, values[1].0];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        Uniform::Vec3(values) => {
-                            let data = [values[0].0, values[1].0, values[2].0];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        Uniform::Vec4(values) => {
-                            let data = [values[0].0, values[1].0, values[2].0, values[3].0];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                    }
-                }
-                // 使用shader定义的默认值
-                else if let Some(uniform_def) = shader.uniform_defs.get(buffer_name) {
-                    match uniform_def {
-                        UniformDef::F32(Some(default_value)) => {
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&[*default_value]),
-                            );
-                        }
-                        UniformDef::Vec2(Some((x, y))) => {
-                            let data = [*x, *y];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        UniformDef::Vec3(Some((x, y, z))) => {
-                            let data = [*x, *y, *z];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        UniformDef::Vec4(Some((x, y, z, w))) => {
-                            let data = [*x, *y, *z, *w];
-                            context.context.queue.write_buffer(
-                                buffer,
-                                0,
-                                bytemuck::cast_slice(&data),
-                            );
-                        }
-                        // 没有默认值的情况
-                        _ => panic!("No uniform value or default for {buffer_name}"),
-                    }
-                } else {
-                    panic!("Uniform definition not found for {buffer_name}");
-                }
+    name
+}
+
+/// 着色器实例的绑定组 2 和它的 uniform 缓冲区，跨帧复用
+pub struct UserBindGroup {
+    shader: ShaderId,
+    pipeline_name: String,
+    // 按绑定顺序排列的缓冲区和上次写入的值
+    buffers: Vec<(String, Buffer, Vec<f32>)>,
+    pub bind_group: BindGroup,
+}
+
+impl UserBindGroup {
+    /// 缓存的绑定组属于同一个着色器和管线时可以直接更新 uniform
+    pub fn matches(&self, shader: ShaderId, pipeline_name: &str) -> bool {
+        self.shader == shader && self.pipeline_name == pipeline_name
+    }
+
+    /// 只写入和上次不同的 uniform
+    pub fn update(&mut self, context: &GraphicsContext, shader: &Shader, instance: &ShaderInstance) {
+        for (buffer_name, buffer, values) in self.buffers.iter_mut() {
+            let data = uniform_values(shader, instance, buffer_name);
+
+            if *values != data {
+                context
+                    .queue
+                    .write_buffer(buffer, 0, bytemuck::cast_slice(&data));
+                *values = data;
             }
         }
     }
+}
 
-    name
+/// 按着色器实例的 uniform 值（没有设置时用默认值）创建绑定组 2。
+///
+/// 同一个着色器的不同实例共用管线，但一帧里会被录制进同一个 encoder，
+/// 所以每个实例都要有自己的 uniform 缓冲区。创建后缓存在 `WgpuRenderer::user_bind_groups`，
+/// 之后的帧用 [`UserBindGroup::update`] 更新。
+pub fn create_user_bind_group(
+    context: &GraphicsContext,
+    pipeline_name: &str,
+    pipeline: &UserRenderPipeline,
+    shader: &Shader,
+    instance: &ShaderInstance,
+) -> UserBindGroup {
+    let buffers: Vec<(String, Buffer, Vec<f32>)> = shader
+        .bindings
+        .iter()
+        .sorted_by_key(|(_, binding)| **binding)
+        .map(|(buffer_name, _)| {
+            let data = uniform_values(shader, instance, buffer_name);
+
+            let buffer = context
+                .device
+                .create_buffer_init(&util::BufferInitDescriptor {
+                    label: Some(&format!("User UB: {}", buffer_name)),
+                    contents: bytemuck::cast_slice(&data),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
+
+            (buffer_name.clone(), buffer, data)
+        })
+        .collect();
+
+    let entries: Vec<BindGroupEntry> = buffers
+        .iter()
+        .map(|(buffer_name, buffer, _)| BindGroupEntry {
+            binding: shader.bindings[buffer_name],
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+
+    let bind_group = context.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("User Bind Group"),
+        layout: &pipeline.layout,
+        entries: &entries,
+    });
+
+    UserBindGroup {
+        shader: shader.id,
+        pipeline_name: pipeline_name.to_owned(),
+        buffers,
+        bind_group,
+    }
+}
+
+fn uniform_values(shader: &Shader, instance: &ShaderInstance, buffer_name: &str) -> Vec<f32> {
+    // 尝试从实例中获取自定义uniform值
+    if let Some(uniform) = instance.uniforms.get(buffer_name) {
+        match uniform {
+            Uniform::F32(value) => vec![value.0],
+            Uniform::Vec2(values) => vec![values[0].0, values[1].0],
+            Uniform::Vec3(values) => vec![values[0].0, values[1].0, values[2].0],
+            Uniform::Vec4(values) => vec![values[0].0, values[1].0, values[2].0, values[3].0],
+        }
+    }
+    // 使用shader定义的默认值
+    else if let Some(uniform_def) = shader.uniform_defs.get(buffer_name) {
+        match uniform_def {
+            UniformDef::F32(Some(value)) => vec![*value],
+            UniformDef::Vec2(Some((x, y))) => vec![*x, *y],
+            UniformDef::Vec3(Some((x, y, z))) => vec![*x, *y, *z],
+            UniformDef::Vec4(Some((x, y, z, w))) => vec![*x, *y, *z, *w],
+            // 没有默认值的情况
+            _ => panic!("No uniform value or default for {buffer_name}"),
+        }
+    } else {
+        panic!("Uniform definition not found for {buffer_name}");
+    }
 }
 
 pub fn create_user_pipeline(
@@ -1,6 +1,16 @@
 use crate::*;
 
-const Z_DIV: f32 = 1000.0;
+/// 能区分的 z_index 范围，超出的会被钳制到边界
+pub const Z_INDEX_LIMIT: i32 = 1 << 16;
+
+/// 把 z_index 映射到顶点的 z 坐标。
+///
+/// z_index 越大越靠前（深度越小），结果始终落在像素投影的 `-1..1` 之内，
+/// 相邻两层之间的间隔在 `Depth32Float` 下仍然可分辨。
+pub fn z_index_depth(z_index: i32) -> f32 {
+    let z_index = z_index.clamp(-Z_INDEX_LIMIT, Z_INDEX_LIMIT);
+    -(z_index as f32) / (Z_INDEX_LIMIT as f32 + 1.0)
+}
 
 pub fn draw_circle(center: Vec2, r: f32, color: Color, z_index: i32) {
     draw_poly_z(center, 40, r, 0.0, color, z_index, BlendMode::Alpha);
@@ -36,7 +46,7 @@ pub fn draw_poly2_z(
     blend_mode: BlendMode,
 ) {
     let (x, y) = (position.x, position.y);
-    let z = z_index as f32 / Z_DIV;
+    let z = z_index_depth(z_index);
 
     let mut vertices = Vec::<SpriteVertex>::with_capacity(sides as usize + 2);
     let mut indices = Vec::<u32>::with_capacity(sides as usize * 3);
@@ -56,8 +66,9 @@ pub fn draw_poly2_z(
 
         vertices.push(vertex);
 
+        // 管线以顺时针为正面，这里按顺时针顺序输出三角形
         if i != sides {
-            indices.extend_from_slice(&[0, i as u32 + 1, i as u32 + 2]);
+            indices.extend_from_slice(&[0, i as u32 + 2, i as u32 + 1]);
         }
     }
 
@@ -115,7 +126,7 @@ pub fn draw_line_tex(
     //
     // 0 1      1 1
 
-    let z = z_index as f32 / Z_DIV;
+    let z = z_index_depth(z_index);
 
     let vertices = [
         SpriteVertex::new(vec3(x1 + tx, y1 + ty, z), vec2(0.0, 0.0), color),
@@ -131,7 +142,8 @@ pub fn draw_line_tex(
     //     SpriteVertex::new(vec2(x2 - tx, y2 - ty), vec2(0.0, 1.0), color),
     // ];
 
-    let indices = [0, 1, 2, 2, 1, 3];
+    // 顺时针，和精灵的正面朝向一致
+    let indices = [0, 2, 1, 2, 3, 1];
 
     draw_mesh(Mesh {
         origin: vec3((x1 + x2) / 2.0, (y1 + y2) / 2.0, z_index as f32),
@@ -185,7 +197,23 @@ pub fn draw_sprite_ex(texture: TextureHandle, params: DrawTextureParams) {
         _ => false,
     };
 
-    let vertices = rotated_rectangle(params.scroll_offset, &params.raw_draw_params, is_rt);
+    // 在图集里的纹理改用所在的页绘制；滚动 UV 依赖 Repeat 寻址，不能用图集
+    let atlas_region = if is_rt || params.scroll_offset != Vec2::ZERO {
+        None
+    } else {
+        atlas_region(texture)
+    };
+
+    let texture = atlas_region
+        .and_then(|region| get_atlas().read().page_handle(region.page))
+        .unwrap_or(texture);
+
+    let vertices = rotated_rectangle(
+        params.scroll_offset,
+        &params.raw_draw_params,
+        is_rt,
+        atlas_region.as_ref(),
+    );
 
     const QUAD_INDICES_U32: &[u32] = &[0, 1, 2, 0, 2, 3];
 
@@ -276,10 +304,12 @@ impl Default for Rotation {
     }
 }
 
+/// `atlas` 不为空时，UV 会映射到纹理在图集页中的区域
 pub fn rotated_rectangle(
     scroll_offset: Vec2,
     params: &RawDrawParams,
     is_rt: bool,
+    atlas: Option<&AtlasRegion>,
 ) -> [SpriteVertex; 4] {
     // 处理目标尺寸和翻转
     let (scale_w, scale_h) = {
@@ -301,7 +331,7 @@ pub fn rotated_rectangle(
         Rotation::Y(angle) => vec3(0.0, angle, 0.0),
         Rotation::Z(angle) => vec3(0.0, 0.0, angle),
         Rotation::Euler(x, y, z) => vec3(x, y, z),
-        Rotation::Quaternion(x, y, z, w) => quat(x, y, z, w).to_euler(EulerRot::XYZ).into(),
+        Rotation::Quaternion(..) => Vec3::ZERO,
     };
 
     rotation_angles.x = rotation_angles.x.to_radians();
@@ -309,7 +339,10 @@ pub fn rotated_rectangle(
     rotation_angles.z = rotation_angles.z.to_radians();
 
     // 创建3x3旋转矩阵（左手坐标系，ZXY旋转顺序）
-    let rotation_matrix = {
+    let rotation_matrix = if let Rotation::Quaternion(x, y, z, w) = params.rotation {
+        // 四元数直接转矩阵，避免欧拉角的单位和旋转顺序问题
+        Mat3::from_quat(quat(x, y, z, w).normalize())
+    } else {
         let (sx, cx) = rotation_angles.x.sin_cos();
         let (sy, cy) = rotation_angles.y.sin_cos();
         let (sz, cz) = rotation_angles.z.sin_cos();
@@ -353,8 +386,8 @@ pub fn rotated_rectangle(
         let pivot_relative = v - pivot_offset;
         // 应用3D旋转
         let rotated = rotation_matrix * pivot_relative;
-        // 转换回世界坐标（包含Z轴）
-        rotated + params.position
+        // 转换回世界坐标（包含Z轴），再按 z_index 分层
+        rotated + params.position + vec3(0.0, 0.0, z_index_depth(params.z_index))
     });
 
     let tex_coords: [Vec2; 4] = if is_rt {
@@ -374,6 +407,11 @@ pub fn rotated_rectangle(
         ]
     };
 
+    let tex_coords = match atlas {
+        Some(region) => tex_coords.map(|uv| region.map_uv(uv)),
+        None => tex_coords,
+    };
+
     // 创建最终顶点
     [
         SpriteVertex::new(world_vertices[0], tex_coords[0], params.color),
@@ -398,3 +436,79 @@ pub fn tex_coord_flip(mut xy: Vec2, params: &RawDrawParams) -> Vec2 {
 
     xy
 }
+
+#[test]
+fn rotated_rectangle_pivot() {
+    let params = RawDrawParams {
+        position: vec3(10.0, 20.0, 0.0),
+        dest_size: Some(uvec2(4, 2)),
+        pivot: Some(Vec2::ZERO),
+        ..Default::default()
+    };
+
+    let vertices = rotated_rectangle(Vec2::ZERO, &params, false, None);
+    let positions = vertices.map(|v| v.position);
+
+    assert_eq!(
+        positions,
+        [
+            [10.0, 20.0, 0.0],
+            [10.0, 22.0, 0.0],
+            [14.0, 22.0, 0.0],
+            [14.0, 20.0, 0.0],
+        ]
+    );
+
+    // 默认 pivot 在中心
+    let centered = rotated_rectangle(Vec2::ZERO, &RawDrawParams { pivot: None, ..params }, false, None);
+    assert_eq!(centered[0].position, [8.0, 19.0, 0.0]);
+    assert_eq!(centered[2].position, [12.0, 21.0, 0.0]);
+}
+
+#[test]
+fn rotated_rectangle_rotation_and_flip() {
+    let params = RawDrawParams {
+        dest_size: Some(uvec2(2, 2)),
+        rotation: Rotation::Z(90.0),
+        flip_x: true,
+        ..Default::default()
+    };
+
+    let vertices = rotated_rectangle(Vec2::ZERO, &params, false, None);
+
+    // 绕中心旋转 90 度：左上角 (-1, -1) 转到 (1, -1)
+    let p = Vec3::from(vertices[0].position);
+    assert!(p.abs_diff_eq(vec3(1.0, -1.0, 0.0), 1e-5), "{p:?}");
+
+    // 翻转 X 后第一个顶点的 UV 从 (0, 0) 变为 (1, 0)
+    assert_eq!(vertices[0].tex_coords, [1.0, 0.0]);
+
+    // 四元数与等价的 Z 轴欧拉角结果一致
+    let q = Quat::from_rotation_z(90f32.to_radians());
+    let quat_vertices = rotated_rectangle(
+        Vec2::ZERO,
+        &RawDrawParams {
+            rotation: Rotation::Quaternion(q.x, q.y, q.z, q.w),
+            ..params.clone()
+        },
+        false,
+        None,
+    );
+
+    for (a, b) in vertices.iter().zip(quat_vertices.iter()) {
+        assert!(Vec3::from(a.position).abs_diff_eq(Vec3::from(b.position), 1e-4));
+    }
+}
+
+#[test]
+fn z_index_depth_is_ordered_and_in_range() {
+    let samples = [i32::MIN, -Z_INDEX_LIMIT, -3000, -1, 0, 1, 1000, 3000, Z_INDEX_LIMIT, i32::MAX];
+    let depths = samples.map(z_index_depth);
+
+    // z_index 越大深度越小，且不会被 -1..1 的投影裁掉
+    assert!(depths.iter().all(|z| z.abs() < 1.0));
+    assert!(depths.windows(2).all(|w| w[0] >= w[1]));
+    assert!(z_index_depth(Z_INDEX_LIMIT - 1) > z_index_depth(Z_INDEX_LIMIT));
+    assert_eq!(z_index_depth(i32::MAX), z_index_depth(Z_INDEX_LIMIT));
+    assert_eq!(z_index_depth(0), 0.0);
+}
@@ -0,0 +1,220 @@
+use crate::*;
+
+use anyhow::{Result, anyhow, bail};
+use image::RgbaImage;
+use wgpu::{
+    BufferDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT, Extent3d, MapMode, Origin3d, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
+};
+
+/// 一次回读需要的 GPU 资源，提交拷贝命令后即可在任意线程等待映射完成
+struct PendingReadback {
+    device: Arc<Device>,
+    buffer: Buffer,
+    size: UVec2,
+    padded_bytes_per_row: u32,
+    format: TextureFormat,
+}
+
+/// 把 RT 的 `resolve_texture` 拷贝回 CPU。
+///
+/// 在 `GameLoop::update` 中调用时，读到的是上一帧渲染完成后的内容。
+pub fn read_render_target(id: RenderTargetId) -> Result<RgbaImage> {
+    let pending = begin_readback(id)?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    pending
+        .buffer
+        .slice(..)
+        .map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+
+    pending.device.poll(wgpu::Maintain::Wait);
+
+    rx.recv()??;
+
+    pending.finish()
+}
+
+/// [`read_render_target`] 的异步版本，等待映射时不会阻塞当前线程。
+pub async fn read_render_target_async(id: RenderTargetId) -> Result<RgbaImage> {
+    let pending = begin_readback(id)?;
+
+    let (tx, rx) = oneshot::channel();
+    pending
+        .buffer
+        .slice(..)
+        .map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+
+    // 在阻塞线程里等 GPU 完成，映射回调会在那里触发
+    let device = pending.device.clone();
+    let wait = move || {
+        device.poll(wgpu::Maintain::Wait);
+    };
+
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(wait);
+        }
+        Err(_) => {
+            std::thread::spawn(wait);
+        }
+    }
+
+    rx.await
+        .map_err(|e| anyhow!("Readback mapping was dropped: {}", e))??;
+
+    pending.finish()
+}
+
+/// 把 RT 的内容保存为 PNG。
+pub fn save_render_target_png(id: RenderTargetId, path: impl AsRef<std::path::Path>) -> Result<()> {
+    let image = read_render_target(id)?;
+    image.save_with_format(path, image::ImageFormat::Png)?;
+
+    Ok(())
+}
+
+/// 截取默认 RT（即屏幕内容）并保存为 PNG。
+pub fn save_screenshot(path: impl AsRef<std::path::Path>) -> Result<()> {
+    save_render_target_png(RenderTargetId(0), path)
+}
+
+fn begin_readback(id: RenderTargetId) -> Result<PendingReadback> {
+    let (device, queue) = {
+        let wr = get_global_wgpu().read();
+        (wr.context.device.clone(), wr.context.queue.clone())
+    };
+
+    let rts = get_global_render_targets().read();
+    let rt = rts
+        .get(&id)
+        .ok_or_else(|| anyhow!("Render target {:?} not found", id))?
+        .read();
+
+    let format = rt.resolve_texture.format();
+
+    if !is_readable_format(format) {
+        bail!("Unsupported render target format for readback: {:?}", format);
+    }
+
+    let size = rt.size;
+
+    // 每行字节数必须按 256 对齐
+    let unpadded_bytes_per_row = size.x * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Render Target Readback Buffer"),
+        size: (padded_bytes_per_row * size.y) as u64,
+        usage: BufferType::Read.usage(),
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.simple_encoder("Render Target Readback Encoder");
+
+    encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+            texture: &rt.resolve_texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size.y),
+            },
+        },
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    queue.submit(std::iter::once(encoder.finish()));
+
+    Ok(PendingReadback {
+        device,
+        buffer,
+        size,
+        padded_bytes_per_row,
+        format,
+    })
+}
+
+fn is_readable_format(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+    )
+}
+
+impl PendingReadback {
+    fn finish(self) -> Result<RgbaImage> {
+        let row_bytes = (self.size.x * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.size.y as usize);
+
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+
+            // 去掉每行末尾的对齐填充
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+
+        self.buffer.unmap();
+
+        // sRGB 格式里存的已经是编码后的值，直接当作 PNG 像素即可，只需处理通道顺序
+        if matches!(
+            self.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.size.x, self.size.y, pixels)
+            .ok_or_else(|| anyhow!("Readback buffer size mismatch"))
+    }
+}
+
+#[test]
+fn async_readback_matches_blocking_readback() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let Some(image) = render_scene(Msaa::Off, || {
+        draw_quad(RawDrawParams {
+            dest_size: Some(uvec2(16, 16)),
+            color: RED,
+            ..Default::default()
+        });
+    }) else {
+        return;
+    };
+
+    let async_image = pollster::block_on(read_render_target_async(RenderTargetId(0))).unwrap();
+    assert_eq!(async_image, image);
+
+    // 在 tokio 运行时里由阻塞线程池等待 GPU
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .build()
+        .unwrap();
+    let async_image = runtime
+        .block_on(read_render_target_async(RenderTargetId(0)))
+        .unwrap();
+    assert_eq!(async_image, image);
+}
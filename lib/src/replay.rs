@@ -0,0 +1,716 @@
+//! 回放录制与播放。
+//!
+//! 录制时每帧把输入事件换算成歌曲时间保存；播放时在 winit 线程每次循环
+//! （`App::about_to_wait`）把到时的事件注入输入模块，和真实的窗口事件走同一条路径。
+//!
+//! 文件格式（小端）：
+//!
+//! ```text
+//! "KKRP" | u16 版本 | u64 谱面哈希 | 设置 | 按键名表 | 事件数 | 事件...
+//! ```
+//!
+//! 事件时间按微秒与上一个事件做差，用 zigzag varint 编码。
+
+use crate::*;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+const REPLAY_MAGIC: &[u8; 4] = b"KKRP";
+pub const REPLAY_VERSION: u16 = 1;
+
+/// 谱面内容的哈希，与文件格式无关，用于确认回放对应的谱面
+pub fn chart_hash(chart: &Chart) -> u64 {
+    // FNV-1a，结果在不同平台和版本之间保持稳定
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+
+    write(&chart.lane_count.to_le_bytes());
+
+    for note in &chart.notes {
+        write(&note.time.to_le_bytes());
+        write(&note.lane.to_le_bytes());
+        write(&note.x.to_le_bytes());
+
+        match note.kind {
+            NoteKind::Tap => write(&[0]),
+            NoteKind::Hold { end_time } => {
+                write(&[1]);
+                write(&end_time.to_le_bytes());
+            }
+            NoteKind::Drag => write(&[2]),
+            NoteKind::Flick => write(&[3]),
+        }
+    }
+
+    hash
+}
+
+/// 影响判定结果的设置，播放回放时由 [`start_replay_playback`] 还原
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplaySettings {
+    /// 歌曲播放速率，见 [`set_song_rate`]
+    pub song_rate: f64,
+    /// 歌曲时钟的偏移（秒），见 [`set_song_offset`]
+    pub offset: f64,
+    /// 判定引擎的设置，播放时通过 [`replay_judgement_config`] 取得
+    pub judgement: JudgementConfig,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            song_rate: 1.0,
+            offset: 0.0,
+            judgement: JudgementConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayEvent {
+    /// 歌曲时间（秒）
+    pub song_time: f64,
+    pub kind: InputEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub chart_hash: u64,
+    pub settings: ReplaySettings,
+    /// 按时间排序
+    pub events: Vec<ReplayEvent>,
+}
+
+struct ReplayWriter {
+    bytes: Vec<u8>,
+}
+
+impl ReplayWriter {
+    fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.bytes.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.bytes.push(v as u8);
+    }
+
+    fn signed(&mut self, v: i64) {
+        self.varint(((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    fn str(&mut self, v: &str) {
+        self.varint(v.len() as u64);
+        self.bytes.extend_from_slice(v.as_bytes());
+    }
+
+    fn vec2(&mut self, v: Vec2) {
+        self.f32(v.x);
+        self.f32(v.y);
+    }
+}
+
+struct ReplayReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ReplayReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .ok_or_else(|| anyhow!("invalid length {} at byte {}", len, self.position))?;
+        let slice = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| anyhow!("unexpected end of replay at byte {}", self.position))?;
+
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        bail!("varint too long at byte {}", self.position)
+    }
+
+    fn signed(&mut self) -> Result<i64> {
+        let v = self.varint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    fn str(&mut self) -> Result<String> {
+        let len = self.varint()? as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn vec2(&mut self) -> Result<Vec2> {
+        Ok(vec2(self.f32()?, self.f32()?))
+    }
+}
+
+fn write_mouse_button(w: &mut ReplayWriter, button: MouseButton) {
+    match button {
+        MouseButton::Left => w.varint(0),
+        MouseButton::Right => w.varint(1),
+        MouseButton::Middle => w.varint(2),
+        MouseButton::Back => w.varint(3),
+        MouseButton::Forward => w.varint(4),
+        MouseButton::Other(other) => w.varint(5 + other as u64),
+    }
+}
+
+fn read_mouse_button(r: &mut ReplayReader) -> Result<MouseButton> {
+    Ok(match r.varint()? {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        3 => MouseButton::Back,
+        4 => MouseButton::Forward,
+        other => MouseButton::Other(u16::try_from(other - 5)?),
+    })
+}
+
+impl Replay {
+    pub fn new(chart_hash: u64, settings: ReplaySettings) -> Self {
+        Self {
+            chart_hash,
+            settings,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut w = ReplayWriter { bytes: Vec::new() };
+
+        w.bytes.extend_from_slice(REPLAY_MAGIC);
+        w.u16(REPLAY_VERSION);
+        w.u64(self.chart_hash);
+
+        let settings = &self.settings;
+        let judgement = &settings.judgement;
+        w.f64(settings.song_rate);
+        w.f64(settings.offset);
+        w.f64(judgement.windows.perfect);
+        w.f64(judgement.windows.great);
+        w.f64(judgement.windows.good);
+        w.f64(judgement.windows.bad);
+        w.f64(judgement.hold_release_tolerance);
+        match judgement.x_tolerance {
+            Some(tolerance) => {
+                w.u8(1);
+                w.f32(tolerance);
+            }
+            None => w.u8(0),
+        }
+        w.f32(judgement.flick_distance);
+
+        // KeyCode 没有稳定的数值，按名字存一份表，事件里只存下标
+        let mut keys: Vec<KeyCode> = Vec::new();
+        for event in &self.events {
+            if let InputEventKind::KeyDown(key) | InputEventKind::KeyUp(key) = event.kind
+                && !keys.contains(&key)
+            {
+                keys.push(key);
+            }
+        }
+
+        w.varint(keys.len() as u64);
+        for key in &keys {
+            let name = serde_json::to_string(key)
+                .with_context(|| format!("Failed to encode key {:?}", key))?;
+            w.str(&name);
+        }
+
+        w.varint(self.events.len() as u64);
+
+        let mut last_micros = 0i64;
+        for event in &self.events {
+            let micros = (event.song_time * 1_000_000.0).round() as i64;
+            w.signed(micros - last_micros);
+            last_micros = micros;
+
+            let key_index = |key| keys.iter().position(|k| *k == key).unwrap_or(0) as u64;
+
+            match event.kind {
+                InputEventKind::KeyDown(key) => {
+                    w.u8(0);
+                    w.varint(key_index(key));
+                }
+                InputEventKind::KeyUp(key) => {
+                    w.u8(1);
+                    w.varint(key_index(key));
+                }
+                InputEventKind::MouseDown(button, position) => {
+                    w.u8(2);
+                    write_mouse_button(&mut w, button);
+                    w.vec2(position);
+                }
+                InputEventKind::MouseUp(button, position) => {
+                    w.u8(3);
+                    write_mouse_button(&mut w, button);
+                    w.vec2(position);
+                }
+                InputEventKind::MouseMove(position) => {
+                    w.u8(4);
+                    w.vec2(position);
+                }
+                InputEventKind::TouchBegan { id, position } => {
+                    w.u8(5);
+                    w.varint(id);
+                    w.vec2(position);
+                }
+                InputEventKind::TouchMoved { id, position } => {
+                    w.u8(6);
+                    w.varint(id);
+                    w.vec2(position);
+                }
+                InputEventKind::TouchEnded { id, position } => {
+                    w.u8(7);
+                    w.varint(id);
+                    w.vec2(position);
+                }
+                InputEventKind::TouchCancelled { id, position } => {
+                    w.u8(8);
+                    w.varint(id);
+                    w.vec2(position);
+                }
+            }
+        }
+
+        Ok(w.bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = ReplayReader { bytes, position: 0 };
+
+        if r.take(4)? != REPLAY_MAGIC {
+            bail!("not a replay file");
+        }
+
+        let version = r.u16()?;
+        if version != REPLAY_VERSION {
+            bail!("unsupported replay version {} (expected {})", version, REPLAY_VERSION);
+        }
+
+        let chart_hash = r.u64()?;
+
+        let song_rate = r.f64()?;
+        let offset = r.f64()?;
+        let windows = JudgementWindows {
+            perfect: r.f64()?,
+            great: r.f64()?,
+            good: r.f64()?,
+            bad: r.f64()?,
+        };
+        let hold_release_tolerance = r.f64()?;
+        let x_tolerance = match r.u8()? {
+            0 => None,
+            _ => Some(r.f32()?),
+        };
+        let flick_distance = r.f32()?;
+
+        let settings = ReplaySettings {
+            song_rate,
+            offset,
+            judgement: JudgementConfig {
+                windows,
+                hold_release_tolerance,
+                x_tolerance,
+                flick_distance,
+            },
+        };
+
+        let key_count = r.varint()? as usize;
+        let keys = (0..key_count)
+            .map(|_| {
+                let name = r.str()?;
+                serde_json::from_str::<KeyCode>(&name)
+                    .with_context(|| format!("unknown key {}", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let key = |r: &mut ReplayReader| -> Result<KeyCode> {
+            let index = r.varint()? as usize;
+            keys.get(index)
+                .copied()
+                .ok_or_else(|| anyhow!("key index {} out of range", index))
+        };
+
+        let event_count = r.varint()? as usize;
+        // 每个事件至少 2 字节，防止损坏的文件申请过大的内存
+        let mut events = Vec::with_capacity(event_count.min(bytes.len() / 2));
+
+        let mut micros = 0i64;
+        for i in 0..event_count {
+            micros = micros
+                .checked_add(r.signed()?)
+                .ok_or_else(|| anyhow!("time of event {} is out of range", i))?;
+
+            let kind = match r.u8()? {
+                0 => InputEventKind::KeyDown(key(&mut r)?),
+                1 => InputEventKind::KeyUp(key(&mut r)?),
+                2 => InputEventKind::MouseDown(read_mouse_button(&mut r)?, r.vec2()?),
+                3 => InputEventKind::MouseUp(read_mouse_button(&mut r)?, r.vec2()?),
+                4 => InputEventKind::MouseMove(r.vec2()?),
+                5 => InputEventKind::TouchBegan { id: r.varint()?, position: r.vec2()? },
+                6 => InputEventKind::TouchMoved { id: r.varint()?, position: r.vec2()? },
+                7 => InputEventKind::TouchEnded { id: r.varint()?, position: r.vec2()? },
+                8 => InputEventKind::TouchCancelled { id: r.varint()?, position: r.vec2()? },
+                tag => bail!("unknown event tag {} in event {}", tag, i),
+            };
+
+            events.push(ReplayEvent {
+                song_time: micros as f64 / 1_000_000.0,
+                kind,
+            });
+        }
+
+        Ok(Self {
+            chart_hash,
+            settings,
+            events,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+        Self::from_bytes(&bytes).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// 事件会被保存为微秒精度，这里同样取整，保证录制和回放看到的时间一致
+    pub fn record(&mut self, song_time: f64, kind: InputEventKind) {
+        let song_time = (song_time * 1_000_000.0).round() / 1_000_000.0;
+        let index = self.events.partition_point(|e| e.song_time <= song_time);
+        self.events.insert(index, ReplayEvent { song_time, kind });
+    }
+}
+
+/// 按歌曲时间依次取出回放事件
+#[derive(Clone, Debug)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    cursor: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    /// 取出歌曲时间不晚于 `song_time` 的事件
+    pub fn events_until(&mut self, song_time: f64) -> &[ReplayEvent] {
+        let start = self.cursor;
+        let events = &self.replay.events;
+
+        while self.cursor < events.len() && events[self.cursor].song_time <= song_time {
+            self.cursor += 1;
+        }
+
+        &events[start..self.cursor]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.replay.events.len()
+    }
+
+    pub fn seek(&mut self, song_time: f64) {
+        self.cursor = self
+            .replay
+            .events
+            .partition_point(|e| e.song_time < song_time);
+    }
+}
+
+static REPLAY_RECORDER: Lazy<Mutex<Option<Replay>>> = Lazy::new(|| Mutex::new(None));
+static REPLAY_PLAYER: Lazy<Mutex<Option<ReplayPlayer>>> = Lazy::new(|| Mutex::new(None));
+
+/// 开始录制，之后每帧的输入事件都会被记录
+pub fn start_replay_recording(chart_hash: u64, settings: ReplaySettings) {
+    *REPLAY_RECORDER.lock() = Some(Replay::new(chart_hash, settings));
+}
+
+pub fn stop_replay_recording() -> Option<Replay> {
+    REPLAY_RECORDER.lock().take()
+}
+
+pub fn is_replay_recording() -> bool {
+    REPLAY_RECORDER.lock().is_some()
+}
+
+/// 由游戏循环在 [`begin_input_frame`] 之后调用，记录本帧的输入事件
+pub fn record_replay_frame() {
+    let mut recorder = REPLAY_RECORDER.lock();
+
+    let Some(replay) = recorder.as_mut() else {
+        return;
+    };
+
+    for event in get_input().read().frame().events.iter() {
+        replay.record(game_time_to_song_time(event.time), event.kind);
+    }
+}
+
+/// 开始播放回放，播放期间真实的输入事件会被忽略。
+///
+/// 回放录制时的歌曲速率和偏移会被还原到歌曲时钟上；判定设置需要游戏在创建
+/// `JudgementEngine` 时从 [`replay_judgement_config`] 取得。
+pub fn start_replay_playback(replay: Replay) {
+    set_song_rate(replay.settings.song_rate);
+    set_song_offset(replay.settings.offset);
+
+    let mut player = ReplayPlayer::new(replay);
+    player.seek(get_song_time());
+
+    *REPLAY_PLAYER.lock() = Some(player);
+}
+
+pub fn stop_replay_playback() -> Option<Replay> {
+    REPLAY_PLAYER.lock().take().map(|player| player.replay)
+}
+
+pub fn is_replay_playing() -> bool {
+    REPLAY_PLAYER.lock().is_some()
+}
+
+/// 正在播放的回放录制时使用的判定设置
+pub fn replay_judgement_config() -> Option<JudgementConfig> {
+    REPLAY_PLAYER
+        .lock()
+        .as_ref()
+        .map(|player| player.replay.settings.judgement)
+}
+
+/// 由 winit 线程每次循环调用，把到时的回放事件注入输入模块
+pub fn pump_replay() {
+    let mut player = REPLAY_PLAYER.lock();
+
+    let Some(player) = player.as_mut() else {
+        return;
+    };
+
+    let song_time = game_time_to_song_time(get_precise_time());
+    let mut input = get_input().write();
+
+    for event in player.events_until(song_time) {
+        input.inject_event(InputEvent {
+            time: song_time_to_game_time(event.song_time),
+            kind: event.kind,
+        });
+    }
+}
+
+#[test]
+fn replay_binary_round_trip() {
+    let mut replay = Replay::new(
+        0x1234_5678_9abc_def0,
+        ReplaySettings {
+            song_rate: 1.25,
+            offset: -0.012,
+            judgement: JudgementConfig {
+                x_tolerance: Some(1.5),
+                ..Default::default()
+            },
+        },
+    );
+
+    replay.record(-0.5, InputEventKind::KeyDown(KeyCode::KeyD));
+    replay.record(0.25, InputEventKind::KeyUp(KeyCode::KeyD));
+    replay.record(0.1, InputEventKind::MouseDown(MouseButton::Other(9), vec2(1.0, 2.0)));
+    replay.record(1.0, InputEventKind::TouchBegan { id: 300, position: vec2(-3.5, 8.0) });
+    replay.record(1.000_001, InputEventKind::TouchCancelled { id: 300, position: vec2(0.0, 0.0) });
+
+    let bytes = replay.to_bytes().unwrap();
+    assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    assert_eq!(replay.events[1].song_time, 0.1);
+
+    let mut bad_version = bytes.clone();
+    bad_version[4] = 99;
+    let error = Replay::from_bytes(&bad_version).unwrap_err().to_string();
+    assert!(error.contains("version 99"), "{error}");
+
+    assert!(Replay::from_bytes(&bytes[..bytes.len() - 3]).is_err());
+}
+
+#[test]
+fn corrupt_replay_lengths_and_times_are_errors() {
+    let mut reader = ReplayReader {
+        bytes: &[1, 2, 3],
+        position: 2,
+    };
+    assert!(reader.take(usize::MAX).is_err());
+
+    // 两个事件的时间增量都是 i64::MAX，累加会溢出
+    let mut replay = Replay::new(0, ReplaySettings::default());
+    replay.record(0.0, InputEventKind::KeyDown(KeyCode::KeyD));
+    replay.record(0.0, InputEventKind::KeyDown(KeyCode::KeyD));
+
+    let bytes = replay.to_bytes().unwrap();
+    // 每个事件是 增量、标签、按键下标 三个单字节
+    let mut w = ReplayWriter {
+        bytes: bytes[..bytes.len() - 6].to_vec(),
+    };
+    for _ in 0..2 {
+        w.signed(i64::MAX);
+        w.u8(0);
+        w.varint(0);
+    }
+
+    let error = Replay::from_bytes(&w.bytes).unwrap_err().to_string();
+    assert!(error.contains("time of event 1"), "{error}");
+}
+
+#[test]
+fn replay_reproduces_judgement() {
+    let chart = Chart::new(
+        ChartMetadata::default(),
+        TimingMap::constant(0.0, 120.0).unwrap(),
+        4,
+        vec![
+            Note::new(1.0, NoteKind::Tap, 0),
+            Note::new(1.5, NoteKind::Hold { end_time: 2.5 }, 1),
+            Note::new(3.0, NoteKind::Tap, 3),
+        ],
+    );
+
+    let keys = [KeyCode::KeyD, KeyCode::KeyF, KeyCode::KeyJ, KeyCode::KeyK];
+
+    let judge = |events: &[InputEvent]| {
+        let mut engine = JudgementEngine::new(&chart, JudgementConfig::default());
+        for event in events {
+            if let Some(input) = JudgeInput::from_event(event, event.time, &keys, |_| None) {
+                engine.handle_input(&input);
+            }
+        }
+        engine.update(10.0);
+        (engine.drain_results(), engine.score())
+    };
+
+    // 原始的一局：歌曲时间与帧计时器对齐
+    let mut live = InputState::default();
+    for (time, kind) in [
+        (1.0123, InputEventKind::KeyDown(KeyCode::KeyD)),
+        (1.05, InputEventKind::KeyUp(KeyCode::KeyD)),
+        (1.4777, InputEventKind::KeyDown(KeyCode::KeyF)),
+        (2.2, InputEventKind::KeyUp(KeyCode::KeyF)),
+        (3.1501, InputEventKind::KeyDown(KeyCode::KeyK)),
+    ] {
+        live.inject_event(InputEvent { time, kind });
+    }
+    live.begin_frame();
+
+    let mut replay = Replay::new(chart_hash(&chart), ReplaySettings::default());
+    for event in &live.frame().events {
+        replay.record(event.time, event.kind);
+    }
+
+    let original = judge(&live.frame().events);
+
+    // 保存、读取后按帧注入
+    let replay = Replay::from_bytes(&replay.to_bytes().unwrap()).unwrap();
+    assert_eq!(replay.chart_hash, chart_hash(&chart));
+
+    let mut player = ReplayPlayer::new(replay);
+    let mut replayed = InputState::default();
+    let mut events = Vec::new();
+
+    for frame in 0..300 {
+        for event in player.events_until(frame as f64 / 60.0) {
+            replayed.inject_event(InputEvent {
+                time: event.song_time,
+                kind: event.kind,
+            });
+        }
+        replayed.begin_frame();
+        events.extend_from_slice(&replayed.frame().events);
+    }
+
+    assert!(player.is_finished());
+    assert_eq!(judge(&events), original);
+}
+
+#[test]
+fn replay_playback_restores_settings() {
+    let settings = ReplaySettings {
+        song_rate: 1.5,
+        offset: 0.02,
+        judgement: JudgementConfig {
+            flick_distance: 42.0,
+            ..Default::default()
+        },
+    };
+
+    start_replay_playback(Replay::new(0, settings));
+
+    assert_eq!(get_song_clock().read().rate(), 1.5);
+    assert_eq!(get_song_clock().read().offset, 0.02);
+    assert_eq!(replay_judgement_config(), Some(settings.judgement));
+
+    stop_replay_playback();
+    assert_eq!(replay_judgement_config(), None);
+
+    set_song_rate(1.0);
+    set_song_offset(0.0);
+}
@@ -0,0 +1,327 @@
+use crate::*;
+
+/// 歌曲时钟的位置来源，例如音频后端汇报的播放位置。
+///
+/// 位置通常是粗粒度的（按混音块更新），由 [`SongClock`] 用帧计时器插值。
+pub trait PositionSource: Send + Sync {
+    /// 当前播放位置（秒），没有在播放时返回 `None`，此时时钟按帧计时器自由运行
+    fn position(&self) -> Option<f64>;
+
+    fn set_paused(&self, _paused: bool) {}
+    fn seek(&self, _seconds: f64) {}
+    fn set_rate(&self, _rate: f64) {}
+}
+
+/// 不依赖任何外部来源，完全由帧计时器驱动
+pub struct FreeRunningSource;
+
+impl PositionSource for FreeRunningSource {
+    fn position(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// 使用音频混音器的音乐播放位置
+pub struct MusicPositionSource;
+
+impl PositionSource for MusicPositionSource {
+    fn position(&self) -> Option<f64> {
+        if check_audio_init() && is_music_playing() {
+            get_music_position()
+        } else {
+            None
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        if check_audio_init() {
+            if paused { pause_music() } else { resume_music() }
+        }
+    }
+
+    fn seek(&self, seconds: f64) {
+        if check_audio_init() {
+            seek_music(seconds);
+        }
+    }
+
+    fn set_rate(&self, rate: f64) {
+        if check_audio_init() {
+            set_music_rate(rate);
+        }
+    }
+}
+
+/// 手动设置位置的来源，主要用于测试
+#[derive(Clone, Default)]
+pub struct ManualPositionSource {
+    pub position: Arc<Mutex<Option<f64>>>,
+}
+
+impl ManualPositionSource {
+    pub fn set(&self, position: Option<f64>) {
+        *self.position.lock() = position;
+    }
+}
+
+impl PositionSource for ManualPositionSource {
+    fn position(&self) -> Option<f64> {
+        *self.position.lock()
+    }
+}
+
+/// 把外部播放位置和帧计时器融合成平滑、单调的歌曲时间。
+///
+/// 所有音符位置和判定都应该读取歌曲时间，而不是 `get_time()`。
+pub struct SongClock {
+    source: Box<dyn PositionSource>,
+
+    /// 用户偏移（秒），正值表示音频有延迟，歌曲时间会相应推迟
+    pub offset: f64,
+    /// 每次收到新位置时向其靠拢的比例，越小越平滑
+    pub smoothing: f64,
+    /// 误差超过该值（秒）时直接对齐到来源位置
+    pub snap_threshold: f64,
+
+    rate: f64,
+    paused: bool,
+
+    // 不含偏移的原始位置
+    position: f64,
+    last_update: Option<f64>,
+    last_source_position: Option<f64>,
+}
+
+impl SongClock {
+    pub fn new(source: impl PositionSource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+            offset: 0.0,
+            smoothing: 0.1,
+            snap_threshold: 0.05,
+            rate: 1.0,
+            paused: false,
+            position: 0.0,
+            last_update: None,
+            last_source_position: None,
+        }
+    }
+
+    pub fn set_source(&mut self, source: impl PositionSource + 'static) {
+        self.source = Box::new(source);
+        self.last_source_position = None;
+    }
+
+    /// 按帧计时器更新，每帧调用一次
+    pub fn update(&mut self) {
+        self.update_at(get_time_f64());
+    }
+
+    /// 以 `now`（秒，与帧计时器同一时间轴）推进时钟
+    pub fn update_at(&mut self, now: f64) {
+        let dt = self.last_update.map_or(0.0, |last| (now - last).max(0.0));
+        self.last_update = Some(now);
+
+        if self.paused {
+            return;
+        }
+
+        let predicted = self.position + dt * self.rate;
+
+        let Some(reported) = self.source.position() else {
+            self.position = predicted;
+            return;
+        };
+
+        // 来源还没有汇报新位置，继续用帧计时器插值
+        if self.last_source_position == Some(reported) {
+            self.position = predicted;
+            return;
+        }
+
+        self.last_source_position = Some(reported);
+
+        let error = reported - predicted;
+
+        self.position = if error.abs() > self.snap_threshold {
+            reported
+        } else {
+            // 小误差只做平滑修正，并保证时间不倒退
+            (predicted + error * self.smoothing).max(self.position)
+        };
+    }
+
+    /// 当前歌曲时间（秒），已应用偏移和全局校准偏移
+    pub fn position(&self) -> f64 {
+        self.position - self.offset - get_global_offset()
+    }
+
+    /// 把帧计时器时间轴上的某个时刻（例如输入事件的时间戳）换算成歌曲时间
+    pub fn song_time_at(&self, game_time: f64) -> f64 {
+        let elapsed = match (self.paused, self.last_update) {
+            (false, Some(last)) => (game_time - last) * self.rate,
+            _ => 0.0,
+        };
+
+        self.position() + elapsed
+    }
+
+    /// [`Self::song_time_at`] 的逆运算，用于把歌曲时间上的事件放回帧计时器时间轴
+    pub fn game_time_at(&self, song_time: f64) -> f64 {
+        let last = self.last_update.unwrap_or(0.0);
+
+        if self.paused || self.rate <= 0.0 {
+            last
+        } else {
+            last + (song_time - self.position()) / self.rate
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.source.set_paused(true);
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.source.set_paused(false);
+        self.last_source_position = self.source.position();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// 跳转到指定歌曲时间（秒）
+    pub fn seek(&mut self, song_time: f64) {
+        self.position = song_time + self.offset + get_global_offset();
+        self.source.seek(self.position.max(0.0));
+
+        // 跳转前的旧位置不再参与修正
+        self.last_source_position = self.source.position();
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate.max(0.0);
+        self.source.set_rate(self.rate);
+    }
+}
+
+impl Default for SongClock {
+    fn default() -> Self {
+        Self::new(FreeRunningSource)
+    }
+}
+
+static SONG_CLOCK: Lazy<RwLock<SongClock>> = Lazy::new(|| RwLock::new(SongClock::default()));
+
+pub fn get_song_clock() -> &'static RwLock<SongClock> {
+    &SONG_CLOCK
+}
+
+pub fn set_song_clock_source(source: impl PositionSource + 'static) {
+    SONG_CLOCK.write().set_source(source);
+}
+
+/// 由游戏循环在 `time::update()` 之后调用
+pub fn update_song_clock() {
+    SONG_CLOCK.write().update();
+}
+
+/// 当前歌曲时间（秒）
+pub fn get_song_time() -> f64 {
+    SONG_CLOCK.read().position()
+}
+
+/// 输入事件时间戳对应的歌曲时间
+pub fn game_time_to_song_time(game_time: f64) -> f64 {
+    SONG_CLOCK.read().song_time_at(game_time)
+}
+
+/// 歌曲时间对应的帧计时器时间
+pub fn song_time_to_game_time(song_time: f64) -> f64 {
+    SONG_CLOCK.read().game_time_at(song_time)
+}
+
+pub fn pause_song() {
+    SONG_CLOCK.write().pause();
+}
+
+pub fn resume_song() {
+    SONG_CLOCK.write().resume();
+}
+
+pub fn seek_song(song_time: f64) {
+    SONG_CLOCK.write().seek(song_time);
+}
+
+pub fn set_song_rate(rate: f64) {
+    SONG_CLOCK.write().set_rate(rate);
+}
+
+pub fn set_song_offset(offset: f64) {
+    SONG_CLOCK.write().offset = offset;
+}
+
+#[test]
+fn song_clock_interpolates_and_smooths() {
+    let source = ManualPositionSource::default();
+    let mut clock = SongClock::new(source.clone());
+
+    source.set(Some(0.0));
+    clock.update_at(10.0);
+    assert_eq!(clock.position(), 0.0);
+
+    // 来源没有更新时按帧计时器插值
+    clock.update_at(10.004);
+    assert!((clock.position() - 0.004).abs() < 1e-9);
+
+    // 来源汇报的位置带有 2ms 抖动，只修正一部分
+    source.set(Some(0.010));
+    clock.update_at(10.008);
+    let expected = 0.008 + 0.002 * clock.smoothing;
+    assert!((clock.position() - expected).abs() < 1e-9);
+
+    // 误差超过阈值直接对齐
+    source.set(Some(1.0));
+    clock.update_at(10.012);
+    assert_eq!(clock.position(), 1.0);
+
+    // 小的负误差不会让时间倒退
+    source.set(Some(0.999));
+    clock.update_at(10.012);
+    assert_eq!(clock.position(), 1.0);
+}
+
+#[test]
+fn song_clock_pause_seek_rate_offset() {
+    let mut clock = SongClock {
+        offset: 0.1,
+        ..Default::default()
+    };
+
+    clock.update_at(0.0);
+    clock.update_at(1.0);
+    assert!((clock.position() - 0.9).abs() < 1e-9);
+
+    clock.pause();
+    clock.update_at(2.0);
+    assert!((clock.position() - 0.9).abs() < 1e-9);
+
+    clock.resume();
+    clock.seek(5.0);
+    assert!((clock.position() - 5.0).abs() < 1e-9);
+
+    clock.set_rate(1.5);
+    clock.update_at(3.0);
+    assert!((clock.position() - 6.5).abs() < 1e-9);
+
+    // 帧之间的事件按速率外推
+    assert!((clock.song_time_at(3.010) - 6.515).abs() < 1e-9);
+    assert!((clock.song_time_at(2.990) - 6.485).abs() < 1e-9);
+    assert!((clock.game_time_at(6.515) - 3.010).abs() < 1e-9);
+}
@@ -1,6 +1,9 @@
 use image::DynamicImage;
 use image::GenericImageView;
 use image::ImageResult;
+use image::ImageError;
+use image::error::LimitError;
+use image::error::LimitErrorKind;
 use wgpu::AddressMode;
 use wgpu::BindGroup;
 use wgpu::BindGroupLayout;
@@ -88,6 +91,8 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+    /// 从图片创建的纹理的采样方式，图集页也用它，放进图集前后绘制结果一致
+    pub const IMAGE_FILTER_MODE: FilterMode = FilterMode::Nearest;
 
     pub fn create_depth_texture(
         device: &Device,
@@ -294,6 +299,14 @@ impl Texture {
         dimensions: (u32, u32),
         bytes_per_pixel: u32,
     ) -> ImageResult<Self> {
+        // 超出设备限制时 create_texture 会直接 panic，提前返回错误
+        let max_size = device.limits().max_texture_dimension_2d;
+        if dimensions.0 > max_size || dimensions.1 > max_size {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        }
+
         let size = Extent3d {
             width: dimensions.0,
             height: dimensions.1,
@@ -334,8 +347,8 @@ impl Texture {
             address_mode_v: address_mode,
             address_mode_w: address_mode,
             // TODO: configure this
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Nearest,
+            mag_filter: Self::IMAGE_FILTER_MODE,
+            min_filter: Self::IMAGE_FILTER_MODE,
             mipmap_filter: FilterMode::Nearest,
             ..Default::default()
         });
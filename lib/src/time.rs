@@ -23,6 +23,9 @@ pub struct SleepTimer {
 
 static TIME: Lazy<Arc<RwLock<Time>>> = Lazy::new(|| Arc::new(RwLock::new(Time::new())));
 
+// 校准得到的全局偏移 (秒)，以 f64 的位存储
+static GLOBAL_OFFSET: AtomicU64 = AtomicU64::new(0);
+
 pub(crate) fn get_timer() -> Arc<RwLock<Time>> {
     Arc::clone(&TIME)
 }
@@ -98,6 +101,37 @@ pub fn get_time() -> f32 {
     TIME.read().current_time.as_secs_f32()
 }
 
+// 获取当前时间 (秒)，双精度，长时间运行也不会丢失毫秒以下的精度
+pub fn get_time_f64() -> f64 {
+    TIME.read().current_time.as_secs_f64()
+}
+
+// 某个时刻在 get_time() 时间轴上的位置 (秒)，用于给输入等事件打时间戳
+pub fn instant_to_time(instant: Instant) -> f64 {
+    let start_time = TIME.read().start_time;
+    instant.saturating_duration_since(start_time).as_secs_f64()
+}
+
+// 获取此刻的精确时间 (秒)，不按帧取整
+pub fn get_precise_time() -> f64 {
+    instant_to_time(Instant::now())
+}
+
+// 设置全局偏移 (秒)，正值表示音频和输入比画面晚，见 calibration.rs
+pub fn set_global_offset(offset: f64) {
+    GLOBAL_OFFSET.store(offset.to_bits(), Ordering::Relaxed);
+}
+
+// 获取全局偏移 (秒)
+pub fn get_global_offset() -> f64 {
+    f64::from_bits(GLOBAL_OFFSET.load(Ordering::Relaxed))
+}
+
+// 获取应用了全局偏移的当前时间 (秒)
+pub fn get_calibrated_time() -> f64 {
+    get_time_f64() - get_global_offset()
+}
+
 // 获取增量时间 (秒)
 pub fn get_delta_time() -> f32 {
     TIME.read().delta_time.as_secs_f32()
@@ -10,3 +10,133 @@ pub fn set_y_sort(z_index: i32, value: bool) {
 pub fn get_y_sort(z_index: i32) -> bool {
     *Y_SORT_FLAGS.read().get(&z_index).unwrap_or(&false)
 }
+
+/// 按 `MeshGroupKey` 的顺序展开绘制队列，开启了 y-sort 的层会跨分组重新排序。
+///
+/// 同一层里的网格按 `origin.y + y_sort_offset` 从大到小绘制（越靠下越靠前），
+/// 纹理、着色器不同也不影响。排序是稳定的：y 相同时保持原来的顺序，
+/// 即先按分组顺序、组内按提交顺序。排序后相邻且分组相同的网格仍然合成一批。
+pub fn y_sort_groups(
+    queues: BTreeMap<MeshGroupKey, RenderQueue>,
+    is_y_sorted: impl Fn(i32) -> bool,
+) -> Vec<(MeshGroupKey, RenderQueue)> {
+    let mut groups = Vec::with_capacity(queues.len());
+
+    for (z_index, layer) in &queues.into_iter().chunk_by(|(key, _)| key.z_index) {
+        if !is_y_sorted(z_index) {
+            groups.extend(layer);
+            continue;
+        }
+
+        let mut meshes: Vec<(MeshGroupKey, Mesh)> = layer
+            .flat_map(|(key, meshes)| meshes.into_iter().map(move |mesh| (key, mesh)))
+            .collect();
+
+        meshes.sort_by(|(_, a), (_, b)| {
+            (b.origin.y + b.y_sort_offset).total_cmp(&(a.origin.y + a.y_sort_offset))
+        });
+
+        for (key, run) in &meshes.into_iter().chunk_by(|(key, _)| *key) {
+            groups.push((key, run.map(|(_, mesh)| mesh).collect()));
+        }
+    }
+
+    groups
+}
+
+#[test]
+fn y_sort_interleaves_groups_within_a_layer() {
+    let key = |z_index: i32, texture: u64| MeshGroupKey {
+        z_index,
+        blend_mode: BlendMode::Alpha,
+        texture_id: TextureHandle::Raw(texture),
+        shader: ShaderInstanceId::default(),
+        render_target: RenderTargetId::default(),
+    };
+    let mesh = |y: f32, offset: f32, z_index: i32| Mesh {
+        origin: vec3(0.0, y, 0.0),
+        y_sort_offset: offset,
+        z_index,
+        ..Default::default()
+    };
+
+    let mut queues = BTreeMap::new();
+    queues.insert(key(0, 1), vec![mesh(10.0, 0.0, 0), mesh(-10.0, 0.0, 0)]);
+    queues.insert(key(0, 2), vec![mesh(0.0, 0.0, 0)]);
+    queues.insert(key(1, 1), vec![mesh(5.0, 0.0, 1), mesh(0.0, 0.0, 1), mesh(5.0, -5.0, 1)]);
+    queues.insert(key(1, 2), vec![mesh(0.0, 0.0, 1), mesh(20.0, 0.0, 1)]);
+
+    let summary = |groups: Vec<(MeshGroupKey, RenderQueue)>| {
+        groups
+            .into_iter()
+            .map(|(key, meshes)| {
+                let ys = meshes.iter().map(|m| m.origin.y + m.y_sort_offset).collect_vec();
+                (key.z_index, key.texture_id, ys)
+            })
+            .collect_vec()
+    };
+
+    let tex = TextureHandle::Raw;
+
+    // 没有开启 y-sort 时保持分组顺序
+    assert_eq!(
+        summary(y_sort_groups(queues.clone(), |_| false)),
+        vec![
+            (0, tex(1), vec![10.0, -10.0]),
+            (0, tex(2), vec![0.0]),
+            (1, tex(1), vec![5.0, 0.0, 0.0]),
+            (1, tex(2), vec![0.0, 20.0]),
+        ]
+    );
+
+    // 只有第 1 层排序，y 相同的网格按分组、再按提交顺序排列
+    assert_eq!(
+        summary(y_sort_groups(queues, |z| z == 1)),
+        vec![
+            (0, tex(1), vec![10.0, -10.0]),
+            (0, tex(2), vec![0.0]),
+            (1, tex(2), vec![20.0]),
+            (1, tex(1), vec![5.0, 0.0, 0.0]),
+            (1, tex(2), vec![0.0]),
+        ]
+    );
+}
+
+#[test]
+fn y_sort_orders_across_blend_modes() {
+    let _guard = GOLDEN_LOCK.lock();
+
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let blue = Color::new(0.0, 0.0, 1.0, 1.0);
+
+    let quad = |y: f32, color: Color, blend_mode: BlendMode| {
+        draw_quad(RawDrawParams {
+            position: vec3(0.0, y, 0.0),
+            dest_size: Some(uvec2(20, 20)),
+            color,
+            z_index: 7,
+            blend_mode,
+            ..Default::default()
+        });
+    };
+
+    set_y_sort(7, true);
+
+    // 无论分组顺序如何，靠下（y 更小）的都在上面
+    for (lower, upper) in [
+        (BlendMode::None, BlendMode::Alpha),
+        (BlendMode::Alpha, BlendMode::None),
+    ] {
+        let Some(image) = render_scene(Msaa::Off, || {
+            quad(-5.0, red, lower);
+            quad(5.0, blue, upper);
+        }) else {
+            break;
+        };
+
+        assert_eq!(image.get_pixel(64, 64), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(64, 64 - 12), &image::Rgba([0, 0, 255, 255]));
+    }
+
+    set_y_sort(7, false);
+}